@@ -0,0 +1,220 @@
+use std::time::{Duration, Instant};
+use crate::png_reader::{FrameControl, FrameRegion, Pixel, PngReader};
+use crate::png_writer::PngWriter;
+
+const DISPOSE_OP_BACKGROUND: u8 = 1;
+const DISPOSE_OP_PREVIOUS: u8 = 2;
+const BLEND_OP_OVER: u8 = 1;
+
+/// One animation frame as it's shown: the whole canvas after compositing.
+pub struct Frame {
+    pub pixel_data: Vec<Vec<Pixel>>,
+    pub delay: Duration,
+}
+
+/// Decodes every frame of an APNG and composites it onto the canvas the way browsers do. Empty
+/// if the file isn't animated.
+pub fn composite(reader: &PngReader) -> Result<Vec<Frame>, String> {
+    Ok(composite_regions(reader.info().width, reader.info().height, reader.decode_frames()?))
+}
+
+/// Composites frame regions onto a transparent canvas of the given size, applying each frame's
+/// blend op and then the previous frame's dispose op.
+pub fn composite_regions(width: u32, height: u32, regions: Vec<FrameRegion>) -> Vec<Frame> {
+    let mut canvas = vec![vec![Pixel::default(); width as usize]; height as usize];
+    let mut frames = vec![];
+
+    for (i, (control, region)) in regions.into_iter().enumerate() {
+        // the first frame has nothing before it to go back to, so clears instead
+        let previous = (control.dispose_op == DISPOSE_OP_PREVIOUS && i > 0).then(|| canvas.clone());
+
+        for_each_pixel(&control, &mut canvas, |x, y, pixel| {
+            *pixel = match control.blend_op {
+                BLEND_OP_OVER => over(region[y][x], *pixel),
+                _ => region[y][x],
+            };
+        });
+
+        frames.push(Frame {
+            pixel_data: canvas.clone(),
+            delay: delay(&control),
+        });
+
+        match (control.dispose_op, previous) {
+            (_, Some(previous)) => canvas = previous,
+            (DISPOSE_OP_BACKGROUND | DISPOSE_OP_PREVIOUS, None) => for_each_pixel(&control, &mut canvas, |_, _, pixel| *pixel = Pixel::default()),
+            _ => (),
+        }
+    }
+
+    frames
+}
+
+/// Browsers show frames with delays this short for `DEFAULT_DELAY` instead.
+const SHORTEST_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Plays an animation's frames with browser timing: each frame's exact delay, divided by the
+/// speed, and stopping on the last frame once it has played `num_plays` times.
+pub struct Playback {
+    frames: Vec<Frame>,
+    num_plays: u32,
+    speed: f64,
+    index: usize,
+    plays: u32,
+    /// When to show the next frame, None once finished.
+    deadline: Option<Instant>,
+}
+
+impl Playback {
+    /// Starts on the first frame, now. `num_plays` 0 plays forever.
+    pub fn new(frames: Vec<Frame>, num_plays: u32, speed: f64) -> Self {
+        let mut playback = Self {
+            frames,
+            num_plays,
+            speed,
+            index: 0,
+            plays: 0,
+            deadline: None,
+        };
+
+        playback.deadline = (playback.frames.len() > 1).then(|| Instant::now() + playback.delay());
+
+        playback
+    }
+
+    pub fn frame(&self) -> &[Vec<Pixel>] {
+        &self.frames[self.index].pixel_data
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Moves on to the frame due at `now`, if any, returning whether the frame changed.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let Some(deadline) = self.deadline.filter(|&deadline| now >= deadline) else {
+            return false;
+        };
+
+        self.index += 1;
+
+        if self.index == self.frames.len() {
+            self.plays += 1;
+
+            if self.num_plays != 0 && self.plays >= self.num_plays {
+                self.index -= 1;
+                self.deadline = None;
+                return false;
+            }
+
+            self.index = 0;
+        }
+
+        // time from the deadline rather than now so delays don't drift, unless so far behind
+        // (e.g. while the window was hidden) that catching up would skip frames
+        let next = deadline + self.delay();
+        self.deadline = Some(if next < now { now + self.delay() } else { next });
+
+        true
+    }
+
+    fn delay(&self) -> Duration {
+        shown_delay(self.frames[self.index].delay).div_f64(self.speed)
+    }
+}
+
+/// How long browsers show a frame with the given delay for.
+pub fn shown_delay(delay: Duration) -> Duration {
+    match delay {
+        delay if delay <= SHORTEST_DELAY => DEFAULT_DELAY,
+        delay => delay,
+    }
+}
+
+/// A zero denominator means hundredths of a second.
+fn delay(control: &FrameControl) -> Duration {
+    let den = if control.delay_den == 0 { 100 } else { control.delay_den };
+
+    Duration::from_secs_f64(control.delay_num as f64 / den as f64)
+}
+
+/// Calls `f` with each canvas pixel in the frame's region and its position within the region,
+/// clipped to the canvas.
+fn for_each_pixel(control: &FrameControl, canvas: &mut [Vec<Pixel>], mut f: impl FnMut(usize, usize, &mut Pixel)) {
+    let (x0, y0) = (control.x_offset as usize, control.y_offset as usize);
+
+    for (y, row) in canvas.iter_mut().skip(y0).take(control.height as usize).enumerate() {
+        for (x, pixel) in row.iter_mut().skip(x0).take(control.width as usize).enumerate() {
+            f(x, y, pixel);
+        }
+    }
+}
+
+/// Source-over compositing of non-premultiplied colours.
+fn over(src: Pixel, dst: Pixel) -> Pixel {
+    match src.a {
+        0xFF => return src,
+        0 => return dst,
+        _ => (),
+    }
+
+    let src_a = src.a as f64 / 255.0;
+    let dst_a = dst.a as f64 / 255.0 * (1.0 - src_a);
+    let a = src_a + dst_a;
+    let channel = |s: u8, d: u8| ((s as f64 * src_a + d as f64 * dst_a) / a).round() as u8;
+
+    Pixel {
+        r: channel(src.r, dst.r),
+        g: channel(src.g, dst.g),
+        b: channel(src.b, dst.b),
+        a: (a * 255.0).round() as u8,
+    }
+}
+
+/// Encodes whole-canvas frames as an APNG played `num_plays` times, 0 meaning forever, with
+/// delays rounded to milliseconds. The first frame is also the default image, for viewers
+/// without APNG support.
+pub fn assemble(width: u32, height: u32, frames: Vec<Frame>, num_plays: u32) -> Vec<u8> {
+    let mut bytes = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut sequence_number = 0u32;
+
+    let mut actl = Vec::with_capacity(8);
+    actl.extend_from_slice(&(frames.len() as u32).to_be_bytes());
+    actl.extend_from_slice(&num_plays.to_be_bytes());
+
+    for (i, frame) in frames.into_iter().enumerate() {
+        let delay_ms = frame.delay.as_millis().min(u16::MAX as u128) as u16;
+        let writer = PngWriter::new(width, height, frame.pixel_data);
+
+        if i == 0 {
+            PngWriter::write_chunk(&mut bytes, b"IHDR", &writer.ihdr());
+            PngWriter::write_chunk(&mut bytes, b"acTL", &actl);
+        }
+
+        let mut fctl = Vec::with_capacity(26);
+        fctl.extend_from_slice(&sequence_number.to_be_bytes());
+        fctl.extend_from_slice(&width.to_be_bytes());
+        fctl.extend_from_slice(&height.to_be_bytes());
+        fctl.extend_from_slice(&[0; 8]);
+        fctl.extend_from_slice(&delay_ms.to_be_bytes());
+        fctl.extend_from_slice(&1000u16.to_be_bytes());
+        // no disposal, and every frame replaces the whole canvas
+        fctl.extend_from_slice(&[0, 0]);
+        PngWriter::write_chunk(&mut bytes, b"fcTL", &fctl);
+        sequence_number += 1;
+
+        match i {
+            0 => PngWriter::write_chunk(&mut bytes, b"IDAT", &writer.idat()),
+            _ => {
+                let fdat = [sequence_number.to_be_bytes().to_vec(), writer.idat()].concat();
+                PngWriter::write_chunk(&mut bytes, b"fdAT", &fdat);
+                sequence_number += 1;
+            }
+        }
+    }
+
+    PngWriter::write_chunk(&mut bytes, b"IEND", &[]);
+
+    bytes
+}
@@ -0,0 +1,34 @@
+//! Drawing decoded images, scaled, into RGBA buffers owned by something else: a window's frame,
+//! a texture's staging buffer or another image.
+
+use crate::png_reader::Pixel;
+
+/// Fills the `width` by `height` area at the start of `dst`, an RGBA buffer whose rows are
+/// `dst_stride` bytes apart, with what `shade` returns for each destination pixel. `shade` gets
+/// the pixel's position in the area and the image coordinate it falls on, `origin` being at the
+/// top-left and each pixel `1 / zoom` further on. Whatever doesn't fit in `dst` is clipped.
+pub fn blit_with(dst: &mut [u8], dst_stride: usize, (width, height): (usize, usize), origin: (f64, f64), zoom: f64, mut shade: impl FnMut(usize, usize, f64, f64) -> [u8; 4]) {
+    let width = width.min(dst_stride / 4);
+
+    for (dy, row) in dst.chunks_mut(dst_stride).take(height).enumerate() {
+        let y = origin.1 + dy as f64 / zoom;
+        let len = (width * 4).min(row.len());
+
+        for (dx, out) in row[..len].chunks_exact_mut(4).enumerate() {
+            let x = origin.0 + dx as f64 / zoom;
+            out.copy_from_slice(&shade(dx, dy, x, y));
+        }
+    }
+}
+
+/// Draws `pixel_data` into `dst` as `blit_with` does, sampling the nearest pixel and leaving
+/// transparent black where there's no image.
+pub fn blit(pixel_data: &[Vec<Pixel>], dst: &mut [u8], dst_stride: usize, size: (usize, usize), origin: (f64, f64), zoom: f64) {
+    blit_with(dst, dst_stride, size, origin, zoom, |_, _, x, y| {
+        let pixel = (x >= 0.0 && y >= 0.0)
+            .then(|| pixel_data.get(y as usize)?.get(x as usize))
+            .flatten();
+
+        pixel.map_or([0; 4], |pixel| [pixel.r, pixel.g, pixel.b, pixel.a])
+    });
+}
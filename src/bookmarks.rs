@@ -0,0 +1,80 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use colored::Colorize;
+use crate::config::Config;
+
+fn path() -> Option<PathBuf> {
+    Config::dir().map(|dir| dir.join("bookmarks.txt"))
+}
+
+/// The bookmarked images, in the order they were bookmarked. Unlike the recent files, those that
+/// no longer exist are kept, as they may be on a drive that isn't mounted.
+pub fn load() -> Vec<PathBuf> {
+    let Some(text) = path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return vec![];
+    };
+
+    text.lines()
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Bookmarks `image_path`, or removes its bookmark if it has one. Returns whether it's now
+/// bookmarked, or None if there's no config directory to keep bookmarks in.
+pub fn toggle(image_path: &Path) -> Option<bool> {
+    let path = path()?;
+    let image_path = image_path.canonicalize().unwrap_or_else(|_| image_path.to_path_buf());
+
+    let mut bookmarks = load();
+    let bookmarked = !bookmarks.contains(&image_path);
+
+    match bookmarked {
+        true => bookmarks.push(image_path),
+        false => bookmarks.retain(|p| *p != image_path),
+    }
+
+    let text = bookmarks
+        .iter()
+        .map(|p| format!("{}\n", p.display()))
+        .collect::<String>();
+
+    let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(&path, text));
+
+    if let Err(e) = result {
+        eprintln!("{} {}: {}", "Failed to save bookmarks".red(), path.display(), e);
+    }
+
+    Some(bookmarked)
+}
+
+/// Lists the bookmarks and asks which to open, defaulting to the first. Returns None if there
+/// are none or the answer isn't one of them.
+pub fn pick() -> Option<PathBuf> {
+    let bookmarks = load();
+
+    if bookmarks.is_empty() {
+        eprintln!("{}", "No bookmarks".red());
+        return None;
+    }
+
+    for (i, path) in bookmarks.iter().enumerate() {
+        match path.exists() {
+            true => println!("{:>3}  {}", (i + 1).to_string().green(), path.display()),
+            false => println!("{:>3}  {} {}", (i + 1).to_string().green(), path.display(), "(missing)".red()),
+        }
+    }
+
+    print!("Open [1]: ");
+    io::stdout().flush().ok()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+
+    let choice = match answer.trim() {
+        "" => 1,
+        answer => answer.parse::<usize>().ok()?,
+    };
+
+    bookmarks.get(choice.checked_sub(1)?).cloned()
+}
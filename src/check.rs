@@ -0,0 +1,297 @@
+use png_viewer::crc::Crc;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// The lines of a check's report, and the errors among them with the file offset each was
+/// found at.
+pub struct Report {
+    pub lines: Vec<String>,
+    pub errors: Vec<(usize, String)>,
+}
+
+impl Report {
+    fn line(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn error(&mut self, offset: usize, message: String) {
+        self.lines.push(format!("  {}", message));
+        self.errors.push((offset, message));
+    }
+}
+
+/// Checks the file's structure and prints a report in pngcheck's verbose (`-v`) format: the file,
+/// each chunk with its offset and length, what pngcheck shows of its content, any errors
+/// and the summary line, so scripts that parse pngcheck can use this instead. Unlike
+/// `PngReader` this never panics on broken files. Returns whether no errors were found.
+pub fn check(path: &str, bytes: &[u8]) -> bool {
+    let report = report(path, bytes);
+
+    for line in &report.lines {
+        println!("{}", line);
+    }
+
+    report.errors.is_empty()
+}
+
+/// The report `check` prints.
+pub fn report(path: &str, bytes: &[u8]) -> Report {
+    let mut report = Report { lines: vec![], errors: vec![] };
+    report.line(format!("File: {} ({} bytes)", path, bytes.len()));
+
+    let mut chunks = 0;
+    let mut header: Option<Header> = None;
+    let mut seen_idat = false;
+    let mut seen_iend = false;
+
+    if bytes.len() < SIGNATURE.len() || bytes[..SIGNATURE.len()] != SIGNATURE {
+        report.error(0, "this is neither a PNG or JNG image nor a MNG stream".to_string());
+        return finish(path, bytes, report, chunks, header);
+    }
+
+    let mut idx = SIGNATURE.len();
+
+    while idx < bytes.len() {
+        if seen_iend {
+            report.error(idx, "additional data after IEND chunk".to_string());
+            break;
+        }
+
+        let Some(length) = read_u32(bytes, idx) else {
+            report.error(idx, "EOF while reading chunk length".to_string());
+            break;
+        };
+
+        let Some(chunk_type) = bytes.get(idx + 4..idx + 8) else {
+            report.error(idx + 4, "EOF while reading chunk type".to_string());
+            break;
+        };
+
+        let name = String::from_utf8_lossy(chunk_type).to_string();
+
+        if !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+            report.error(idx + 4, format!("invalid chunk name \"{}\" ({:02x} {:02x} {:02x} {:02x})", name, chunk_type[0], chunk_type[1], chunk_type[2], chunk_type[3]));
+            break;
+        }
+
+        report.line(format!("  chunk {} at offset 0x{:05x}, length {}", name, idx, length));
+
+        let data_start = idx + 8;
+        let data_end = data_start + length as usize;
+
+        let (Some(data), Some(crc)) = (bytes.get(data_start..data_end), read_u32(bytes, data_end)) else {
+            report.error(data_start, format!("EOF while reading {} chunk data", name));
+            break;
+        };
+
+        chunks += 1;
+
+        if chunks == 1 && name != "IHDR" {
+            report.error(idx, "first chunk must be IHDR".to_string());
+        }
+
+        match name.as_str() {
+            "IHDR" => match Header::parse(data) {
+                Some(parsed) => {
+                    report.line(format!("    {} x {} image, {}, {}", parsed.width, parsed.height, parsed.colour(), parsed.interlacing()));
+                    header = Some(parsed);
+                }
+                None => report.error(data_start, format!("invalid IHDR length ({})", length)),
+            },
+            "IDAT" => {
+                if !seen_idat {
+                    if let Some(description) = describe_zlib(data) {
+                        report.line(format!("    {}", description));
+                    }
+                }
+
+                seen_idat = true;
+            }
+            "IEND" => seen_iend = true,
+            _ => {
+                if let Some(description) = describe(&name, data) {
+                    report.line(format!("    {}", description));
+                }
+            }
+        }
+
+        let mut computed = Crc::new();
+        computed.update(chunk_type);
+        computed.update(data);
+
+        if computed.sum() != crc {
+            report.error(data_end, format!("CRC error in chunk {} (computed {:08x}, expected {:08x})", name, computed.sum(), crc));
+        }
+
+        idx = data_end + 4;
+    }
+
+    if report.errors.is_empty() && !seen_idat {
+        report.error(bytes.len(), "no IDAT chunks".to_string());
+    }
+
+    if report.errors.is_empty() && !seen_iend {
+        report.error(bytes.len(), "file doesn't end with an IEND chunk".to_string());
+    }
+
+    finish(path, bytes, report, chunks, header)
+}
+
+fn finish(path: &str, bytes: &[u8], mut report: Report, chunks: usize, header: Option<Header>) -> Report {
+    if !report.errors.is_empty() {
+        report.line(format!("ERRORS DETECTED in {}", path));
+        return report;
+    }
+
+    let compression = header.map_or(0.0, |header| header.compression(bytes.len()));
+    report.line(format!("No errors detected in {} ({} chunks, {:.1}% compression).", path, chunks, compression));
+
+    report
+}
+
+#[derive(Copy, Clone)]
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    colour_type: u8,
+    interlace_method: u8,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != 13 {
+            return None;
+        }
+
+        Some(Self {
+            width: read_u32(data, 0)?,
+            height: read_u32(data, 4)?,
+            bit_depth: data[8],
+            colour_type: data[9],
+            interlace_method: data[12],
+        })
+    }
+
+    fn channels(&self) -> u32 {
+        match self.colour_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// E.g. `32-bit RGB+alpha`, counting the bits of every channel, as pngcheck does.
+    fn colour(&self) -> String {
+        let name = match self.colour_type {
+            0 => "grayscale",
+            2 => "RGB",
+            3 => "palette",
+            4 => "grayscale+alpha",
+            6 => "RGB+alpha",
+            _ => "illegal colour type",
+        };
+
+        format!("{}-bit {}", self.bit_depth as u32 * self.channels(), name)
+    }
+
+    fn interlacing(&self) -> &'static str {
+        match self.interlace_method {
+            0 => "non-interlaced",
+            _ => "interlaced",
+        }
+    }
+
+    /// How much smaller the file is than the raw image data, in percent.
+    fn compression(&self, file_size: usize) -> f64 {
+        let row_bytes = (self.width as u64 * self.bit_depth as u64 * self.channels() as u64).div_ceil(8);
+        let raw_size = row_bytes * self.height as u64;
+
+        match raw_size {
+            0 => 0.0,
+            _ => 100.0 * (1.0 - file_size as f64 / raw_size as f64),
+        }
+    }
+}
+
+/// The zlib header of the first IDAT, e.g. `zlib: deflated, 32K window, maximum compression`.
+fn describe_zlib(data: &[u8]) -> Option<String> {
+    let (&cmf, &flg) = (data.first()?, data.get(1)?);
+
+    if cmf & 0x0F != 8 {
+        return Some(format!("zlib: unknown compression method {}", cmf & 0x0F));
+    }
+
+    let window = 1u32 << ((cmf >> 4) + 8);
+    let window = match window >= 1024 {
+        true => format!("{}K", window / 1024),
+        false => format!("{}-byte", window),
+    };
+
+    let level = ["superfast", "fast", "default", "maximum"][(flg >> 6) as usize];
+
+    Some(format!("zlib: deflated, {} window, {} compression", window, level))
+}
+
+/// What pngcheck shows of the content of the ancillary chunks it knows.
+fn describe(name: &str, data: &[u8]) -> Option<String> {
+    match name {
+        "PLTE" => Some(format!("{} palette entries", data.len() / 3)),
+        "tRNS" => Some(format!("{} transparency entries", data.len())),
+        "gAMA" => Some(format!("{:.5}", read_u32(data, 0)? as f64 / 100000.0)),
+        "sRGB" => {
+            let intent = match data.first()? {
+                0 => "perceptual",
+                1 => "relative colorimetric",
+                2 => "saturation",
+                3 => "absolute colorimetric",
+                _ => "invalid",
+            };
+
+            Some(format!("rendering intent = {}", intent))
+        }
+        "pHYs" => {
+            let (x, y, unit) = (read_u32(data, 0)?, read_u32(data, 4)?, *data.get(8)?);
+
+            match unit {
+                1 if x == y => Some(format!("{} x {} pixels/meter ({:.0} dpi)", x, y, x as f64 * 0.0254)),
+                1 => Some(format!("{} x {} pixels/meter", x, y)),
+                _ => Some(format!("{} x {} pixels/unit", x, y)),
+            }
+        }
+        "tEXt" => {
+            let separator = data.iter().position(|&b| b == 0)?;
+            let keyword = String::from_utf8_lossy(&data[..separator]);
+            let text = String::from_utf8_lossy(&data[separator + 1..]);
+
+            Some(format!("{}:\n        {}", keyword, text))
+        }
+        "tIME" => {
+            const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+            let time = data.get(..7)?;
+            let year = u16::from_be_bytes([time[0], time[1]]);
+            let month = MONTHS.get((time[2] as usize).wrapping_sub(1))?;
+
+            Some(format!("{} {} {}, {:02}:{:02}:{:02} UTC", time[3], month, year, time[4], time[5], time[6]))
+        }
+        "iCCP" => {
+            let separator = data.iter().position(|&b| b == 0)?;
+
+            Some(format!(
+                "profile name = {}, compression method = {} (deflate)\n    compressed profile = {} bytes",
+                String::from_utf8_lossy(&data[..separator]),
+                data.get(separator + 1)?,
+                data.len().saturating_sub(separator + 2),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn read_u32(bytes: &[u8], idx: usize) -> Option<u32> {
+    let b = bytes.get(idx..idx + 4)?;
+
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+use qcms::{CIE_xyY, CIE_xyYTRIPLE, DataType, Intent, Profile, Transform};
+use crate::png_reader::{Chromaticities, PngReader};
+
+/// cICP colour primaries and transfer function codes (ITU-T H.273) this viewer understands.
+const CICP_BT709: u8 = 1;
+const CICP_DISPLAY_P3: u8 = 12;
+const CICP_SRGB_TRANSFER: u8 = 13;
+
+/// Converts RGBA pixels from one colour space into another.
+pub struct ColourTransform(Transform);
+
+impl ColourTransform {
+    /// A transform from `source` to `output`, either of which is sRGB when None. Returns None
+    /// when both are sRGB and there's nothing to do.
+    pub fn new(source: Option<&Profile>, output: Option<&Profile>) -> Option<Self> {
+        if source.is_none() && output.is_none() {
+            return None;
+        }
+
+        let srgb = Profile::new_sRGB();
+
+        Transform::new(source.unwrap_or(&srgb), output.unwrap_or(&srgb), DataType::RGBA8, Intent::default()).map(Self)
+    }
+
+    /// Converts RGBA bytes in place. Alpha is left alone.
+    pub fn apply(&self, rgba: &mut [u8]) {
+        self.0.apply(rgba);
+    }
+}
+
+/// Reads the ICC profile of a display, to be used as an output profile.
+pub fn load_profile(path: &Path) -> Result<Box<Profile>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut profile = Profile::new_from_slice(&bytes, false)
+        .ok_or_else(|| format!("{}: not a usable ICC profile", path.display()))?;
+
+    profile.precache_output_transform();
+
+    Ok(profile)
+}
+
+/// Display P3: the DCI-P3 primaries with a D65 white point and the sRGB transfer function.
+pub fn display_p3() -> Box<Profile> {
+    let xy = |x: f64, y: f64| CIE_xyY { x, y, Y: 1.0 };
+
+    let primaries = CIE_xyYTRIPLE {
+        red: xy(0.680, 0.320),
+        green: xy(0.265, 0.690),
+        blue: xy(0.150, 0.060),
+    };
+
+    let mut profile = Profile::new_rgb_with_table(xy(0.3127, 0.3290), primaries, &srgb_transfer_table(1024)).unwrap();
+    profile.precache_output_transform();
+
+    profile
+}
+
+fn srgb_transfer_table(size: usize) -> Vec<u16> {
+    (0..size)
+        .map(|i| {
+            let v = i as f64 / (size - 1) as f64;
+            let linear = if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) };
+            (linear * 65535.0).round() as u16
+        })
+        .collect()
+}
+
+/// The colour space an image declares, or None for sRGB (including untagged images).
+/// A cICP chunk takes precedence over iCCP, as the PNG specification requires.
+pub fn image_profile(reader: &PngReader) -> Option<Box<Profile>> {
+    match reader.info().cicp {
+        Some([CICP_BT709, CICP_SRGB_TRANSFER, ..]) => return None,
+        Some([CICP_DISPLAY_P3, CICP_SRGB_TRANSFER, ..]) => return Some(display_p3()),
+        _ => (),
+    }
+
+    let profile = Profile::new_from_slice(reader.info().icc_profile.as_ref()?, false)?;
+
+    match profile.is_sRGB() {
+        true => None,
+        false => Some(profile),
+    }
+}
+
+/// The colour space the image's pixels are in, by every chunk that can say so, in the order of
+/// precedence the PNG specification gives: cICP, iCCP, sRGB, then gAMA and cHRM. None for sRGB,
+/// including untagged images. Unlike `image_profile` this honours gAMA and cHRM, which browsers
+/// mostly ignore, so it's for converting files rather than showing them as a browser would.
+pub fn declared_profile(reader: &PngReader) -> Option<Box<Profile>> {
+    let info = reader.info();
+
+    if info.cicp.is_some() || info.icc_profile.is_some() {
+        return image_profile(reader);
+    }
+
+    if info.srgb_intent.is_some() || (info.gamma.is_none() && info.chromaticities.is_none()) {
+        return None;
+    }
+
+    let xy = |(x, y): (f64, f64)| CIE_xyY { x, y, Y: 1.0 };
+
+    // without cHRM, the primaries and white point are sRGB's
+    let chromaticities = info.chromaticities.unwrap_or(Chromaticities {
+        white: (0.3127, 0.3290),
+        red: (0.640, 0.330),
+        green: (0.300, 0.600),
+        blue: (0.150, 0.060),
+    });
+
+    let primaries = CIE_xyYTRIPLE {
+        red: xy(chromaticities.red),
+        green: xy(chromaticities.green),
+        blue: xy(chromaticities.blue),
+    };
+
+    // gAMA gives the exponent that encoded the samples, so decoding raises them to its inverse;
+    // without it the samples are taken to have sRGB's transfer function
+    let table = match info.gamma.filter(|&gamma| gamma > 0.0) {
+        Some(gamma) => (0..1024).map(|i| ((i as f64 / 1023.0).powf(1.0 / gamma) * 65535.0).round() as u16).collect(),
+        None => srgb_transfer_table(1024),
+    };
+
+    Profile::new_rgb_with_table(xy(chromaticities.white), primaries, &table)
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use colored::Colorize;
+use serde::Deserialize;
+use crate::keys::KeyBindings;
+use crate::options::parse_colour;
+use crate::viewer::ScalingFilter;
+
+/// Defaults read from `config.toml` in the config directory (`~/.config/png-viewer` on Linux).
+/// Command line flags override them.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Colour around the image, as `#rgb` or `#rrggbb`.
+    pub background: Option<String>,
+    /// Scaling filter: `nearest`, `linear` or `bicubic`.
+    pub filter: Option<String>,
+    /// Startup zoom, 1.0 showing one image pixel per logical pixel.
+    pub zoom: Option<f64>,
+    /// Seconds each image is shown for in a slideshow.
+    pub slideshow_interval: Option<f64>,
+    /// ICC profile of the display.
+    pub display_profile: Option<PathBuf>,
+    /// Whether the display is Display P3, when there's no display profile.
+    pub display_p3: Option<bool>,
+    /// Action name to key name(s), each optionally prefixed with `Ctrl+`, replacing that action's
+    /// default keys.
+    pub keys: HashMap<String, String>,
+}
+
+impl Config {
+    pub fn dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("png-viewer"))
+    }
+
+    /// Loads the config file, falling back to the defaults if there is none or it is invalid.
+    pub fn load() -> Self {
+        let Some(path) = Self::dir().map(|dir| dir.join("config.toml")) else {
+            return Self::default();
+        };
+
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        let config = toml::from_str::<Self>(&text)
+            .map_err(|e| e.to_string())
+            .and_then(|config| config.validate().map(|_| config));
+
+        match config {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{} {}: {}", "Ignoring invalid config".red(), path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Checks the values that parse as TOML but mean nothing to the viewer.
+    fn validate(&self) -> Result<(), String> {
+        if let Some(background) = self.background.as_ref().filter(|background| parse_colour(background).is_none()) {
+            return Err(format!("invalid background {:?}, expected #rgb or #rrggbb", background));
+        }
+
+        if let Some(filter) = self.filter.as_ref().filter(|filter| ScalingFilter::parse(filter).is_none()) {
+            return Err(format!("invalid filter {:?}, expected nearest, linear or bicubic", filter));
+        }
+
+        for (name, value) in [("zoom", self.zoom), ("slideshow_interval", self.slideshow_interval)] {
+            if let Some(value) = value.filter(|value| !(value.is_finite() && *value > 0.0)) {
+                return Err(format!("invalid {} {}, expected a positive number", name, value));
+            }
+        }
+
+        KeyBindings::new(&self.keys).map(|_| ())
+    }
+}
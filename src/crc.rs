@@ -0,0 +1,134 @@
+/// The reversed CRC-32 polynomial PNG uses, the same as zlib's and Ethernet's.
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+/// `TABLES[0]` is the usual byte-at-a-time table; `TABLES[k][n]` is the CRC of byte `n` followed
+/// by `k` zero bytes, which slicing-by-8 uses to fold eight bytes in at once.
+static TABLES: [[u32; 256]; 8] = tables();
+
+const fn tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0; 256]; 8];
+    let mut n = 0;
+
+    while n < 256 {
+        let mut c = n as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            c = if c & 1 != 0 { POLYNOMIAL ^ (c >> 1) } else { c >> 1 };
+            bit += 1;
+        }
+
+        tables[0][n] = c;
+        n += 1;
+    }
+
+    let mut k = 1;
+
+    while k < 8 {
+        let mut n = 0;
+
+        while n < 256 {
+            let previous = tables[k - 1][n];
+            tables[k][n] = (previous >> 8) ^ tables[0][(previous & 0xFF) as usize];
+            n += 1;
+        }
+
+        k += 1;
+    }
+
+    tables
+}
+
+/// A running CRC-32 over a chunk's type and data, as stored after them.
+#[derive(Copy, Clone, Debug)]
+pub struct Crc {
+    /// The register, inverted as the algorithm keeps it between updates.
+    state: u32,
+}
+
+impl Crc {
+    pub fn new() -> Self {
+        Self { state: 0xFFFFFFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.state = slicing_by_8(self.state, data);
+    }
+
+    pub fn sum(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The CRC of a chunk with this type and data.
+pub fn chunk_crc(chunk_type: &[u8], data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(chunk_type);
+    crc.update(data);
+    crc.sum()
+}
+
+/// Folds `data` into the register one byte at a time, with a table lookup per byte.
+pub fn table(mut state: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        state = (state >> 8) ^ TABLES[0][((state ^ byte as u32) & 0xFF) as usize];
+    }
+
+    state
+}
+
+/// Folds `data` into the register eight bytes at a time, with eight independent lookups per
+/// step instead of eight dependent ones, then the rest a byte at a time.
+pub fn slicing_by_8(mut state: u32, data: &[u8]) -> u32 {
+    let mut blocks = data.chunks_exact(8);
+
+    for block in &mut blocks {
+        let low = state ^ u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        let high = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+        state = TABLES[7][(low & 0xFF) as usize]
+            ^ TABLES[6][(low >> 8 & 0xFF) as usize]
+            ^ TABLES[5][(low >> 16 & 0xFF) as usize]
+            ^ TABLES[4][(low >> 24) as usize]
+            ^ TABLES[3][(high & 0xFF) as usize]
+            ^ TABLES[2][(high >> 8 & 0xFF) as usize]
+            ^ TABLES[1][(high >> 16 & 0xFF) as usize]
+            ^ TABLES[0][(high >> 24) as usize];
+    }
+
+    table(state, blocks.remainder())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        let mut crc = Crc::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+
+        assert_eq!(crc.sum(), 0xCBF43926);
+    }
+
+    #[test]
+    fn chunk() {
+        assert_eq!(chunk_crc(b"IEND", &[]), 0xAE426082);
+    }
+
+    #[test]
+    fn slicing_matches_table() {
+        let data = (0..64).map(|i| (i * 37 + 11) as u8).collect::<Vec<_>>();
+
+        for len in 0..data.len() {
+            assert_eq!(slicing_by_8(0xFFFFFFFF, &data[..len]), table(0xFFFFFFFF, &data[..len]));
+        }
+    }
+}
@@ -0,0 +1,431 @@
+/// Order in which the code length code lengths are stored in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BlockType {
+    Stored,
+    Fixed,
+    Dynamic,
+}
+
+/// One deflate block and what it encodes.
+pub struct Block {
+    pub block_type: BlockType,
+    /// Bit offset of the block header from the start of the deflate data.
+    pub start_bit: usize,
+    /// Size of the block including its header, in bits.
+    pub bits: usize,
+    /// Bytes the block inflates to.
+    pub output_bytes: usize,
+    pub literals: usize,
+    pub matches: usize,
+    /// Bytes produced by matches rather than literals.
+    pub matched_bytes: usize,
+    pub longest_match: usize,
+    /// Furthest back any match in the block reaches.
+    pub max_distance: usize,
+}
+
+/// The structure of a zlib stream.
+pub struct Analysis {
+    /// Window size declared in the zlib header.
+    pub window_size: usize,
+    /// Compression level hint from the zlib header, 0 (fastest) to 3 (maximum).
+    pub level: u8,
+    pub blocks: Vec<Block>,
+    pub adler32_ok: bool,
+}
+
+/// Inflates a zlib stream, recording every block instead of keeping the output.
+pub fn analyze(zlib: &[u8]) -> Result<Analysis, String> {
+    run(zlib, |_| ()).map(|(analysis, _)| analysis)
+}
+
+/// Inflates a zlib stream, calling `trace` with each block once it's decoded, and checks the
+/// Adler-32 checksum of the output.
+pub fn inflate(zlib: &[u8], trace: impl FnMut(&Block)) -> Result<Vec<u8>, String> {
+    let (analysis, output) = run(zlib, trace)?;
+
+    if !analysis.adler32_ok {
+        return Err("The Adler-32 checksum doesn't match the inflated data".to_string());
+    }
+
+    Ok(output)
+}
+
+fn run(zlib: &[u8], mut trace: impl FnMut(&Block)) -> Result<(Analysis, Vec<u8>), String> {
+    let (&cmf, &flg) = (zlib.first().ok_or("Empty stream")?, zlib.get(1).ok_or("Truncated zlib header")?);
+
+    if cmf & 0x0F != 8 {
+        return Err(format!("Unknown compression method {}", cmf & 0x0F));
+    }
+
+    if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err("Corrupt zlib header".to_string());
+    }
+
+    if flg & 0x20 != 0 {
+        return Err("Preset dictionaries aren't allowed in PNG".to_string());
+    }
+
+    let mut inflater = Inflater {
+        bits: BitReader { data: &zlib[2..], pos: 0 },
+        output: vec![],
+    };
+
+    let mut blocks = vec![];
+
+    loop {
+        let start_bit = inflater.bits.pos;
+        let output_start = inflater.output.len();
+        let last = inflater.bits.read(1)? == 1;
+
+        let mut block = match inflater.bits.read(2)? {
+            0 => inflater.stored()?,
+            1 => inflater.codes(&Huffman::fixed_literals(), &Huffman::fixed_distances(), BlockType::Fixed)?,
+            2 => {
+                let (literals, distances) = inflater.dynamic_tables()?;
+                inflater.codes(&literals, &distances, BlockType::Dynamic)?
+            }
+            _ => return Err(format!("Invalid block type at bit {}", start_bit)),
+        };
+
+        block.start_bit = start_bit;
+        block.bits = inflater.bits.pos - start_bit;
+        block.output_bytes = inflater.output.len() - output_start;
+        trace(&block);
+        blocks.push(block);
+
+        if last {
+            break;
+        }
+    }
+
+    let trailer_start = 2 + inflater.bits.pos.div_ceil(8);
+    let adler32_ok = zlib
+        .get(trailer_start..trailer_start + 4)
+        .is_some_and(|trailer| u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) == adler32(&inflater.output));
+
+    let analysis = Analysis {
+        window_size: 1 << ((cmf >> 4) + 8),
+        level: flg >> 6,
+        blocks,
+        adler32_ok,
+    };
+
+    Ok((analysis, inflater.output))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    b << 16 | a
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    /// Reads `count` bits, least significant first.
+    fn read(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+
+        for i in 0..count {
+            let byte = self.data.get(self.pos / 8).ok_or("Unexpected end of deflate data")?;
+            value |= ((byte >> (self.pos % 8)) as u32 & 1) << i;
+            self.pos += 1;
+        }
+
+        Ok(value)
+    }
+}
+
+/// A canonical Huffman code as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn fixed_literals() -> Self {
+        let lengths = (0..288)
+            .map(|symbol| match symbol {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            })
+            .collect::<Vec<_>>();
+
+        Self::new(&lengths)
+    }
+
+    fn fixed_distances() -> Self {
+        Self::new(&[5; 30])
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for length in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[length] as i32;
+
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(format!("Invalid Huffman code before bit {}", bits.pos))
+    }
+}
+
+struct Inflater<'a> {
+    bits: BitReader<'a>,
+    output: Vec<u8>,
+}
+
+impl Inflater<'_> {
+    fn empty_block(block_type: BlockType) -> Block {
+        Block {
+            block_type,
+            start_bit: 0,
+            bits: 0,
+            output_bytes: 0,
+            literals: 0,
+            matches: 0,
+            matched_bytes: 0,
+            longest_match: 0,
+            max_distance: 0,
+        }
+    }
+
+    fn stored(&mut self) -> Result<Block, String> {
+        self.bits.pos = self.bits.pos.div_ceil(8) * 8;
+
+        let length = self.bits.read(16)? as usize;
+        let complement = self.bits.read(16)? as usize;
+
+        if length != !complement & 0xFFFF {
+            return Err(format!("Stored block length doesn't match its complement before bit {}", self.bits.pos));
+        }
+
+        let start = self.bits.pos / 8;
+        let data = self.bits.data.get(start..start + length).ok_or("Unexpected end of stored block")?;
+        self.output.extend_from_slice(data);
+        self.bits.pos += length * 8;
+
+        let mut block = Self::empty_block(BlockType::Stored);
+        block.literals = length;
+
+        Ok(block)
+    }
+
+    fn dynamic_tables(&mut self) -> Result<(Huffman, Huffman), String> {
+        let literal_count = self.bits.read(5)? as usize + 257;
+        let distance_count = self.bits.read(5)? as usize + 1;
+        let code_length_count = self.bits.read(4)? as usize + 4;
+
+        let mut code_lengths = [0u8; 19];
+
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = self.bits.read(3)? as u8;
+        }
+
+        let code_length_code = Huffman::new(&code_lengths);
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+
+        while lengths.len() < literal_count + distance_count {
+            let (value, repeat) = match code_length_code.decode(&mut self.bits)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => (*lengths.last().ok_or("Repeat with no previous length")?, 3 + self.bits.read(2)?),
+                17 => (0, 3 + self.bits.read(3)?),
+                _ => (0, 11 + self.bits.read(7)?),
+            };
+
+            lengths.extend(std::iter::repeat_n(value, repeat as usize));
+        }
+
+        if lengths.len() > literal_count + distance_count {
+            return Err("Code lengths overrun the table".to_string());
+        }
+
+        Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+    }
+
+    fn codes(&mut self, literals: &Huffman, distances: &Huffman, block_type: BlockType) -> Result<Block, String> {
+        let mut block = Self::empty_block(block_type);
+
+        loop {
+            let symbol = literals.decode(&mut self.bits)? as usize;
+
+            match symbol {
+                0..=255 => {
+                    self.output.push(symbol as u8);
+                    block.literals += 1;
+                }
+                256 => return Ok(block),
+                _ => {
+                    let index = symbol - 257;
+                    let length = *LENGTH_BASE.get(index).ok_or("Invalid length symbol")? as usize + self.bits.read(LENGTH_EXTRA[index] as u32)? as usize;
+
+                    let index = distances.decode(&mut self.bits)? as usize;
+                    let distance = *DISTANCE_BASE.get(index).ok_or("Invalid distance symbol")? as usize + self.bits.read(DISTANCE_EXTRA[index] as u32)? as usize;
+
+                    if distance > self.output.len() {
+                        return Err(format!("Distance {} reaches before the start of the data", distance));
+                    }
+
+                    let start = self.output.len() - distance;
+
+                    for i in 0..length {
+                        let byte = self.output[start + i];
+                        self.output.push(byte);
+                    }
+
+                    block.matches += 1;
+                    block.matched_bytes += length;
+                    block.longest_match = block.longest_match.max(length);
+                    block.max_distance = block.max_distance.max(distance);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use super::*;
+
+    /// Text that compresses well followed by bytes that don't, so encoders use long matches,
+    /// literals and, at level 0, stored blocks.
+    fn sample(len: usize) -> Vec<u8> {
+        let text = b"The quick brown fox jumps over the lazy dog. ".iter().copied().cycle().take(len / 2);
+        let mut state = 0x2545F491u32;
+        let noise = std::iter::repeat_with(|| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        });
+
+        text.chain(noise).take(len).collect()
+    }
+
+    fn flate2(data: &[u8], level: u32) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::new(level));
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zopfli(data: &[u8]) -> Vec<u8> {
+        let mut compressed = vec![];
+        zopfli::compress(zopfli::Options::default(), zopfli::Format::Zlib, data, &mut compressed).unwrap();
+        compressed
+    }
+
+    /// Inflates `zlib`, checking it gives back `data`, and returns the types of its blocks.
+    fn round_trip(zlib: &[u8], data: &[u8]) -> Vec<BlockType> {
+        let mut block_types = vec![];
+        let output = inflate(zlib, |block| block_types.push(block.block_type)).unwrap();
+
+        assert_eq!(output, data);
+        block_types
+    }
+
+    #[test]
+    fn stored() {
+        let data = sample(100_000);
+
+        assert!(round_trip(&flate2(&data, 0), &data).iter().all(|&block_type| block_type == BlockType::Stored));
+    }
+
+    #[test]
+    fn fixed() {
+        let data = b"abcabcabcabc";
+
+        assert_eq!(round_trip(&flate2(data, 6), data), [BlockType::Fixed]);
+    }
+
+    #[test]
+    fn dynamic() {
+        let data = sample(100_000);
+
+        assert!(round_trip(&flate2(&data, 9), &data).contains(&BlockType::Dynamic));
+        assert!(round_trip(&zopfli(&data), &data).contains(&BlockType::Dynamic));
+    }
+
+    #[test]
+    fn zopfli_fixed() {
+        let data = b"abcabcabcabc";
+
+        assert_eq!(round_trip(&zopfli(data), data), [BlockType::Fixed]);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(round_trip(&flate2(&[], 6), &[]).len(), 1);
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut zlib = flate2(&sample(1000), 6);
+        *zlib.last_mut().unwrap() ^= 1;
+
+        assert!(inflate(&zlib, |_| ()).is_err());
+    }
+}
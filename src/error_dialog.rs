@@ -0,0 +1,21 @@
+use std::io::{self, IsTerminal};
+use colored::Colorize;
+use rfd::{MessageDialog, MessageLevel};
+
+/// Says that `image_path` can't be shown and why, on stderr and, when there's no terminal to
+/// read that in, as when the viewer is opened from a file manager, in an error dialog. `message`
+/// is the reader's error, which says where in the file it failed. Must be called on the main
+/// thread, which the dialog needs on macOS.
+pub fn show(image_path: &str, message: &str) {
+    eprintln!("{} {}: {}", "Failed to show".red(), image_path, message);
+
+    if io::stderr().is_terminal() {
+        return;
+    }
+
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title("png-viewer")
+        .set_description(format!("Failed to show {}\n\n{}", image_path, message))
+        .show();
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use colored::Colorize;
+use flate2::read::ZlibDecoder;
+use crate::colour::{self, ColourTransform};
+use crate::metadata;
+use crate::options::Options;
+use crate::png_reader::{Pixel, PngReader};
+use crate::png_writer::PngWriter;
+use crate::resize;
+use crate::transform;
+use crate::viewer::Rect;
+
+/// Writes the decoded image to `output` as an 8-bit RGBA PNG, turned upright by its EXIF
+/// orientation if enabled, given the alpha of `--apply-alpha`, then turned by `--rotate` and
+/// `--flip`, cropped to `--crop`, scaled by `--resize` and premultiplied with `--premultiply`.
+/// With `--to-srgb` the colours are first converted from the colour space the file declares to
+/// sRGB, and the file written says it's sRGB. `--set-dpi` gives it a pHYs chunk.
+pub fn run(mut reader: PngReader, options: &Options, output: &Path) -> Result<(), String> {
+    reader.decode_image_data()?;
+
+    let mut pixel_data = transform::orient(&reader.pixel_data, crate::orientation(&reader, options));
+
+    if options.to_srgb {
+        to_srgb(&mut pixel_data, &reader);
+    }
+
+    if let Some(mask) = &options.apply_alpha {
+        apply_alpha(&mut pixel_data, mask, options)?;
+    }
+
+    pixel_data = turn(&pixel_data, options);
+
+    if let Some(rect) = options.crop {
+        pixel_data = crop(&pixel_data, rect)?;
+    }
+
+    if let Some(size) = options.resize {
+        let (width, height) = size.target(pixel_data[0].len() as u32, pixel_data.len() as u32);
+        pixel_data = resize::resize(&pixel_data, width, height, options.resize_filter);
+    }
+
+    if options.premultiply {
+        premultiply(&mut pixel_data);
+    }
+
+    let (width, height) = (pixel_data.first().map_or(0, |row| row.len()) as u32, pixel_data.len() as u32);
+    let mut writer = PngWriter::new(width, height, pixel_data);
+
+    if options.to_srgb {
+        // perceptual rendering intent
+        writer.add_chunk(*b"sRGB", vec![0]);
+    }
+
+    if let Some(dpi) = options.set_dpi {
+        writer.add_chunk(*b"pHYs", metadata::phys(dpi));
+    }
+
+    let bytes = writer.write();
+
+    fs::write(output, &bytes).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{} {}x{} -> {}", "Exported".green(), width, height, output.display());
+
+    Ok(())
+}
+
+/// Writes the upright image's alpha channel to `output` as an 8-bit greyscale PNG, opaque
+/// being white.
+pub fn extract_alpha(mut reader: PngReader, options: &Options, output: &Path) -> Result<(), String> {
+    reader.decode_image_data()?;
+
+    let pixel_data = transform::orient(&reader.pixel_data, crate::orientation(&reader, options));
+    let alpha = pixel_data.iter().map(|row| row.iter().map(|pixel| pixel.a).collect()).collect::<Vec<Vec<u8>>>();
+    let (width, height) = (pixel_data[0].len() as u32, pixel_data.len() as u32);
+
+    fs::write(output, PngWriter::write_grey(width, height, &alpha)).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{} {}x{} -> {}", "Extracted the alpha channel".green(), width, height, output.display());
+
+    Ok(())
+}
+
+/// Writes the IDAT chunks' data joined together, which is the zlib stream, to `stream`, and the
+/// inflated but still filtered scanlines, each led by its filter type byte, to `raw`.
+pub fn extract_idat(reader: &PngReader, stream: Option<&Path>, raw: Option<&Path>) -> Result<(), String> {
+    let compressed = reader.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type == "IDAT")
+        .flat_map(|chunk| reader.chunk_data(chunk))
+        .copied()
+        .collect::<Vec<u8>>();
+
+    if let Some(output) = stream {
+        fs::write(output, &compressed).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+        println!("{} {} bytes of zlib stream -> {}", "Extracted".green(), compressed.len(), output.display());
+    }
+
+    if let Some(output) = raw {
+        let mut data = vec![];
+        ZlibDecoder::new(&compressed[..]).read_to_end(&mut data).map_err(|e| format!("Invalid image data: {}", e))?;
+
+        fs::write(output, &data).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+        println!("{} {} bytes of filtered scanlines -> {}", "Extracted".green(), data.len(), output.display());
+    }
+
+    Ok(())
+}
+
+/// Replaces the alpha of every pixel with the brightness of the mask's pixel, which should be
+/// grey, at the same position.
+fn apply_alpha(pixel_data: &mut [Vec<Pixel>], mask_path: &Path, options: &Options) -> Result<(), String> {
+    let bytes = fs::read(mask_path).map_err(|e| format!("Failed to open {}: {}", mask_path.display(), e))?;
+    let mut mask = PngReader::new(bytes);
+    mask.set_verbose(false);
+    mask.set_dither(options.dither);
+    mask.read_chunks().and_then(|()| mask.decode_image_data()).map_err(|e| format!("{}: {}", mask_path.display(), e))?;
+
+    let (width, height) = (pixel_data[0].len(), pixel_data.len());
+
+    if (mask.info().width as usize, mask.info().height as usize) != (width, height) {
+        return Err(format!("The mask is {}x{}, not {}x{} like the image", mask.info().width, mask.info().height, width, height));
+    }
+
+    for (row, mask_row) in pixel_data.iter_mut().zip(&mask.pixel_data) {
+        for (pixel, m) in row.iter_mut().zip(mask_row) {
+            pixel.a = (0.2126 * m.r as f64 + 0.7152 * m.g as f64 + 0.0722 * m.b as f64).round() as u8;
+        }
+    }
+
+    Ok(())
+}
+
+/// Cuts the upright image into `width` x `height` tiles, left to right and top to bottom, and
+/// writes each to `dir` as `tile_<row>_<column>.png`, counting from 1. Pixels past the last
+/// whole tile on the right and bottom are left out, as they'd make tiles of another size.
+pub fn slice(mut reader: PngReader, (width, height): (u32, u32), skip_transparent: bool, options: &Options, dir: &Path) -> Result<(), String> {
+    reader.decode_image_data()?;
+
+    let pixel_data = transform::orient(&reader.pixel_data, crate::orientation(&reader, options));
+    let (columns, rows) = (pixel_data[0].len() / width as usize, pixel_data.len() / height as usize);
+
+    if columns == 0 || rows == 0 {
+        return Err(format!("The {}x{} image is smaller than a {}x{} tile", pixel_data[0].len(), pixel_data.len(), width, height));
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let digits = rows.max(columns).to_string().len();
+    let (mut written, mut skipped) = (0, 0);
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let rect = Rect { x: column as u32 * width, y: row as u32 * height, width, height };
+            let tile = crop(&pixel_data, rect)?;
+
+            if skip_transparent && tile.iter().flatten().all(|pixel| pixel.a == 0) {
+                skipped += 1;
+                continue;
+            }
+
+            let path = dir.join(format!("tile_{:0digits$}_{:0digits$}.png", row + 1, column + 1, digits = digits));
+            fs::write(&path, PngWriter::new(width, height, tile).write()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            written += 1;
+        }
+    }
+
+    let left_over = (pixel_data[0].len() % width as usize, pixel_data.len() % height as usize);
+
+    if left_over != (0, 0) {
+        println!("Left out {} px on the right and {} px at the bottom", left_over.0, left_over.1);
+    }
+
+    match skipped {
+        0 => println!("{} {} tiles of {}x{} -> {}", "Sliced".green(), written, width, height, dir.display()),
+        _ => println!("{} {} tiles of {}x{}, skipping {} transparent ones -> {}", "Sliced".green(), written, width, height, skipped, dir.display()),
+    }
+
+    Ok(())
+}
+
+/// Applies `--rotate` and then `--flip`.
+pub fn turn(pixel_data: &[Vec<Pixel>], options: &Options) -> Vec<Vec<Pixel>> {
+    let rotated = transform::rotate(pixel_data, options.rotate);
+
+    match options.flip {
+        Some(flip) => transform::flip(&rotated, flip),
+        None => rotated,
+    }
+}
+
+/// Converts the pixels from the colour space the image declares into sRGB, if it's not already.
+fn to_srgb(pixel_data: &mut [Vec<Pixel>], reader: &PngReader) {
+    let Some(transform) = ColourTransform::new(colour::declared_profile(reader).as_deref(), None) else {
+        return;
+    };
+
+    for row in pixel_data {
+        let mut rgba = row.iter().flat_map(|p| [p.r, p.g, p.b, p.a]).collect::<Vec<u8>>();
+        transform.apply(&mut rgba);
+
+        for (pixel, p) in row.iter_mut().zip(rgba.chunks_exact(4)) {
+            (pixel.r, pixel.g, pixel.b) = (p[0], p[1], p[2]);
+        }
+    }
+}
+
+/// Multiplies each pixel's colour by its alpha, for pipelines that want premultiplied data.
+/// Nothing records that in the file, so a viewer will show it darkened where it's transparent.
+pub fn premultiply(pixel_data: &mut [Vec<Pixel>]) {
+    for pixel in pixel_data.iter_mut().flatten() {
+        let alpha = pixel.a as u32;
+        let multiply = |c: u8| ((c as u32 * alpha + 127) / 255) as u8;
+
+        (pixel.r, pixel.g, pixel.b) = (multiply(pixel.r), multiply(pixel.g), multiply(pixel.b));
+    }
+}
+
+/// The pixels inside `rect`, clipped to the image so e.g. `0,0,99999x40` keeps the top 40 rows.
+fn crop(pixel_data: &[Vec<Pixel>], rect: Rect) -> Result<Vec<Vec<Pixel>>, String> {
+    let (width, height) = (pixel_data.first().map_or(0, |row| row.len()), pixel_data.len());
+    let (x, y) = (rect.x as usize, rect.y as usize);
+    let right = x.saturating_add(rect.width as usize).min(width);
+    let bottom = y.saturating_add(rect.height as usize).min(height);
+
+    if x >= right || y >= bottom {
+        return Err(format!("The crop {},{},{}x{} is outside the {}x{} image", rect.x, rect.y, rect.width, rect.height, width, height));
+    }
+
+    Ok(pixel_data[y..bottom].iter().map(|row| row[x..right].to_vec()).collect())
+}
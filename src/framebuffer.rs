@@ -0,0 +1,209 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use crossterm::ExecutableCommand;
+use crate::keys::{self, Action};
+use crate::options::Options;
+use crate::viewer::{AlphaView, Simulation, Viewer};
+
+const DEVICE: &str = "/dev/fb0";
+const SYSFS: &str = "/sys/class/graphics/fb0";
+
+/// Whether to show images on the Linux framebuffer rather than in a window: there's no X or
+/// Wayland session to open one in, but there is a framebuffer, as on kiosk devices and the
+/// console. DRM/KMS drivers provide one through fbdev emulation.
+pub fn available() -> bool {
+    env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_none() && Path::new(DEVICE).exists()
+}
+
+/// The framebuffer device, with its geometry from sysfs.
+struct Framebuffer {
+    file: File,
+    width: u32,
+    height: u32,
+    stride: usize,
+    bits_per_pixel: u32,
+}
+
+impl Framebuffer {
+    fn open() -> io::Result<Self> {
+        let read = |name: &str| fs::read_to_string(Path::new(SYSFS).join(name)).map(|text| text.trim().to_string());
+        let invalid = |name: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}/{}", SYSFS, name));
+
+        let size = read("virtual_size")?;
+        let (width, height) = size
+            .split_once(',')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+            .ok_or_else(|| invalid("virtual_size"))?;
+
+        let bits_per_pixel = read("bits_per_pixel")?.parse().map_err(|_| invalid("bits_per_pixel"))?;
+        let stride = read("stride")?.parse().map_err(|_| invalid("stride"))?;
+
+        if ![16, 24, 32].contains(&bits_per_pixel) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} bits per pixel isn't supported", bits_per_pixel)));
+        }
+
+        Ok(Self {
+            file: OpenOptions::new().write(true).open(DEVICE)?,
+            width,
+            height,
+            stride,
+            bits_per_pixel,
+        })
+    }
+
+    /// Copies an RGBA frame of the framebuffer's size to the screen, assuming the usual
+    /// little-endian layouts: XRGB8888, RGB888 stored as BGR, or RGB565.
+    fn present(&mut self, frame: &[u8]) -> io::Result<()> {
+        let mut bytes = vec![0; self.stride * self.height as usize];
+
+        for (row, line) in frame.chunks_exact(self.width as usize * 4).zip(bytes.chunks_exact_mut(self.stride)) {
+            for (i, p) in row.chunks_exact(4).enumerate() {
+                match self.bits_per_pixel {
+                    32 => line[i * 4..i * 4 + 4].copy_from_slice(&[p[2], p[1], p[0], 0xFF]),
+                    24 => line[i * 3..i * 3 + 3].copy_from_slice(&[p[2], p[1], p[0]]),
+                    _ => {
+                        let rgb565 = (p[0] as u16 >> 3) << 11 | (p[1] as u16 >> 2) << 5 | p[2] as u16 >> 3;
+                        line[i * 2..i * 2 + 2].copy_from_slice(&rgb565.to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&bytes)
+    }
+}
+
+/// Shows the first of `image_paths` full screen on the framebuffer, reading keys from the
+/// terminal until `q` or Ctrl-C.
+pub fn run(image_paths: &[String], options: &Options) -> io::Result<()> {
+    let mut framebuffer = Framebuffer::open()?;
+
+    let mut viewer = Viewer::new(framebuffer.width, framebuffer.height, 1.0);
+    crate::configure_viewer(&mut viewer, options);
+    crate::open_png(&image_paths[0], options)
+        .and_then(|reader| crate::show_decoded(&mut viewer, reader, options))
+        .map_err(|e| io::Error::other(format!("{}: {}", image_paths[0], e)))?;
+    viewer.zoom_to_fit();
+
+    enable_raw_mode()?;
+    io::stdout().execute(Hide)?;
+
+    let result = event_loop(&mut framebuffer, &mut viewer, image_paths, options);
+
+    io::stdout().execute(Show)?;
+    disable_raw_mode()?;
+
+    result
+}
+
+fn event_loop(framebuffer: &mut Framebuffer, viewer: &mut Viewer, image_paths: &[String], options: &Options) -> io::Result<()> {
+    let mut frame = vec![0; framebuffer.width as usize * framebuffer.height as usize * 4];
+    let mut index = 0;
+    viewer.set_position(index, image_paths.len());
+
+    loop {
+        viewer.draw(&mut frame);
+        framebuffer.present(&frame)?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        if key.code == KeyCode::Char('q') || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+            return Ok(());
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        let Some(key) = key_name(key.code).and_then(|name| keys::parse_key(&name)) else {
+            continue;
+        };
+
+        match options.key_bindings.action(key, ctrl) {
+            Some(action @ (Action::NextImage | Action::PreviousImage | Action::FirstImage | Action::LastImage)) if image_paths.len() > 1 => {
+                index = match action {
+                    Action::NextImage => (index + 1) % image_paths.len(),
+                    Action::PreviousImage => (index + image_paths.len() - 1) % image_paths.len(),
+                    Action::FirstImage => 0,
+                    _ => image_paths.len() - 1,
+                };
+
+                crate::open_png(&image_paths[index], options)
+                    .and_then(|reader| crate::show_decoded(viewer, reader, options))
+                    .map_err(|e| io::Error::other(format!("{}: {}", image_paths[index], e)))?;
+                viewer.set_position(index, image_paths.len());
+                viewer.zoom_to_fit();
+            }
+            Some(action @ (Action::ActualSize | Action::Zoom200 | Action::Zoom300)) => viewer.set_zoom(match action {
+                Action::Zoom200 => 2.0,
+                Action::Zoom300 => 3.0,
+                _ => 1.0,
+            }),
+            Some(Action::ZoomToFit) => viewer.zoom_to_fit(),
+            Some(Action::ToggleMetadata) => viewer.toggle_metadata(),
+            Some(Action::ToggleFilters) => viewer.toggle_filters(),
+            Some(Action::ToggleRulers) => viewer.toggle_rulers(),
+            Some(Action::CycleSimulation) => viewer.set_simulation(Simulation::next(viewer.simulation())),
+            Some(Action::CycleAlphaView) => viewer.set_alpha_view(AlphaView::next(viewer.alpha_view())),
+            Some(Action::ToggleInvert) => {
+                viewer.toggle_invert();
+            }
+            Some(Action::SwapRedBlue) => {
+                viewer.toggle_swap_red_blue();
+            }
+            Some(Action::ToggleClipping) => {
+                viewer.toggle_clipping();
+            }
+            Some(Action::ToggleLinear) => {
+                viewer.toggle_linear_samples();
+            }
+            Some(Action::TogglePremultipliedAlpha) => {
+                viewer.toggle_premultiplied_alpha();
+            }
+            Some(action @ (Action::IncreaseExposure | Action::DecreaseExposure)) => {
+                viewer.adjust_exposure(if action == Action::IncreaseExposure { 0.25 } else { -0.25 });
+            }
+            Some(action @ (Action::IncreaseGamma | Action::DecreaseGamma)) => {
+                viewer.adjust_gamma(if action == Action::IncreaseGamma { 1.1 } else { 1.0 / 1.1 });
+            }
+            Some(Action::ResetTone) => viewer.reset_tone(),
+            _ => ()
+        }
+    }
+}
+
+/// The name `keys::parse_key` knows a terminal key by.
+fn key_name(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(' ') => "Space",
+        KeyCode::Char(c) => return Some(c.to_string()),
+        KeyCode::F(n) => return Some(format!("F{}", n)),
+        KeyCode::Enter => "Return",
+        KeyCode::Esc => "Escape",
+        KeyCode::Tab => "Tab",
+        KeyCode::Backspace => "Backspace",
+        KeyCode::Delete => "Delete",
+        KeyCode::Insert => "Insert",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::PageDown => "PageDown",
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
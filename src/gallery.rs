@@ -0,0 +1,103 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::path::Path;
+use std::time::SystemTime;
+use colored::Colorize;
+
+/// Extensions of the files a directory is searched for, compared case-insensitively.
+const EXTENSIONS: [&str; 2] = ["png", "apng"];
+
+/// Order to step through images in, following feh's `--sort`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SortOrder {
+    /// By file name, whatever directory the file is in.
+    Name,
+    /// Most recently modified first.
+    Mtime,
+    /// Largest file first.
+    Size,
+    /// Shuffled, differently each run.
+    Random,
+}
+
+impl SortOrder {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Self::Name),
+            "mtime" => Some(Self::Mtime),
+            "size" => Some(Self::Size),
+            "random" => Some(Self::Random),
+            _ => None,
+        }
+    }
+}
+
+/// Reorders `paths` by `order`, or leaves them in the order given when None, then reverses them
+/// if asked. Ties are broken by path, and files that can't be read sort as oldest and empty.
+pub fn sort(paths: &mut [String], order: Option<SortOrder>, reverse: bool) {
+    match order {
+        Some(SortOrder::Name) => paths.sort_by_cached_key(|path| (Path::new(path).file_name().map(|name| name.to_os_string()), path.clone())),
+        Some(SortOrder::Mtime) => paths.sort_by_cached_key(|path| {
+            let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            (Reverse(modified), path.clone())
+        }),
+        Some(SortOrder::Size) => paths.sort_by_cached_key(|path| (Reverse(fs::metadata(path).map_or(0, |metadata| metadata.len())), path.clone())),
+        Some(SortOrder::Random) => {
+            // hashing with freshly seeded keys gives each run its own order
+            let state = RandomState::new();
+            paths.sort_by_cached_key(|path| state.hash_one(path));
+        }
+        None => (),
+    }
+
+    if reverse {
+        paths.reverse();
+    }
+}
+
+/// Replaces each directory in `paths` with every image beneath it, sorted by path so a
+/// directory's images stay together. Files are kept as given.
+pub fn expand(paths: &[String]) -> Vec<String> {
+    let mut images = vec![];
+
+    for path in paths {
+        match Path::new(path).is_dir() {
+            true => {
+                let mut found = vec![];
+                collect(Path::new(path), &mut found);
+                found.sort();
+                images.extend(found);
+            }
+            false => images.push(path.clone()),
+        }
+    }
+
+    images
+}
+
+fn collect(dir: &Path, images: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{} {}: {}", "Failed to read".red(), dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        // file_type doesn't follow symlinks, so a link back up the tree isn't walked forever
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => collect(&path, images),
+            Ok(_) if is_image(&path) => images.push(path.to_string_lossy().into_owned()),
+            _ => (),
+        }
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| EXTENSIONS.iter().any(|e| extension.eq_ignore_ascii_case(e)))
+}
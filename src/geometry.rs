@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::PathBuf;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use winit::dpi::{LogicalSize, PhysicalPosition};
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::Window;
+use crate::config::Config;
+
+/// Where the window was when it was last closed, saved to `geometry.toml` in the config directory.
+#[derive(Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Name of the monitor the window was on. The position is only restored onto the same monitor.
+    monitor: Option<String>,
+    /// Outer position relative to the monitor's top-left corner, in physical pixels.
+    x: i32,
+    y: i32,
+    /// Inner size in logical pixels, so it carries over between monitors with different scale factors.
+    width: f64,
+    height: f64,
+}
+
+impl WindowGeometry {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("geometry.toml"))
+    }
+
+    pub fn load() -> Option<Self> {
+        let text = fs::read_to_string(Self::path()?).ok()?;
+
+        toml::from_str(&text).ok()
+    }
+
+    /// Reads the current geometry from the window. Returns None while it's minimised or the
+    /// platform can't report a position.
+    pub fn from_window(window: &Window) -> Option<Self> {
+        if window.is_minimized() == Some(true) {
+            return None;
+        }
+
+        let position = window.outer_position().ok()?;
+        let monitor = window.current_monitor();
+        let origin = monitor.as_ref().map_or(PhysicalPosition::new(0, 0), |monitor| monitor.position());
+        let size = window.inner_size().to_logical::<f64>(window.scale_factor());
+
+        Some(Self {
+            monitor: monitor.and_then(|monitor| monitor.name()),
+            x: position.x - origin.x,
+            y: position.y - origin.y,
+            width: size.width,
+            height: size.height,
+        })
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+
+        let result = fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, toml::to_string(self).unwrap()));
+
+        if let Err(e) = result {
+            eprintln!("{} {}: {}", "Failed to save window geometry".red(), path.display(), e);
+        }
+    }
+
+    pub fn size(&self) -> LogicalSize<f64> {
+        LogicalSize::new(self.width, self.height)
+    }
+
+    /// The saved position on its monitor, if that monitor is still connected.
+    pub fn position<T>(&self, event_loop: &EventLoopWindowTarget<T>) -> Option<PhysicalPosition<i32>> {
+        let monitor = event_loop
+            .available_monitors()
+            .find(|monitor| monitor.name().is_some() && monitor.name() == self.monitor)?;
+
+        let origin = monitor.position();
+
+        Some(PhysicalPosition::new(origin.x + self.x, origin.y + self.y))
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Encoder, Repeat};
+use crate::apng::{self, Frame};
+use crate::png_reader::{FrameControl, Pixel};
+
+/// A decoded animation: whole-canvas frames and how many times to play them, 0 meaning forever.
+pub struct Animation {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Frame>,
+    pub num_plays: u32,
+}
+
+pub fn is_gif(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")
+}
+
+/// Decodes every frame of a GIF and composites them the way browsers do, with a transparent
+/// background whatever the file's background colour.
+pub fn decode(bytes: &[u8]) -> Result<Animation, String> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+
+    let mut decoder = options.read_info(bytes).map_err(|e| e.to_string())?;
+    let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+    let mut regions = vec![];
+
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        let region = frame.buffer
+            .chunks_exact(frame.width as usize * 4)
+            .map(|row| row.chunks_exact(4).map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] }).collect())
+            .collect();
+
+        // GIF delays are in hundredths of a second and pixels are either opaque or transparent,
+        // so blending over leaves transparent pixels showing what's beneath as GIF does
+        let control = FrameControl {
+            width: frame.width as u32,
+            height: frame.height as u32,
+            x_offset: frame.left as u32,
+            y_offset: frame.top as u32,
+            delay_num: frame.delay,
+            delay_den: 100,
+            dispose_op: match frame.dispose {
+                DisposalMethod::Background => 1,
+                DisposalMethod::Previous => 2,
+                _ => 0,
+            },
+            blend_op: 1,
+        };
+
+        regions.push((control, region));
+    }
+
+    // the loop count is the number of repeats after the first play, and no count plays once
+    let num_plays = match decoder.repeat() {
+        Repeat::Infinite => 0,
+        Repeat::Finite(repeats) => repeats as u32 + 1,
+    };
+
+    Ok(Animation {
+        width,
+        height,
+        frames: apng::composite_regions(width, height, regions),
+        num_plays,
+    })
+}
+
+/// Encodes whole-canvas frames as a GIF, quantising each to its own palette of up to 256
+/// colours. Pixels less than half opaque become transparent and the rest opaque, and delays are
+/// rounded to hundredths of a second.
+pub fn encode(animation: &Animation) -> Result<Vec<u8>, String> {
+    let (width, height) = match (u16::try_from(animation.width), u16::try_from(animation.height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(format!("{}x{} is too large for a GIF", animation.width, animation.height)),
+    };
+
+    let mut bytes = vec![];
+    let mut encoder = Encoder::new(&mut bytes, width, height, &[]).map_err(|e| e.to_string())?;
+
+    match animation.num_plays {
+        0 => encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?,
+        1 => (),
+        plays => encoder.set_repeat(Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16)).map_err(|e| e.to_string())?,
+    }
+
+    for frame in &animation.frames {
+        let mut rgba = frame.pixel_data
+            .iter()
+            .flatten()
+            .flat_map(|p| [p.r, p.g, p.b, if p.a >= 0x80 { 0xFF } else { 0 }])
+            .collect::<Vec<u8>>();
+
+        let mut gif_frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+        gif_frame.delay = centiseconds(frame.delay);
+        // every frame covers the whole canvas, so clear it rather than letting earlier frames
+        // show through transparent pixels
+        gif_frame.dispose = DisposalMethod::Background;
+
+        encoder.write_frame(&gif_frame).map_err(|e| e.to_string())?;
+    }
+
+    drop(encoder);
+
+    Ok(bytes)
+}
+
+fn centiseconds(delay: Duration) -> u16 {
+    (delay.as_secs_f64() * 100.0).round().min(u16::MAX as f64) as u16
+}
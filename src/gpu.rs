@@ -0,0 +1,308 @@
+use pixels::wgpu::{self, util::DeviceExt};
+use pixels::Pixels;
+use crate::viewer::{PaneSource, RenderParams, ScalingFilter, Viewer, DIVIDER};
+use crate::Pixel;
+
+/// Size of one pane's uniforms: five vec4s.
+const PARAMS_SIZE: u64 = 80;
+
+/// Draws the image on the GPU from textures uploaded once, instead of sampling every frame pixel
+/// on the CPU, then composites the overlays the viewer drew into the pixel buffer on top.
+/// Views that need CPU-only features (the filter tint, colour vision simulation, inverted or
+/// swapped colours, linear samples, alpha views, clipping stripes, colour management) are drawn
+/// by `Viewer::draw` instead.
+pub struct GpuRenderer {
+    image_pipeline: wgpu::RenderPipeline,
+    image_layout: wgpu::BindGroupLayout,
+    overlay_pipeline: wgpu::RenderPipeline,
+    overlay_layout: wgpu::BindGroupLayout,
+    /// Uniforms for the left and right panes.
+    pane_params: [wgpu::Buffer; 2],
+    /// Bind groups for the left and right panes with the current image textures.
+    pane_bind_groups: Option<[wgpu::BindGroup; 2]>,
+    /// `Viewer::generation` the textures were made from.
+    generation: Option<u64>,
+    max_texture_size: u32,
+}
+
+impl GpuRenderer {
+    pub fn new(pixels: &Pixels) -> Self {
+        let device = pixels.device();
+        let target_format = pixels.render_texture_format();
+
+        let source = include_str!("shaders/image.wgsl")
+            .replace("OUTPUT_IS_SRGB: bool = true", &format!("OUTPUT_IS_SRGB: bool = {}", target_format.is_srgb()))
+            .replace("OVERLAY_IS_SRGB: bool = true", &format!("OVERLAY_IS_SRGB: bool = {}", pixels.context().texture_format.is_srgb()));
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("image_shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let image_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("image_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(PARAMS_SIZE),
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+            ],
+        });
+
+        let overlay_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("overlay_bind_group_layout"),
+            entries: &[texture_entry(3)],
+        });
+
+        let pipeline = |label: &str, layout: &wgpu::BindGroupLayout, entry_point: &str, blend: Option<wgpu::BlendState>| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        let image_pipeline = pipeline("image_pipeline", &image_layout, "fs_image", None);
+        let overlay_pipeline = pipeline("overlay_pipeline", &overlay_layout, "fs_overlay", Some(wgpu::BlendState::ALPHA_BLENDING));
+
+        let params_buffer = || device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("image_params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            image_pipeline,
+            image_layout,
+            overlay_pipeline,
+            overlay_layout,
+            pane_params: [params_buffer(), params_buffer()],
+            pane_bind_groups: None,
+            generation: None,
+            max_texture_size: device.limits().max_texture_dimension_2d,
+        }
+    }
+
+    /// Whether the viewer's current view can be drawn here.
+    pub fn can_render(&self, viewer: &Viewer) -> bool {
+        let (pixel_data, compare) = viewer.images();
+        let fits = |data: &[Vec<Pixel>]| {
+            data.len() as u32 <= self.max_texture_size && data.first().map_or(0, |row| row.len()) as u32 <= self.max_texture_size
+        };
+
+        !viewer.needs_cpu() && fits(pixel_data) && compare.is_none_or(fits)
+    }
+
+    /// Renders the view. The pixel buffer must already hold `Viewer::draw_overlays_only`.
+    pub fn render(&mut self, pixels: &Pixels, viewer: &Viewer) -> Result<(), pixels::Error> {
+        if self.generation != Some(viewer.generation()) {
+            let (pixel_data, compare) = viewer.images();
+            self.upload(pixels, pixel_data, compare);
+            self.generation = Some(viewer.generation());
+        }
+
+        let params = viewer.render_params();
+
+        for (pane, source) in params.panes.iter().enumerate() {
+            pixels.queue().write_buffer(&self.pane_params[pane], 0, &pane_uniforms(&params, pane, *source));
+        }
+
+        let context = pixels.context();
+        let overlay_view = context.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let overlay_bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("overlay_bind_group"),
+            layout: &self.overlay_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&overlay_view),
+            }],
+        });
+
+        let background = clear_colour(params.background, pixels.render_texture_format().is_srgb());
+        let bind_groups = self.pane_bind_groups.as_ref().unwrap();
+
+        pixels.render_with(|encoder, render_target, _| {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("image_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: render_target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(background),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            pass.set_pipeline(&self.image_pipeline);
+
+            for (pane, bind_group) in bind_groups.iter().take(params.panes.len()).enumerate() {
+                let x = (pane as u32 * params.pane_width) as f32;
+                pass.set_viewport(x, 0.0, params.pane_width as f32, params.frame_height as f32, 0.0, 1.0);
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+
+            pass.set_viewport(0.0, 0.0, params.frame_width as f32, params.frame_height as f32, 0.0, 1.0);
+            pass.set_pipeline(&self.overlay_pipeline);
+            pass.set_bind_group(0, &overlay_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+
+            Ok(())
+        })
+    }
+
+    fn upload(&mut self, pixels: &Pixels, pixel_data: &[Vec<Pixel>], compare: Option<&[Vec<Pixel>]>) {
+        let image = create_texture(pixels, pixel_data);
+        let compare = create_texture(pixels, compare.unwrap_or(&[]));
+
+        let image_view = image.create_view(&wgpu::TextureViewDescriptor::default());
+        let compare_view = compare.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = |params: &wgpu::Buffer| pixels.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("image_bind_group"),
+            layout: &self.image_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&image_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&compare_view),
+                },
+            ],
+        });
+
+        self.pane_bind_groups = Some([bind_group(&self.pane_params[0]), bind_group(&self.pane_params[1])]);
+    }
+}
+
+/// Uploads an image as an RGBA texture. An empty image becomes a single transparent pixel, as
+/// textures can't be empty; it's never sampled.
+fn create_texture(pixels: &Pixels, pixel_data: &[Vec<Pixel>]) -> wgpu::Texture {
+    let width = pixel_data.first().map_or(0, |row| row.len()) as u32;
+    let height = pixel_data.len() as u32;
+
+    let (size, bytes) = match width > 0 && height > 0 {
+        true => (
+            (width, height),
+            pixel_data.iter().flatten().flat_map(|p| [p.r, p.g, p.b, p.a]).collect::<Vec<u8>>(),
+        ),
+        false => ((1, 1), vec![0; 4]),
+    };
+
+    pixels.device().create_texture_with_data(
+        pixels.queue(),
+        &wgpu::TextureDescriptor {
+            label: Some("image_texture"),
+            size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        &bytes,
+    )
+}
+
+/// Packs the shader's `Params` for one pane.
+fn pane_uniforms(params: &RenderParams, pane: usize, source: PaneSource) -> Vec<u8> {
+    let colour = |c: [u8; 4]| c.map(|c| c as f32 / 255.0);
+
+    let (source, amplify) = match source {
+        PaneSource::Image => (0, 1.0),
+        PaneSource::Compare => (1, 1.0),
+        PaneSource::Difference { amplify } => (2, amplify as f32),
+    };
+
+    let filter = match params.filter {
+        ScalingFilter::Nearest => 0,
+        ScalingFilter::Linear => 1,
+        ScalingFilter::Bicubic => 2,
+    };
+
+    let floats = [
+        [(pane as u32 * params.pane_width) as f32, params.zoom as f32, params.origin.0 as f32, params.origin.1 as f32],
+        colour(params.background),
+        colour(DIVIDER),
+        [2f64.powf(params.exposure) as f32, (1.0 / params.gamma) as f32, amplify, 0.0],
+    ];
+    let options: [u32; 4] = [source, filter, params.premultiplied_alpha as u32, (pane > 0) as u32];
+
+    floats
+        .iter()
+        .flatten()
+        .flat_map(|f| f.to_ne_bytes())
+        .chain(options.iter().flat_map(|o| o.to_ne_bytes()))
+        .collect()
+}
+
+/// The background as a clear colour, linearised for an sRGB render target.
+fn clear_colour(background: [u8; 4], srgb: bool) -> wgpu::Color {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        match srgb {
+            true if c <= 0.04045 => c / 12.92,
+            true => ((c + 0.055) / 1.055).powf(2.4),
+            false => c,
+        }
+    };
+
+    wgpu::Color {
+        r: channel(background[0]),
+        g: channel(background[1]),
+        b: channel(background[2]),
+        a: 1.0,
+    }
+}
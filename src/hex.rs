@@ -0,0 +1,415 @@
+use std::io::{self, stdout, Stdout};
+use std::ops::Range;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use png_viewer::crc;
+
+const BYTES_PER_LINE: usize = 16;
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// What a run of bytes in the file is, which decides its colour.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Kind {
+    Signature,
+    Length,
+    Type,
+    /// A named field of a chunk's data, numbered within the chunk so neighbours can alternate
+    /// colours.
+    Field(usize),
+    Crc { matches: bool },
+    /// Bytes that aren't a whole chunk: after IEND, or a chunk cut off by the end of the file.
+    Stray,
+}
+
+/// A run of bytes with one meaning.
+struct Region {
+    range: Range<usize>,
+    /// Index into the chunk list, None for the signature and stray bytes.
+    chunk: Option<usize>,
+    name: String,
+    kind: Kind,
+}
+
+/// A chunk as laid out in the file, found without the decoder so a broken file can be explored.
+struct Chunk {
+    chunk_type: String,
+    offset: usize,
+    length: usize,
+    stored_crc: u32,
+    computed_crc: u32,
+}
+
+struct State<'a> {
+    bytes: &'a [u8],
+    chunks: Vec<Chunk>,
+    regions: Vec<Region>,
+    cursor: usize,
+    /// First line shown.
+    scroll: usize,
+    /// Lines the hex pane had room for when last drawn, for paging and keeping the cursor in view.
+    page: usize,
+}
+
+/// Shows the file as a hex dump coloured by structure, with the chunk list alongside and the
+/// field under the cursor explained underneath, until the user quits. Tab and Shift+Tab jump
+/// between chunks.
+pub fn run(bytes: &[u8]) -> io::Result<()> {
+    let chunks = chunks(bytes);
+    let regions = regions(bytes, &chunks);
+
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+    let mut state = State {
+        bytes,
+        chunks,
+        regions,
+        cursor: 0,
+        scroll: 0,
+        page: 1,
+    };
+
+    let result = event_loop(&mut terminal, &mut state);
+
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+
+    result
+}
+
+fn event_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &mut State) -> io::Result<()> {
+    let last = state.bytes.len().saturating_sub(1);
+
+    loop {
+        terminal.draw(|frame| draw(frame, state))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let page = state.page * BYTES_PER_LINE;
+
+        state.cursor = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Left | KeyCode::Char('h') => state.cursor.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => (state.cursor + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') => state.cursor.saturating_sub(BYTES_PER_LINE),
+            KeyCode::Down | KeyCode::Char('j') => (state.cursor + BYTES_PER_LINE).min(last),
+            KeyCode::PageUp => state.cursor.saturating_sub(page),
+            KeyCode::PageDown => (state.cursor + page).min(last),
+            KeyCode::Home | KeyCode::Char('g') => 0,
+            KeyCode::End | KeyCode::Char('G') => last,
+            KeyCode::BackTab => state.previous_chunk(),
+            KeyCode::Tab | KeyCode::Char('n') => state.next_chunk(),
+            KeyCode::Char('p') | KeyCode::Char('N') => state.previous_chunk(),
+            _ => continue,
+        };
+
+        // keep the cursor's line in view
+        let line = state.cursor / BYTES_PER_LINE;
+        state.scroll = state.scroll.clamp(line.saturating_sub(state.page.saturating_sub(1)), line);
+    }
+}
+
+impl State<'_> {
+    fn region_at(&self, offset: usize) -> Option<&Region> {
+        let i = self.regions.partition_point(|region| region.range.end <= offset);
+        self.regions.get(i).filter(|region| region.range.contains(&offset))
+    }
+
+    fn chunk_at(&self, offset: usize) -> Option<usize> {
+        self.region_at(offset).and_then(|region| region.chunk)
+    }
+
+    /// The start of the chunk after the cursor's, staying put after the last.
+    fn next_chunk(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.offset).find(|&offset| offset > self.cursor).unwrap_or(self.cursor)
+    }
+
+    /// The start of the cursor's chunk, or of the one before if already there.
+    fn previous_chunk(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.offset).rev().find(|&offset| offset < self.cursor).unwrap_or(0)
+    }
+}
+
+fn draw(frame: &mut Frame, state: &mut State) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(28), Constraint::Min(80)])
+        .split(frame.size());
+
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(6)])
+        .split(columns[1]);
+
+    let current = state.chunk_at(state.cursor);
+
+    let items = state.chunks
+        .iter()
+        .map(|chunk| {
+            let style = match chunk.stored_crc == chunk.computed_crc {
+                true => Style::default(),
+                false => Style::default().fg(Color::Red),
+            };
+
+            ListItem::new(format!("{} @{:<8} {:>8}B", chunk.chunk_type, chunk.offset, chunk.length)).style(style)
+        })
+        .collect::<Vec<_>>();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Chunks (Tab/Shift+Tab)"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(list, columns[0], &mut ListState::default().with_selected(current));
+
+    let hex_block = Block::default().borders(Borders::ALL).title(format!("{} bytes (arrows, PgUp/PgDn, Home/End, q)", state.bytes.len()));
+    state.page = hex_block.inner(right[0]).height.max(1) as usize;
+
+    let lines = (state.scroll..state.bytes.len().div_ceil(BYTES_PER_LINE))
+        .take(state.page)
+        .map(|line| hex_line(state, line))
+        .collect::<Vec<_>>();
+
+    frame.render_widget(Paragraph::new(lines).block(hex_block), right[0]);
+
+    let details = Paragraph::new(describe(state)).block(Block::default().borders(Borders::ALL).title(format!("Offset {} (0x{:x})", state.cursor, state.cursor)));
+    frame.render_widget(details, right[1]);
+}
+
+fn hex_line(state: &State, line: usize) -> Line<'static> {
+    let start = line * BYTES_PER_LINE;
+    let end = (start + BYTES_PER_LINE).min(state.bytes.len());
+
+    let mut spans = vec![Span::styled(format!("{:08x}  ", start), Style::default().fg(Color::DarkGray))];
+
+    for offset in start..end {
+        let mut style = state.region_at(offset).map_or(Style::default(), |region| style(region.kind));
+
+        if offset == state.cursor {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+
+        spans.push(Span::styled(format!("{:02x}", state.bytes[offset]), style));
+        spans.push(Span::raw(if offset % 8 == 7 { "  " } else { " " }));
+    }
+
+    spans.push(Span::raw(" ".repeat((start + BYTES_PER_LINE - end) * 3 + usize::from(end < start + 8))));
+
+    let ascii = state.bytes[start..end].iter().map(|&b| if b.is_ascii_graphic() { b as char } else { '.' }).collect::<String>();
+    spans.push(Span::styled(format!("{:<16}", ascii), Style::default().fg(Color::Cyan)));
+
+    // mark where chunks begin on this line
+    for chunk in state.chunks.iter().filter(|chunk| (start..end).contains(&chunk.offset)) {
+        spans.push(Span::styled(format!("  ◀ {}", chunk.chunk_type), style(Kind::Type)));
+    }
+
+    Line::from(spans)
+}
+
+fn style(kind: Kind) -> Style {
+    match kind {
+        Kind::Signature => Style::default().fg(Color::Cyan),
+        Kind::Length => Style::default().fg(Color::Yellow),
+        Kind::Type => Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+        Kind::Field(i) if i % 2 == 0 => Style::default().fg(Color::White),
+        Kind::Field(_) => Style::default().fg(Color::LightBlue),
+        Kind::Crc { matches: true } => Style::default().fg(Color::Green),
+        Kind::Crc { matches: false } => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Kind::Stray => Style::default().fg(Color::DarkGray),
+    }
+}
+
+/// What the byte under the cursor belongs to and, for fields, their value.
+fn describe(state: &State) -> Vec<Line<'static>> {
+    let Some(region) = state.region_at(state.cursor) else {
+        return vec![Line::from("(empty file)")];
+    };
+
+    let bytes = &state.bytes[region.range.clone()];
+    let chunk = region.chunk.map(|i| &state.chunks[i]);
+
+    let heading = match chunk {
+        Some(chunk) => format!("{} chunk at {}: {}", chunk.chunk_type, chunk.offset, region.name),
+        None => region.name.clone(),
+    };
+
+    let value = match region.kind {
+        Kind::Signature => match bytes == SIGNATURE {
+            true => "PNG signature".to_string(),
+            false => "Not a PNG signature".to_string(),
+        },
+        Kind::Length => format!("{} bytes of data", chunk.map_or(0, |chunk| chunk.length)),
+        Kind::Type => type_properties(bytes),
+        Kind::Crc { matches } => {
+            let chunk = chunk.expect("a CRC belongs to a chunk");
+            format!("Stored {:08x}, computed {:08x}{}", chunk.stored_crc, chunk.computed_crc, if matches { "" } else { ": mismatch" })
+        }
+        Kind::Field(_) => field_value(bytes),
+        Kind::Stray => format!("{} bytes", bytes.len()),
+    };
+
+    vec![
+        Line::styled(heading, Style::default().fg(Color::Green)),
+        Line::styled(value, style(region.kind)),
+        Line::styled(format!("Bytes {}..{} ({} long)", region.range.start, region.range.end, bytes.len()), Style::default().fg(Color::DarkGray)),
+    ]
+}
+
+/// The meaning of the case of each letter of a chunk type.
+fn type_properties(chunk_type: &[u8]) -> String {
+    let bit = |i: usize, lower: &'static str, upper: &'static str| match chunk_type[i].is_ascii_lowercase() {
+        true => lower,
+        false => upper,
+    };
+
+    format!(
+        "{}: {}, {}, {}",
+        String::from_utf8_lossy(chunk_type),
+        bit(0, "ancillary", "critical"),
+        bit(1, "private", "public"),
+        bit(3, "safe to copy", "unsafe to copy"),
+    )
+}
+
+/// A field's bytes as a big-endian number when it's that short, otherwise as text if it reads
+/// as Latin-1, otherwise as its first bytes.
+fn field_value(bytes: &[u8]) -> String {
+    match bytes.len() {
+        1 => format!("{}", bytes[0]),
+        2 => format!("{}", u16::from_be_bytes([bytes[0], bytes[1]])),
+        4 => format!("{}", u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        _ if bytes.iter().all(|&b| b >= 0x20 && b != 0x7F) => bytes.iter().take(200).map(|&b| b as char).collect(),
+        _ => bytes.iter().take(16).map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ") + if bytes.len() > 16 { " ..." } else { "" },
+    }
+}
+
+/// Walks the chunks from after the signature, stopping at IEND or the first that doesn't fit in
+/// the file.
+fn chunks(bytes: &[u8]) -> Vec<Chunk> {
+    let mut chunks = vec![];
+    let mut offset = SIGNATURE.len();
+
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let data_end = offset + 8 + length;
+
+        let Some(stored) = bytes.get(data_end..data_end + 4) else {
+            break;
+        };
+
+        chunks.push(Chunk {
+            chunk_type: String::from_utf8_lossy(&header[4..8]).into_owned(),
+            offset,
+            length,
+            stored_crc: u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]),
+            computed_crc: crc::chunk_crc(&header[4..8], &bytes[offset + 8..data_end]),
+        });
+
+        offset = data_end + 4;
+
+        if &header[4..8] == b"IEND" {
+            break;
+        }
+    }
+
+    chunks
+}
+
+/// Splits the whole file into regions, in order.
+fn regions(bytes: &[u8], chunks: &[Chunk]) -> Vec<Region> {
+    let mut regions = vec![];
+    let signature_end = SIGNATURE.len().min(bytes.len());
+
+    if signature_end > 0 {
+        regions.push(Region { range: 0..signature_end, chunk: None, name: "Signature".to_string(), kind: Kind::Signature });
+    }
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let data_start = chunk.offset + 8;
+        let data_end = data_start + chunk.length;
+        let region = |range, name: &str, kind| Region { range, chunk: Some(i), name: name.to_string(), kind };
+
+        regions.push(region(chunk.offset..chunk.offset + 4, "Length", Kind::Length));
+        regions.push(region(chunk.offset + 4..data_start, "Type", Kind::Type));
+
+        let mut start = data_start;
+
+        for (n, (name, length)) in fields(&chunk.chunk_type, &bytes[data_start..data_end]).into_iter().enumerate() {
+            let end = start.saturating_add(length).min(data_end);
+
+            if end > start {
+                regions.push(region(start..end, &name, Kind::Field(n)));
+            }
+
+            start = end;
+        }
+
+        regions.push(region(data_end..data_end + 4, "CRC", Kind::Crc { matches: chunk.stored_crc == chunk.computed_crc }));
+    }
+
+    let end = regions.last().map_or(0, |region| region.range.end);
+
+    if end < bytes.len() {
+        let name = match chunks.last().is_some_and(|chunk| chunk.chunk_type == "IEND") {
+            true => "Data after IEND",
+            false => "Incomplete chunk",
+        };
+
+        regions.push(Region { range: end..bytes.len(), chunk: None, name: name.to_string(), kind: Kind::Stray });
+    }
+
+    regions
+}
+
+/// The named fields a chunk's data is made of, with their lengths in order. What's left over
+/// after the last becomes a field of its own.
+fn fields(chunk_type: &str, data: &[u8]) -> Vec<(String, usize)> {
+    // the keyword or name that text and profile chunks start with, up to a null separator
+    let keyword = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let named = |fields: &[(&str, usize)]| fields.iter().map(|&(name, length)| (name.to_string(), length)).collect::<Vec<_>>();
+
+    let mut fields = match chunk_type {
+        "IHDR" => named(&[("Width", 4), ("Height", 4), ("Bit depth", 1), ("Colour type", 1), ("Compression method", 1), ("Filter method", 1), ("Interlace method", 1)]),
+        "PLTE" => (0..data.len() / 3).map(|i| (format!("Palette entry {} (RGB)", i), 3)).collect(),
+        "gAMA" => named(&[("Gamma, times 100000", 4)]),
+        "cHRM" => named(&[
+            ("White point x, times 100000", 4), ("White point y, times 100000", 4),
+            ("Red x, times 100000", 4), ("Red y, times 100000", 4),
+            ("Green x, times 100000", 4), ("Green y, times 100000", 4),
+            ("Blue x, times 100000", 4), ("Blue y, times 100000", 4),
+        ]),
+        "sRGB" => named(&[("Rendering intent", 1)]),
+        "cICP" => named(&[("Colour primaries", 1), ("Transfer function", 1), ("Matrix coefficients", 1), ("Full range", 1)]),
+        "pHYs" => named(&[("Pixels per unit, x", 4), ("Pixels per unit, y", 4), ("Unit (1 is the metre)", 1)]),
+        "tIME" => named(&[("Year", 2), ("Month", 1), ("Day", 1), ("Hour", 1), ("Minute", 1), ("Second", 1)]),
+        "acTL" => named(&[("Number of frames", 4), ("Number of plays", 4)]),
+        "fcTL" => named(&[
+            ("Sequence number", 4), ("Width", 4), ("Height", 4), ("X offset", 4), ("Y offset", 4),
+            ("Delay numerator", 2), ("Delay denominator", 2), ("Dispose op", 1), ("Blend op", 1),
+        ]),
+        "fdAT" => named(&[("Sequence number", 4), ("Frame data (zlib)", usize::MAX)]),
+        "IDAT" => named(&[("Image data (zlib)", usize::MAX)]),
+        "tEXt" => named(&[("Keyword", keyword), ("Separator", 1), ("Text", usize::MAX)]),
+        "zTXt" => named(&[("Keyword", keyword), ("Separator", 1), ("Compression method", 1), ("Compressed text (zlib)", usize::MAX)]),
+        "iCCP" => named(&[("Profile name", keyword), ("Separator", 1), ("Compression method", 1), ("Compressed profile (zlib)", usize::MAX)]),
+        _ => vec![],
+    };
+
+    let covered = fields.iter().fold(0usize, |total, (_, length)| total.saturating_add(*length));
+
+    if covered < data.len() {
+        fields.push(("Data".to_string(), data.len() - covered));
+    }
+
+    fields
+}
@@ -0,0 +1,53 @@
+use image::{DynamicImage, ImageBuffer, RgbaImage};
+use crate::png_reader::{OutputFormat, Pixel, PngReader};
+use crate::png_writer::PngWriter;
+
+/// Decodes the image, reading its chunks first if that hasn't been done, into the closest
+/// `DynamicImage`: 16-bit images keep their precision as RGBA, and 8-bit greyscale and
+/// truecolour images without alpha stay as they are.
+impl TryFrom<PngReader> for DynamicImage {
+    type Error = String;
+
+    fn try_from(mut reader: PngReader) -> Result<Self, Self::Error> {
+        if reader.chunks().is_empty() {
+            reader.read_chunks()?;
+        }
+
+        let info = reader.info();
+        let format = match (info.bit_depth, info.colour_type) {
+            (16, _) => OutputFormat::Rgba16,
+            (_, 0) => OutputFormat::Gray8,
+            (_, 2) => OutputFormat::Rgb8,
+            _ => OutputFormat::Rgba8,
+        };
+
+        let decoded = reader.decode(format)?;
+        let (width, height) = (decoded.width, decoded.height);
+        let wrong_size = || "The decoded image isn't the size of its header".to_string();
+
+        let image = match format {
+            OutputFormat::Gray8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, decoded.data).ok_or_else(wrong_size)?),
+            OutputFormat::Rgb8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, decoded.data).ok_or_else(wrong_size)?),
+            OutputFormat::Rgba16 => {
+                let samples = decoded.data.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect();
+                DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, samples).ok_or_else(wrong_size)?)
+            }
+            _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, decoded.data).ok_or_else(wrong_size)?),
+        };
+
+        Ok(image)
+    }
+}
+
+/// Converts the image to 8-bit RGBA for encoding, so 16-bit images lose precision.
+impl From<&DynamicImage> for PngWriter {
+    fn from(image: &DynamicImage) -> Self {
+        let rgba: RgbaImage = image.to_rgba8();
+        let pixel_data = rgba
+            .rows()
+            .map(|row| row.map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] }).collect())
+            .collect();
+
+        PngWriter::new(rgba.width(), rgba.height(), pixel_data)
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use winit::event::VirtualKeyCode;
+
+/// Something the viewer can do in response to a key press.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    SaveSelection,
+    CopySelection,
+    ClearSelection,
+    ToggleMetadata,
+    ToggleFilters,
+    Blink,
+    Difference,
+    AmplifyDifference,
+    AttenuateDifference,
+    NextImage,
+    PreviousImage,
+    FirstImage,
+    LastImage,
+    ToggleSlideshow,
+    ActualSize,
+    ZoomToFit,
+    Zoom200,
+    Zoom300,
+    TogglePremultipliedAlpha,
+    IncreaseExposure,
+    DecreaseExposure,
+    IncreaseGamma,
+    DecreaseGamma,
+    ResetTone,
+    ToggleRulers,
+    Loupe,
+    CycleSimulation,
+    ToggleInvert,
+    CycleAlphaView,
+    SwapRedBlue,
+    ToggleLinear,
+    CycleDither,
+    ToggleClipping,
+    ToggleViewLock,
+    Paste,
+    SavePasted,
+    Bookmark,
+}
+
+/// A key, pressed with Ctrl or without.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Shortcut {
+    pub ctrl: bool,
+    pub key: VirtualKeyCode,
+}
+
+const fn plain(key: VirtualKeyCode) -> Shortcut {
+    Shortcut { ctrl: false, key }
+}
+
+const fn ctrl(key: VirtualKeyCode) -> Shortcut {
+    Shortcut { ctrl: true, key }
+}
+
+/// Every action with its name in the `[keys]` table of the config file and its default keys.
+const ACTIONS: &[(Action, &str, &[Shortcut])] = &[
+    (Action::SaveSelection, "save_selection", &[plain(VirtualKeyCode::Return)]),
+    (Action::CopySelection, "copy_selection", &[plain(VirtualKeyCode::C)]),
+    (Action::ClearSelection, "clear_selection", &[plain(VirtualKeyCode::Escape)]),
+    (Action::ToggleMetadata, "toggle_metadata", &[plain(VirtualKeyCode::I)]),
+    (Action::ToggleFilters, "toggle_filters", &[plain(VirtualKeyCode::S)]),
+    (Action::Blink, "blink", &[plain(VirtualKeyCode::Tab)]),
+    (Action::Difference, "difference", &[plain(VirtualKeyCode::D)]),
+    (Action::AmplifyDifference, "amplify_difference", &[plain(VirtualKeyCode::Equals), plain(VirtualKeyCode::Plus)]),
+    (Action::AttenuateDifference, "attenuate_difference", &[plain(VirtualKeyCode::Minus)]),
+    (Action::NextImage, "next_image", &[plain(VirtualKeyCode::Right), plain(VirtualKeyCode::PageDown)]),
+    (Action::PreviousImage, "previous_image", &[plain(VirtualKeyCode::Left), plain(VirtualKeyCode::PageUp)]),
+    (Action::FirstImage, "first_image", &[plain(VirtualKeyCode::Home)]),
+    (Action::LastImage, "last_image", &[plain(VirtualKeyCode::End)]),
+    (Action::ToggleSlideshow, "toggle_slideshow", &[plain(VirtualKeyCode::Space)]),
+    (Action::ActualSize, "actual_size", &[plain(VirtualKeyCode::Key1)]),
+    (Action::ZoomToFit, "zoom_to_fit", &[plain(VirtualKeyCode::Key0), plain(VirtualKeyCode::F)]),
+    (Action::Zoom200, "zoom_200", &[plain(VirtualKeyCode::Key2)]),
+    (Action::Zoom300, "zoom_300", &[plain(VirtualKeyCode::Key3)]),
+    (Action::TogglePremultipliedAlpha, "toggle_premultiplied_alpha", &[plain(VirtualKeyCode::A)]),
+    (Action::IncreaseExposure, "increase_exposure", &[plain(VirtualKeyCode::RBracket)]),
+    (Action::DecreaseExposure, "decrease_exposure", &[plain(VirtualKeyCode::LBracket)]),
+    (Action::IncreaseGamma, "increase_gamma", &[plain(VirtualKeyCode::Period)]),
+    (Action::DecreaseGamma, "decrease_gamma", &[plain(VirtualKeyCode::Comma)]),
+    (Action::ResetTone, "reset_tone", &[plain(VirtualKeyCode::Backslash)]),
+    (Action::ToggleRulers, "toggle_rulers", &[plain(VirtualKeyCode::R)]),
+    (Action::Loupe, "loupe", &[plain(VirtualKeyCode::M)]),
+    (Action::CycleSimulation, "cycle_simulation", &[plain(VirtualKeyCode::V)]),
+    (Action::ToggleInvert, "toggle_invert", &[plain(VirtualKeyCode::N)]),
+    (Action::CycleAlphaView, "cycle_alpha_view", &[plain(VirtualKeyCode::T)]),
+    (Action::SwapRedBlue, "swap_red_blue", &[plain(VirtualKeyCode::B)]),
+    (Action::ToggleLinear, "toggle_linear", &[plain(VirtualKeyCode::L)]),
+    (Action::CycleDither, "cycle_dither", &[plain(VirtualKeyCode::G)]),
+    (Action::ToggleClipping, "toggle_clipping", &[plain(VirtualKeyCode::Z)]),
+    (Action::ToggleViewLock, "toggle_view_lock", &[plain(VirtualKeyCode::K)]),
+    (Action::Paste, "paste", &[ctrl(VirtualKeyCode::V)]),
+    (Action::SavePasted, "save_pasted", &[ctrl(VirtualKeyCode::S)]),
+    (Action::Bookmark, "bookmark", &[ctrl(VirtualKeyCode::B)]),
+];
+
+pub struct KeyBindings {
+    actions: HashMap<Shortcut, Action>,
+}
+
+impl KeyBindings {
+    /// Starts from the default bindings and replaces the keys of each action named in
+    /// `overrides` (action name -> key name, or several key names separated by spaces, each
+    /// optionally prefixed with `Ctrl+`).
+    pub fn new(overrides: &HashMap<String, String>) -> Result<Self, String> {
+        let mut bindings = ACTIONS
+            .iter()
+            .map(|(action, _, keys)| (*action, keys.to_vec()))
+            .collect::<Vec<_>>();
+
+        for (name, keys) in overrides {
+            let index = ACTIONS
+                .iter()
+                .position(|(_, action_name, _)| action_name == name)
+                .ok_or_else(|| format!("Unknown action: {}", name))?;
+
+            bindings[index].1 = keys
+                .split_whitespace()
+                .map(|key| parse_shortcut(key).ok_or_else(|| format!("Unknown key: {}", key)))
+                .collect::<Result<_, _>>()?;
+        }
+
+        let actions = bindings
+            .into_iter()
+            .flat_map(|(action, keys)| keys.into_iter().map(move |key| (key, action)))
+            .collect();
+
+        Ok(Self { actions })
+    }
+
+    /// The action bound to `key`, pressed with Ctrl or without. A key with nothing bound to it
+    /// with Ctrl does what it does alone.
+    pub fn action(&self, key: VirtualKeyCode, ctrl: bool) -> Option<Action> {
+        ctrl.then(|| self.actions.get(&Shortcut { ctrl: true, key }))
+            .flatten()
+            .or_else(|| self.actions.get(&plain(key)))
+            .copied()
+    }
+}
+
+/// Parses a key name as `parse_key` does, pressed with Ctrl if it's prefixed with `Ctrl+`.
+fn parse_shortcut(name: &str) -> Option<Shortcut> {
+    match name.strip_prefix("Ctrl+") {
+        Some(key) => parse_key(key).map(ctrl),
+        None => parse_key(name).map(plain),
+    }
+}
+
+/// Parses a key name as written in the config file: a letter, a digit, one of `+-=[],.\`,
+/// F1-F12 or a named key.
+pub fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+
+    const LETTERS: [VirtualKeyCode; 26] = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    const DIGITS: [VirtualKeyCode; 10] = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const FUNCTION_KEYS: [VirtualKeyCode; 12] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+
+    let mut chars = name.chars();
+
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let c = c.to_ascii_uppercase();
+
+        return match c {
+            'A'..='Z' => Some(LETTERS[(c as u8 - b'A') as usize]),
+            '0'..='9' => Some(DIGITS[(c as u8 - b'0') as usize]),
+            '+' => Some(Plus),
+            '-' => Some(Minus),
+            '=' => Some(Equals),
+            '[' => Some(LBracket),
+            ']' => Some(RBracket),
+            ',' => Some(Comma),
+            '.' => Some(Period),
+            '\\' => Some(Backslash),
+            _ => None,
+        };
+    }
+
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<usize>().ok()) {
+        return FUNCTION_KEYS.get(n.wrapping_sub(1)).copied();
+    }
+
+    match name {
+        "Return" | "Enter" => Some(Return),
+        "Escape" | "Esc" => Some(Escape),
+        "Tab" => Some(Tab),
+        "Space" => Some(Space),
+        "Backspace" => Some(Back),
+        "Delete" => Some(Delete),
+        "Insert" => Some(Insert),
+        "Home" => Some(Home),
+        "End" => Some(End),
+        "PageUp" => Some(PageUp),
+        "PageDown" => Some(PageDown),
+        "Left" => Some(Left),
+        "Right" => Some(Right),
+        "Up" => Some(Up),
+        "Down" => Some(Down),
+        _ => None,
+    }
+}
@@ -0,0 +1,13 @@
+//! The PNG decoder and encoder behind the viewer, for use on their own.
+
+pub mod blit;
+pub mod crc;
+pub mod deflate;
+#[cfg(feature = "image")]
+mod image_interop;
+#[cfg(feature = "async")]
+pub mod png_async;
+pub mod png_reader;
+pub mod png_writer;
+#[cfg(feature = "wgpu")]
+mod texture;
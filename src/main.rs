@@ -1,343 +1,1762 @@
-use std::env;
-use std::fs::File;
-use std::io::{BufReader, Read};
-use std::ops::Range;
+mod apng;
+mod bookmarks;
+mod check;
+mod colour;
+mod config;
+mod error_dialog;
+mod export;
+mod font;
+#[cfg(target_os = "linux")]
+mod framebuffer;
+mod gallery;
+mod geometry;
+mod gif_file;
+mod gpu;
+mod hex;
+mod keys;
+#[cfg(feature = "heap-stats")]
+mod mem_stats;
+mod metadata;
+mod montage;
+mod options;
+mod recent;
+mod recompress;
+mod renderer;
+mod report;
+mod resize;
+mod selftest;
+mod transform;
+mod tui;
+mod video;
+mod view_lock;
+mod viewer;
+#[cfg(target_os = "linux")]
+mod wallpaper;
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use colored::Colorize;
-use flate2::read::{ZlibDecoder};
-use pixels::{Pixels, SurfaceTexture};
-use winit::dpi::LogicalSize;
-use winit::event::Event;
-use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy};
+use winit::window::{Icon, WindowBuilder, WindowLevel};
+use png_viewer::{deflate, png_reader, png_writer};
+use keys::Action;
+use geometry::WindowGeometry;
+use options::{Options, RawFormat, Scale};
+use png_reader::{CancelToken, Pixel, PngReader};
+use png_writer::PngWriter;
+use renderer::Renderer;
+use viewer::{AlphaView, CompareMode, MenuItem, Rect, Simulation, Viewer};
+use view_lock::ViewLock;
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(400);
+/// How often a window with its view locked checks for other windows' pan and zoom.
+const VIEW_LOCK_INTERVAL: Duration = Duration::from_millis(100);
+/// How often `--watch` checks the image's file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the stripes marking clipped pixels flash.
+const CLIPPING_INTERVAL: Duration = Duration::from_millis(500);
+/// Fraction of the monitor a `--scale fit` window may take up.
+const FIT_MARGIN: f64 = 0.9;
+/// Side of the square window icon made from the image.
+const ICON_SIZE: u32 = 64;
+
+/// Events sent to the window from other threads, tagged with the playlist position of the image
+/// they belong to so a decode the user has moved away from can be ignored.
+enum UserEvent {
+    /// A newer, possibly partial, decode of the image.
+    PixelData(usize, Vec<Vec<Pixel>>),
+    /// Decoding finished: the per-pixel filter map and filter usage statistics.
+    FilterTypes(usize, Vec<Vec<u8>>, Vec<String>),
+    /// The image couldn't be read or decoded, and why.
+    LoadFailed(usize, String),
+}
+
+#[cfg(feature = "heap-stats")]
+#[global_allocator]
+static ALLOCATOR: mem_stats::CountingAllocator = mem_stats::CountingAllocator;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut options = Options::parse();
+    init_logging(&options);
 
-    let image_path = args.get(1).expect("No image file specified");
+    if options.selftest {
+        std::process::exit(if selftest::run() { 0 } else { 1 });
+    }
 
-    let buf = BufReader::new(File::open(image_path).expect("Failed to open file"));
+    if options.assemble {
+        let output = options.out.as_deref().expect("Options::parse requires --out");
 
-    let bytes = buf.bytes().flat_map(|b| b).collect::<Vec<u8>>();
+        if !assemble(&options.paths, output, &options) {
+            std::process::exit(1);
+        }
 
-    let mut reader = PngReader::new(bytes);
+        return;
+    }
 
-    reader.read();
+    if options.montage {
+        let output = options.out.as_deref().expect("Options::parse requires --out");
 
-    init_window(reader.width, reader.height, reader.pixel_data.clone());
-}
+        if let Err(e) = montage::run(&options.paths, options.columns, options.tile, &options, output) {
+            eprintln!("{} {}", "Failed to make the montage".red(), e);
+            std::process::exit(1);
+        }
 
-fn init_window(width: u32, height: u32, pixel_data: Vec<Vec<Pixel>>) {
-    let event_loop = EventLoop::new();
+        return;
+    }
 
-    let window = {
-        let size = LogicalSize::new(width, height);
-        WindowBuilder::new()
-            .with_title("png-viewer")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
-    };
+    if options.convert {
+        let (Some(input), Some(output)) = (options.paths.first(), options.paths.get(1)) else {
+            panic!("convert needs an input and an output file");
+        };
 
-    let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(width, height, surface_texture).unwrap()
-    };
+        let to_gif = Path::new(output).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
 
-    event_loop.run(move |event, _, _| {
-        if let Event::RedrawRequested(_) = event {
-            println!("RedrawRequested");
-
-            for h in 0..height as usize {
-                for w in 0..width as usize {
-                    let idx = h * width as usize * 4 + w * 4;
-                    pixels.frame_mut()[idx] = pixel_data[h][w].r;
-                    pixels.frame_mut()[idx + 1] = pixel_data[h][w].g;
-                    pixels.frame_mut()[idx + 2] = pixel_data[h][w].b;
-                    pixels.frame_mut()[idx + 3] = pixel_data[h][w].a;
+        if !convert(input, Path::new(output), to_gif, &options) {
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    if options.check {
+        let results = options.paths
+            .iter()
+            .map(|path| match std::fs::read(path) {
+                Ok(bytes) => check::check(path, &bytes),
+                Err(e) => {
+                    eprintln!("{} {}: {}", "Failed to open".red(), path, e);
+                    false
                 }
+            })
+            .collect::<Vec<_>>();
+
+        std::process::exit(if results.iter().all(|&ok| ok) { 0 } else { 1 });
+    }
+
+    if options.hex {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+
+        match fs::read(image_path) {
+            Ok(bytes) => hex::run(&bytes).expect("Failed to run the terminal interface"),
+            Err(e) => {
+                eprintln!("{} {}: {}", "Failed to open".red(), image_path, e);
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    if options.info {
+        for image_path in &options.paths {
+            println!("{}", image_path.on_blue().white());
+
+            // reading dumps the chunks, and the summary follows
+            for line in read_png(image_path, &options).summary() {
+                println!("{}", line);
             }
 
-            pixels.render();
+            println!();
         }
-    })
-}
 
-#[derive(Default, Copy, Clone)]
-struct Pixel {
-    r: u8,
-    g: u8,
-    b: u8,
-    a: u8,
-}
+        return;
+    }
+
+    if options.recent {
+        match recent::pick() {
+            Some(path) => options.paths.insert(0, path.to_string_lossy().into_owned()),
+            None => return,
+        }
+    }
 
-struct PngReader {
-    bytes: Vec<u8>,
+    if options.bookmarks {
+        match bookmarks::pick() {
+            Some(path) => options.paths.insert(0, path.to_string_lossy().into_owned()),
+            None => return,
+        }
+    }
 
-    pub width: u32,
-    pub height: u32,
-    bit_depth: u8,
-    colour_type: u8,
-    compression_method: u8,
-    filter_method: u8,
-    interlace_method: u8,
+    if options.recursive && !options.paths.is_empty() {
+        options.paths = gallery::expand(&options.paths);
 
-    image_data: Vec<u8>,
-    pub pixel_data: Vec<Vec<Pixel>>,
-}
+        if options.paths.is_empty() {
+            eprintln!("{}", "No images found".red());
+            std::process::exit(1);
+        }
+    }
+
+    gallery::sort(&mut options.paths, options.sort, options.reverse);
+
+    if options.tui {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+
+        let mut reader = read_png(image_path, &options);
+        decode_or_exit(&mut reader, image_path);
+
+        let pixel_data = transform::orient(&reader.pixel_data, orientation(&reader, &options));
+
+        tui::run(&reader, &pixel_data).expect("Failed to run the terminal interface");
+        return;
+    }
+
+    if options.analyze_deflate {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+        analyze_deflate(&read_png(image_path, &options));
+        return;
+    }
+
+    if let Some(dir) = &options.extract_frames {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+        extract_frames(&read_png(image_path, &options), dir);
+        return;
+    }
+
+    if let Some(output) = &options.to_gif {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+
+        if !convert(image_path, output, true, &options) {
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    if let Some(output) = &options.to_video {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-impl PngReader {
-    pub fn new(bytes: Vec<u8>) -> Self {
-        Self {
-            bytes,
-            width: 0,
-            height: 0,
-            bit_depth: 0,
-            colour_type: 0,
-            compression_method: 0,
-            filter_method: 0,
-            interlace_method: 0,
-            image_data: vec![],
-            pixel_data: vec![],
+        let result = load_animation(image_path, &options).and_then(|animation| {
+            video::write(&animation, options.background, output)?;
+            Ok(animation.frames.len())
+        });
+
+        match result {
+            Ok(frame_count) => println!("{} {} frames -> {}", "Encoded".green(), frame_count, output.display()),
+            Err(e) => {
+                eprintln!("{} {}", "Failed to write the video:".red(), e);
+                std::process::exit(1);
+            }
         }
+
+        return;
     }
 
-    pub fn read(&mut self) {
-        let mut idx = 0;
+    if options.edits_metadata() {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+
+        if let Err(e) = metadata::rewrite(image_path, &read_png(image_path, &options), &options) {
+            eprintln!("{} {}", "Failed to rewrite the metadata".red(), e);
+            std::process::exit(1);
+        }
+
+        return;
+    }
 
-        idx = self.read_signature(idx).expect("Invalid data");
+    if options.recompress {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-        while idx < self.bytes.len() {
-            idx = self.read_chunk(idx).expect("Invalid data");
+        if let Err(e) = recompress::run(image_path, &read_png(image_path, &options), options.zopfli, options.write) {
+            eprintln!("{} {}", "Failed to recompress".red(), e);
+            std::process::exit(1);
         }
 
-        self.decode_image_data();
+        return;
     }
 
-    fn read_signature(&self, idx: usize) -> Result<usize, ()> {
-        let sig = &[137, 80, 78, 71, 13, 10, 26, 10];
+    if let Some(output) = &options.report {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-        if self.bytes[0..sig.len()] != *sig {
-            return Err(());
+        if let Err(e) = report::run(image_path, &options, output) {
+            eprintln!("{} {}", "Failed to write the report".red(), e);
+            std::process::exit(1);
         }
 
-        Self::print("Signature", &self.bytes[0..sig.len()]);
+        return;
+    }
 
-        Ok(sig.len())
+    if let Some(output) = &options.export {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+
+        if let Err(e) = export::run(read_png(image_path, &options), &options, output) {
+            eprintln!("{} {}", "Failed to export".red(), e);
+            std::process::exit(1);
+        }
+
+        return;
     }
 
-    fn read_chunk(&mut self, idx: usize) -> Result<usize, ()> {
-        let start_idx = idx;
-        let mut idx = idx;
+    if let Some(output) = &options.extract_alpha {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-        // length
-        let data_len = usize::from_be_bytes([
-            0, 0, 0, 0,
-            self.bytes[idx],
-            self.bytes[idx + 1],
-            self.bytes[idx + 2],
-            self.bytes[idx + 3],
-        ]);
-        idx += 4;
+        if let Err(e) = export::extract_alpha(read_png(image_path, &options), &options, output) {
+            eprintln!("{} {}", "Failed to extract the alpha channel".red(), e);
+            std::process::exit(1);
+        }
 
-        // chunk type
-        let chunk_type = std::str::from_utf8(&self.bytes[idx..idx + 4]).unwrap();
-        idx += 4;
+        return;
+    }
 
-        // chunk data
-        let data_range = idx..idx + data_len;
-        let data = &self.bytes[data_range.clone()];
-        idx += data_len;
+    if options.extract_idat.is_some() || options.extract_raw.is_some() {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-        // crc
-        idx += 4;
+        if let Err(e) = export::extract_idat(&read_png(image_path, &options), options.extract_idat.as_deref(), options.extract_raw.as_deref()) {
+            eprintln!("{} {}", "Failed to extract the image data".red(), e);
+            std::process::exit(1);
+        }
 
-        Self::print(chunk_type, data);
+        return;
+    }
 
-        match chunk_type {
-            "IHDR" => self.read_chunk_ihdr(&data_range),
-            "IDAT" => self.read_chunk_idat(&data_range),
-            "tEXt" => Self::read_chunk_text(data),
-            "tIME" => Self::read_chunk_time(data),
-            _ => ()
-        };
+    if let Some(size) = options.slice {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+        let dir = options.out.as_deref().expect("clap requires --out with --slice");
 
-        println!();
-
-        Ok(idx)
-    }
-
-    fn read_chunk_ihdr(&mut self, data_range: &Range<usize>) {
-        let data = &self.bytes[data_range.clone()];
-        self.width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-        self.height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
-        self.bit_depth = data[8];
-        self.colour_type = data[9];
-        self.compression_method = data[10];
-        self.filter_method = data[11];
-        self.interlace_method = data[12];
-
-        Self::print_content(
-            "Image header",
-            format!(r#"[Size] {}x{}
-[Bit depth] {}
-[Colour type] {}
-[Compression method] {}
-[Filter method] {}
-[Interlace method] {}"#,
-                    self.width, self.height, self.bit_depth, self.colour_type, self.compression_method, self.filter_method, self.interlace_method),
-        );
+        if let Err(e) = export::slice(read_png(image_path, &options), size, options.skip_transparent, &options, dir) {
+            eprintln!("{} {}", "Failed to slice".red(), e);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    if let Some(format) = options.raw_out {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
+        write_raw(image_path, format, &options);
+        return;
     }
 
-    fn read_chunk_idat(&mut self, data_range: &Range<usize>) {
-        let data = &self.bytes[data_range.clone()];
-        self.image_data.append(&mut data.to_vec());
+    if options.wallpaper {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-        /*
-        let mut decompressed_data = Vec::<u8>::new();
-        let data_len = ZlibDecoder::new(data).read_to_end(&mut decompressed_data).unwrap();
-        */
+        let mut viewer = Viewer::new(1, 1, 1.0);
+        configure_viewer(&mut viewer, &options);
+        if let Err(e) = show_decoded(&mut viewer, read_png(image_path, &options), &options) {
+            decode_failed(image_path, &e);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Err(e) = wallpaper::run(viewer) {
+            eprintln!("{} {}", "Failed to set the wallpaper".red(), e);
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        eprintln!("{}", "--wallpaper needs a Wayland compositor".red());
 
-        Self::print_content("Image data", format!("{} bytes", data.len()));
+        return;
     }
 
-    fn read_chunk_text(data: &[u8]) {
-        let mut separator_idx: usize = 0;
+    if let Some(output) = &options.render {
+        let image_path = options.paths.first().expect("Options::parse requires an image");
 
-        for i in 0..data.len() {
-            if data[i] == 0 {
-                separator_idx = i;
-                break;
-            }
+        let compare = options.compare.then(|| {
+            let compare_path = options.paths.get(1).expect("diff takes two images");
+            let mut compare = read_png(compare_path, &options);
+            decode_or_exit(&mut compare, compare_path);
+            compare
+        });
+
+        render_to_file(image_path, compare, &options, output);
+        return;
+    }
+
+    if options.compare {
+        let a = options.paths.first().expect("diff takes two images");
+        let b = options.paths.get(1).expect("diff takes two images");
+
+        let compare = open_png(b, &options)
+            .and_then(|mut compare| compare.decode_image_data().map(|()| compare))
+            .unwrap_or_else(|e| {
+                error_dialog::show(b, &e);
+                std::process::exit(1);
+            });
+
+        init_window(vec![a.clone()], Some(compare), options);
+        return;
+    }
+
+    #[cfg(target_os = "linux")]
+    if framebuffer::available() {
+        if options.paths.is_empty() {
+            eprintln!("{}", "No image file specified".red());
+            std::process::exit(1);
+        }
+
+        if let Err(e) = framebuffer::run(&options.paths, &options) {
+            eprintln!("{} {}", "Failed to show the image on the framebuffer".red(), e);
         }
 
-        let keyword = std::str::from_utf8(&data[0..separator_idx]).unwrap();
-        let text = std::str::from_utf8(&data[separator_idx + 1..data.len()]).unwrap();
+        return;
+    }
+
+    if options.paths.is_empty() {
+        match pick_image() {
+            Some(path) => options.paths.push(path),
+            None => return,
+        }
+    }
+
+    init_window(options.paths.clone(), None, options);
+}
+
+/// Asks for an image with the platform's file dialog, for when none was given on the command line.
+fn pick_image() -> Option<String> {
+    rfd::FileDialog::new()
+        .set_title("Open image")
+        .add_filter("PNG images", &["png", "apng"])
+        .pick_file()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Sends the chunk dump and status messages, as plain lines, to stdout, or to stderr when stdout
+/// carries `--raw-out`'s pixels. `RUST_LOG` filters them, `info` when unset; at `debug` each
+/// decode, inflate, unfilter and render also reports how long it took.
+fn init_logging(options: &Options) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (writer, ansi) = match options.raw_out {
+        Some(_) => (BoxMakeWriter::new(io::stderr), io::stderr().is_terminal()),
+        None => (BoxMakeWriter::new(io::stdout), io::stdout().is_terminal()),
+    };
+
+    // the event format leaves out the time of day, but spans still time themselves
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_span_events(FmtSpan::CLOSE)
+        .event_format(tracing_subscriber::fmt::format().without_time().with_level(false).with_target(false))
+        .init();
+}
+
+/// Reads the file and parses its chunks, leaving the image data to be decoded, or exits with
+/// why it can't be read.
+fn read_png(image_path: &str, options: &Options) -> PngReader {
+    open_png(image_path, options).unwrap_or_else(|e| {
+        eprintln!("{} {}: {}", "Failed to read".red(), image_path, e);
+        std::process::exit(1);
+    })
+}
+
+/// Reads the file and parses its chunks, leaving the image data to be decoded. Fails with the
+/// error opening the file or, saying where, the chunk that can't be read.
+fn open_png(image_path: &str, options: &Options) -> Result<PngReader, String> {
+    let start = Instant::now();
+    let buf = BufReader::new(File::open(image_path).map_err(|e| e.to_string())?);
+
+    let bytes = buf.bytes().flatten().collect::<Vec<u8>>();
+
+    if options.timings {
+        print_timing("Read file", start.elapsed());
+    }
+
+    let mut reader = PngReader::new(bytes);
+    reader.set_verbose(options.dump_chunks());
+    reader.set_dither(options.dither);
+
+    reader.read_chunks()?;
 
-        Self::print_content("Textual data", format!("[keyword] {}\n[text] {}", keyword, text));
+    if options.timings {
+        print_timing("Parse chunks", reader.timings().parse);
     }
 
-    fn read_chunk_time(data: &[u8]) {
-        let year = u16::from_be_bytes([data[0], data[1]]);
-        let month = data[2];
-        let day = data[3];
-        let hour = data[4];
-        let minutes = data[5];
-        let second = data[6];
+    Ok(reader)
+}
+
+/// The file's modification time and size, to tell when it's been rewritten or replaced. None if
+/// it can't be read, e.g. between being deleted and replaced.
+fn file_stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Whether the file ends with IEND, so a file still being written isn't read half finished.
+fn is_complete_png(path: &str) -> bool {
+    fs::read(path).is_ok_and(|bytes| bytes.starts_with(&[137, 80, 78, 71, 13, 10, 26, 10]) && bytes.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]))
+}
 
-        Self::print_content("Image last-modification time", format!("{}/{}/{} {:<02}:{:<02}:{:<02}", year, month, day, hour, minutes, second));
+/// The EXIF orientation to display the image with, 1 (as stored) unless enabled and present.
+fn orientation(reader: &PngReader, options: &Options) -> u16 {
+    match options.exif_orientation {
+        true => reader.info().exif_orientation.unwrap_or(1),
+        false => 1,
     }
+}
+
+/// Opens the viewer on the first of `image_paths`; the others can be stepped through or shown
+/// as a slideshow. `compare` is an already decoded image to show alongside.
+fn init_window(image_paths: Vec<String>, compare: Option<PngReader>, mut options: Options) {
+    // there's no window to show anything in yet, so no size to give it
+    let reader = open_png(&image_paths[0], &options).unwrap_or_else(|e| {
+        error_dialog::show(&image_paths[0], &e);
+        std::process::exit(1);
+    });
+
+    let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
+    let proxy = event_loop.create_proxy();
+    let (width, height) = transform::oriented_size(reader.info().width, reader.info().height, orientation(&reader, &options));
+
+    let (width, height) = match &compare {
+        Some(compare) => {
+            let (compare_width, compare_height) = transform::oriented_size(compare.info().width, compare.info().height, orientation(compare, &options));
+            (width + compare_width, height.max(compare_height))
+        }
+        None => (width, height),
+    };
+
+    let zoom = options.zoom * match options.scale.unwrap_or(Scale::Factor(1.0)) {
+        Scale::Factor(factor) => factor,
+        Scale::Fit => fit_scale(&event_loop, width as f64 * options.zoom, height as f64 * options.zoom),
+    };
+
+    let window = {
+        let size = LogicalSize::new(width as f64 * zoom, height as f64 * zoom);
+        let mut inner_size = size;
+        let mut builder = WindowBuilder::new()
+            .with_title("png-viewer")
+            .with_min_inner_size(size)
+            .with_decorations(!options.borderless)
+            .with_transparent(options.transparent)
+            .with_active(!options.click_through);
+
+        // asking for a size or place takes precedence over where the window was last time
+        let restore = options.scale.is_none() && options.position.is_none() && options.monitor.is_none();
+
+        if let Some(geometry) = WindowGeometry::load().filter(|_| restore) {
+            inner_size = geometry.size();
+
+            if let Some(position) = geometry.position(&event_loop) {
+                builder = builder.with_position(position);
+            }
+        }
+
+        if let Some(position) = placement(&event_loop, &options, inner_size) {
+            builder = builder.with_position(position);
+        }
+
+        if options.always_on_top {
+            builder = builder.with_window_level(WindowLevel::AlwaysOnTop);
+        }
+
+        builder = builder.with_inner_size(inner_size);
+
+        Rc::new(builder.build(&event_loop).unwrap())
+    };
 
-    fn print(title: &str, data: &[u8]) {
-        println!("{}\n{:<02x?}\n", title.on_blue().white(), data.iter().take(30).collect::<Vec<_>>());
+    if options.click_through {
+        if let Err(e) = window.set_cursor_hittest(false) {
+            eprintln!("{} {}", "Failed to pass clicks through the window:".red(), e);
+        }
     }
 
-    fn print_content(title: &str, content: String) {
-        println!("{}\n{}\n", title.green(), content);
+    let window_size = window.inner_size();
+
+    let mut renderer = Renderer::new(&window, options.transparent);
+
+    let mut viewer = Viewer::new(window_size.width, window_size.height, window.scale_factor());
+    configure_viewer(&mut viewer, &options);
+    viewer.zoom_by(zoom);
+
+    if options.transparent {
+        viewer.set_background([0; 4]);
+    }
+
+    // stops the background decode of an interlaced image on moving to the next
+    let mut decoding = CancelToken::new();
+    let mut playback = load_image(&mut viewer, &image_paths[0], reader, 0, &options, &proxy, &mut decoding);
+    update_window_icon(&window, &viewer);
+
+    if let Some(compare) = compare {
+        viewer.set_compare(transform::orient(&compare.pixel_data, orientation(&compare, &options)));
+        viewer.set_compare_profile(colour::image_profile(&compare));
     }
 
-    fn decode_image_data(&mut self) {
-        let mut data = Vec::<u8>::new();
-        let data_len = ZlibDecoder::new(self.image_data.as_slice()).read_to_end(&mut data).unwrap();
+    let mut index = 0;
+    viewer.set_position(index, image_paths.len());
+
+    let mut next_blink = Instant::now();
+    let mut next_flash = Instant::now();
+    // whether the first render is still to be timed
+    let mut first_render = options.timings;
+    let mut view_lock = options.lock_views.then(ViewLock::new);
+    let mut next_slide = None;
+    // the watched file and its modification time and size when last loaded
+    let mut watched = options.watch.then(|| (image_paths[index].clone(), file_stamp(&image_paths[index])));
+    let mut next_watch = Instant::now() + WATCH_INTERVAL;
+    let mut modifiers = ModifiersState::empty();
+    // whether the window is hidden behind others or minimised, where the platform says
+    let mut occluded = false;
+    // the viewer generation an image pasted from the clipboard was shown at, while it still is
+    let mut pasted = None;
+
+    event_loop.run(move |event, _, control_flow| {
+        let mut deadline = None;
+
+        if viewer.is_blinking() {
+            if Instant::now() >= next_blink {
+                viewer.blink();
+                window.request_redraw();
+                next_blink = Instant::now() + BLINK_INTERVAL;
+            }
+
+            deadline = Some(next_blink);
+        }
+
+        if let Some(view_lock) = &mut view_lock {
+            if let Some((zoom, origin)) = view_lock.sync(viewer.view()) {
+                viewer.set_view(zoom, origin);
+                window.request_redraw();
+            }
+
+            let next = Instant::now() + VIEW_LOCK_INTERVAL;
+            deadline = Some(deadline.map_or(next, |deadline: Instant| deadline.min(next)));
+        }
+
+        if viewer.is_showing_clipping() {
+            if Instant::now() >= next_flash {
+                viewer.flash_clipping();
+                window.request_redraw();
+                next_flash = Instant::now() + CLIPPING_INTERVAL;
+            }
+
+            deadline = Some(deadline.map_or(next_flash, |deadline: Instant| deadline.min(next_flash)));
+        }
+
+        if let Some((path, stamp)) = &mut watched {
+            if Instant::now() >= next_watch {
+                let current = file_stamp(&image_paths[index]);
+
+                // moving to another image only starts watching it
+                if *path != image_paths[index] {
+                    *path = image_paths[index].clone();
+                    *stamp = current;
+                } else if current != *stamp && is_complete_png(path) {
+                    *stamp = current;
+
+                    let (zoom, origin) = viewer.view();
+                    playback = open_image(&mut viewer, &image_paths[index], index, &options, &proxy, &mut decoding);
+                    update_window_icon(&window, &viewer);
+                    viewer.set_view(zoom, origin);
+                    window.request_redraw();
+                    tracing::info!(path = %image_paths[index], "Reloaded");
+                }
+
+                next_watch = Instant::now() + WATCH_INTERVAL;
+            }
+
+            deadline = Some(deadline.map_or(next_watch, |deadline: Instant| deadline.min(next_watch)));
+        }
+
+        if let Some(mut next) = next_slide {
+            if Instant::now() >= next {
+                index = (index + 1) % image_paths.len();
+                playback = open_image(&mut viewer, &image_paths[index], index, &options, &proxy, &mut decoding);
+                update_window_icon(&window, &viewer);
+                viewer.set_position(index, image_paths.len());
+                window.request_redraw();
+                next = Instant::now() + options.slideshow_interval;
+                next_slide = Some(next);
+            }
+
+            deadline = Some(deadline.map_or(next, |deadline: Instant| deadline.min(next)));
+        }
+
+        // an animation only wakes the loop when its next frame is due, and not at all while it
+        // can't be seen; it picks up from the frame after on coming back
+        if let Some(playback) = playback.as_mut().filter(|_| !occluded) {
+            if playback.advance(Instant::now()) {
+                viewer.set_pixel_data(playback.frame().to_vec());
+                window.request_redraw();
+            }
+
+            if let Some(next) = playback.deadline() {
+                deadline = Some(deadline.map_or(next, |deadline: Instant| deadline.min(next)));
+            }
+        }
 
-        let color_len = match self.colour_type {
-            0 => 1,
-            2 => 3,
-            3 => 1,
-            4 => 2,
-            6 => 4,
-            _ => panic!("Invalid colour type")
+        *control_flow = match deadline {
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
         };
 
-        for h in 0..self.height as usize {
-            self.pixel_data.push(vec![Default::default(); self.width as usize]);
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => {
+                    if let Some(geometry) = WindowGeometry::from_window(&window) {
+                        geometry.save();
+                    }
 
-            let mut idx = (self.width as usize * color_len + 1) * h;
-            let filter_type = data[idx];
-            idx += 1;
+                    if let Some(view_lock) = &mut view_lock {
+                        view_lock.publish(viewer.view());
+                    }
 
-            for w in 0..self.width as usize {
-                let a = if w == 0 { Default::default() } else { self.pixel_data[h][w - 1] };
-                let b = if h == 0 { Default::default() } else { self.pixel_data[h - 1][w] };
-                let c = if w == 0 || h == 0 { Default::default() } else { self.pixel_data[h - 1][w - 1] };
+                    // an interlaced image may still be decoding
+                    decoding.cancel();
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                    renderer.resize(size.width, size.height);
+                    viewer.resize(size.width, size.height);
+                    window.request_redraw();
+                }
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+                WindowEvent::Occluded(state) => {
+                    occluded = state;
+
+                    // the animation set no deadline while hidden; a redraw wakes the loop to pick it up
+                    if !occluded {
+                        window.request_redraw();
+                    }
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let changed = viewer.cursor_moved(position.x, position.y);
 
-                match self.colour_type {
-                    0 | 4 => {
-                        let pixel_r = Self::remove_filter(filter_type, data[idx], a.r, b.r, c.r);
-                        let pixel_a = if self.colour_type == 0 { 0xFF } else {
-                            Self::remove_filter(filter_type, data[idx + 1], a.a, b.a, c.a)
+                    if changed {
+                        update_selection_title(&window, viewer.selection());
+                        window.request_redraw();
+                    }
+                }
+                WindowEvent::MouseInput { button: MouseButton::Left, state: ElementState::Pressed, .. } if viewer.is_menu_open() => {
+                    if let Some(item) = viewer.close_menu() {
+                        choose_menu_item(item, &window, &mut viewer, Path::new(&image_paths[index]));
+                    }
+
+                    window.request_redraw();
+                }
+                WindowEvent::MouseInput { button: MouseButton::Right, state: ElementState::Pressed, .. } => {
+                    viewer.open_menu();
+                    window.request_redraw();
+                }
+                WindowEvent::MouseInput { button: MouseButton::Left, state, .. } => {
+                    match state {
+                        ElementState::Pressed if modifiers.shift() => viewer.begin_measure(),
+                        ElementState::Pressed if modifiers.alt() => viewer.begin_sample(),
+                        ElementState::Pressed => {
+                            // with no title bar to grab, dragging the image moves the window and
+                            // selecting takes Ctrl
+                            let moving = options.borderless && !modifiers.ctrl();
+
+                            let handled = viewer.click_minimap() || moving && window.drag_window().is_ok();
+
+                            if !handled {
+                                viewer.begin_selection();
+                            }
+                        }
+                        ElementState::Released => {
+                            viewer.end_selection();
+                            viewer.end_measure();
+
+                            if let Some(sample) = viewer.end_sample() {
+                                copy_sample(&sample);
+                            }
+                        }
+                    }
+
+                    update_selection_title(&window, viewer.selection());
+                    window.request_redraw();
+                }
+                WindowEvent::MouseInput { button: MouseButton::Middle, state, .. } => match state {
+                    ElementState::Pressed => viewer.begin_pan(),
+                    ElementState::Released => viewer.end_pan(),
+                },
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y as f64,
+                        MouseScrollDelta::PixelDelta(position) => position.y / 40.0,
+                    };
+
+                    viewer.zoom_at_cursor(1.25f64.powf(lines));
+                    window.request_redraw();
+                }
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput { state: ElementState::Released, virtual_keycode: Some(key), .. }, ..
+                } if options.key_bindings.action(key, modifiers.ctrl()) == Some(Action::Loupe) && viewer.set_loupe(false) => window.request_redraw(),
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. }, ..
+                } => match options.key_bindings.action(key, modifiers.ctrl()) {
+                    Some(Action::SaveSelection) => {
+                        if let Some(selection) = viewer.selection() {
+                            save_selection(Path::new(&image_paths[index]), selection, viewer.crop(selection));
+                        }
+                    }
+                    Some(Action::CopySelection) => {
+                        if let Some(selection) = viewer.selection() {
+                            copy_selection(selection, viewer.crop(selection));
+                        }
+                    }
+                    Some(Action::Blink) => {
+                        let toggled = viewer.toggle_compare_mode(CompareMode::Blink { showing_compare: false });
+
+                        if toggled {
+                            next_blink = Instant::now() + BLINK_INTERVAL;
+                            window.request_redraw();
+                        }
+                    }
+                    Some(Action::Difference) => {
+                        let toggled = viewer.toggle_compare_mode(CompareMode::Difference { amplify: 8 });
+
+                        if toggled {
+                            window.request_redraw();
+                        }
+                    }
+                    Some(action @ (Action::AmplifyDifference | Action::AttenuateDifference)) => {
+                        if let Some(amplify) = viewer.amplify_difference(action == Action::AmplifyDifference) {
+                            tracing::info!(amplify, "Difference");
+                            window.request_redraw();
+                        }
+                    }
+                    Some(Action::ToggleFilters) => {
+                        viewer.toggle_filters();
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleMetadata) => {
+                        viewer.toggle_metadata();
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleRulers) => {
+                        viewer.toggle_rulers();
+                        window.request_redraw();
+                    }
+                    Some(Action::CycleSimulation) => {
+                        viewer.set_simulation(Simulation::next(viewer.simulation()));
+
+                        match viewer.simulation() {
+                            Some(simulation) => tracing::info!(simulation = simulation.name(), "Simulating"),
+                            None => tracing::info!("Simulation off"),
+                        }
+
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleInvert) => {
+                        let inverted = viewer.toggle_invert();
+                        tracing::info!(inverted, "Inverted colours");
+                        window.request_redraw();
+                    }
+                    Some(Action::SwapRedBlue) => {
+                        let swapped = viewer.toggle_swap_red_blue();
+                        tracing::info!(swapped, "Red and blue");
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleLinear) => {
+                        let linear = viewer.toggle_linear_samples();
+                        tracing::info!(encoding = if linear { "linear light" } else { "sRGB" }, "Samples treated as");
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleViewLock) => {
+                        view_lock = match view_lock {
+                            Some(_) => None,
+                            None => Some(ViewLock::new()),
                         };
 
-                        self.pixel_data[h][w] = Pixel {
-                            r: pixel_r,
-                            g: pixel_r,
-                            b: pixel_r,
-                            a: pixel_a,
+                        tracing::info!(on = view_lock.is_some(), "View lock");
+                        // wake up to start polling
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleClipping) => {
+                        let clipping = viewer.toggle_clipping();
+                        tracing::info!(clipping, "Clipping stripes");
+                        next_flash = Instant::now() + CLIPPING_INTERVAL;
+                        window.request_redraw();
+                    }
+                    Some(Action::CycleAlphaView) => {
+                        viewer.set_alpha_view(AlphaView::next(viewer.alpha_view()));
+
+                        match viewer.alpha_view() {
+                            Some(alpha_view) => tracing::info!(alpha_view = alpha_view.name(), "Alpha view"),
+                            None => tracing::info!("Alpha view off"),
+                        }
+
+                        window.request_redraw();
+                    }
+                    // held down, so repeats change nothing
+                    Some(Action::Loupe) if viewer.set_loupe(true) => window.request_redraw(),
+                    Some(Action::TogglePremultipliedAlpha) => {
+                        let alpha = match viewer.toggle_premultiplied_alpha() {
+                            true => "premultiplied, composited over the background",
+                            false => "straight, ignored",
                         };
+                        tracing::info!(alpha, "Alpha");
+                        window.request_redraw();
                     }
-                    2 | 6 => {
-                        let pixel_a = if self.colour_type == 2 { 0xFF } else {
-                            Self::remove_filter(filter_type, data[idx + 3], a.a, b.a, c.a)
+                    Some(action @ (Action::IncreaseExposure | Action::DecreaseExposure)) => {
+                        let exposure = viewer.adjust_exposure(if action == Action::IncreaseExposure { 0.25 } else { -0.25 });
+                        tracing::info!(ev = %format_args!("{:+.2}", exposure), "Exposure");
+                        window.request_redraw();
+                    }
+                    Some(action @ (Action::IncreaseGamma | Action::DecreaseGamma)) => {
+                        let gamma = viewer.adjust_gamma(if action == Action::IncreaseGamma { 1.1 } else { 1.0 / 1.1 });
+                        tracing::info!(gamma = %format_args!("{:.2}", gamma), "Gamma");
+                        window.request_redraw();
+                    }
+                    Some(Action::ResetTone) => {
+                        viewer.reset_tone();
+                        tracing::info!("Exposure and gamma reset");
+                        window.request_redraw();
+                    }
+                    Some(Action::ClearSelection) => {
+                        viewer.close_menu();
+                        viewer.clear_selection();
+                        update_selection_title(&window, None);
+                        window.request_redraw();
+                    }
+                    Some(action @ (Action::ActualSize | Action::Zoom200 | Action::Zoom300)) => {
+                        viewer.set_zoom(match action {
+                            Action::Zoom200 => 2.0,
+                            Action::Zoom300 => 3.0,
+                            _ => 1.0,
+                        });
+                        snap_window(&window, viewer.zoomed_size());
+                        window.request_redraw();
+                    }
+                    Some(Action::ZoomToFit) => {
+                        viewer.zoom_to_fit();
+                        window.request_redraw();
+                    }
+                    Some(action @ (Action::NextImage | Action::PreviousImage | Action::FirstImage | Action::LastImage)) if image_paths.len() > 1 => {
+                        index = match action {
+                            Action::NextImage => (index + 1) % image_paths.len(),
+                            Action::PreviousImage => (index + image_paths.len() - 1) % image_paths.len(),
+                            Action::FirstImage => 0,
+                            _ => image_paths.len() - 1,
                         };
 
-                        self.pixel_data[h][w] = Pixel {
-                            r: Self::remove_filter(filter_type, data[idx], a.r, b.r, c.r),
-                            g: Self::remove_filter(filter_type, data[idx + 1], a.g, b.g, c.g),
-                            b: Self::remove_filter(filter_type, data[idx + 2], a.b, b.b, c.b),
-                            a: pixel_a,
+                        playback = open_image(&mut viewer, &image_paths[index], index, &options, &proxy, &mut decoding);
+                        update_window_icon(&window, &viewer);
+                        viewer.set_position(index, image_paths.len());
+                        update_selection_title(&window, None);
+                        window.request_redraw();
+
+                        if next_slide.is_some() {
+                            next_slide = Some(Instant::now() + options.slideshow_interval);
+                        }
+                    }
+                    Some(Action::CycleDither) => {
+                        options.dither = options.dither.next();
+                        tracing::info!(dither = options.dither.name(), "Dithering 16-bit samples");
+
+                        playback = open_image(&mut viewer, &image_paths[index], index, &options, &proxy, &mut decoding);
+                        update_window_icon(&window, &viewer);
+                        update_selection_title(&window, None);
+                        window.request_redraw();
+                    }
+                    Some(Action::ToggleSlideshow) if image_paths.len() > 1 => {
+                        next_slide = match next_slide {
+                            Some(_) => None,
+                            None => Some(Instant::now() + options.slideshow_interval),
                         };
                     }
-                    _ => {}
+                    Some(Action::Paste) => {
+                        if let Some(pixel_data) = paste_image() {
+                            // nothing of the file shown before should replace it
+                            decoding.cancel();
+                            playback = None;
+
+                            let (width, height) = (pixel_data[0].len() as u32, pixel_data.len() as u32);
+                            viewer.set_image(width, height, pixel_data);
+                            viewer.set_image_profile(None);
+                            viewer.set_metadata(vec!["Pasted from the clipboard".to_string(), format!("Size: {}x{}", width, height)]);
+                            pasted = Some(viewer.generation());
+
+                            update_window_icon(&window, &viewer);
+                            window.set_title("png-viewer - clipboard");
+                            window.request_redraw();
+                            tracing::info!(width, height, "Pasted");
+                        }
+                    }
+                    Some(Action::SavePasted) => match pasted == Some(viewer.generation()) {
+                        true => save_pasted(viewer.images().0),
+                        false => eprintln!("{}", "Only a pasted image is saved this way".red()),
+                    },
+                    Some(Action::Bookmark) => match pasted == Some(viewer.generation()) {
+                        true => eprintln!("{}", "A pasted image has no file to bookmark".red()),
+                        false => match bookmarks::toggle(Path::new(&image_paths[index])) {
+                            Some(true) => tracing::info!(path = %image_paths[index], "Bookmarked"),
+                            Some(false) => tracing::info!(path = %image_paths[index], "Removed bookmark"),
+                            None => eprintln!("{}", "No config directory to keep bookmarks in".red()),
+                        },
+                    },
+                    _ => ()
+                },
+                _ => ()
+            },
+            Event::UserEvent(UserEvent::PixelData(image, pixel_data)) if image == index => {
+                viewer.set_pixel_data(pixel_data);
+                update_window_icon(&window, &viewer);
+                window.request_redraw();
+            }
+            Event::UserEvent(UserEvent::FilterTypes(image, filter_map, filter_statistics)) if image == index => {
+                viewer.set_filter_types(filter_map, filter_statistics);
+                window.request_redraw();
+            }
+            Event::UserEvent(UserEvent::LoadFailed(image, message)) => error_dialog::show(&image_paths[image], &message),
+            Event::RedrawRequested(_) => {
+                let _span = tracing::debug_span!("render").entered();
+                let start = Instant::now();
+                renderer.render(&viewer);
+
+                if first_render {
+                    print_timing("First render", start.elapsed());
+                    first_render = false;
                 }
+            }
+            // winit ends the process with `exit` after this, which doesn't run destructors
+            Event::LoopDestroyed => {
+                let _ = io::stdout().flush();
+                let _ = io::stderr().flush();
+            }
+            _ => ()
+        }
+    })
+}
 
+/// The scale, at most 1, at which a `width` x `height` logical pixel window fits on the primary
+/// monitor, leaving room for panels and window decorations.
+fn fit_scale(event_loop: &EventLoop<UserEvent>, width: f64, height: f64) -> f64 {
+    let Some(monitor) = event_loop.primary_monitor().or_else(|| event_loop.available_monitors().next()) else {
+        return 1.0;
+    };
 
-                idx += color_len;
-            }
+    let available = monitor.size().to_logical::<f64>(monitor.scale_factor());
+
+    (available.width * FIT_MARGIN / width)
+        .min(available.height * FIT_MARGIN / height)
+        .min(1.0)
+}
+
+/// Where `--position` and `--monitor` put a window of `size`: at the position, relative to the
+/// monitor if one was chosen, or centred on the monitor. None if neither was given.
+fn placement(event_loop: &EventLoop<UserEvent>, options: &Options, size: LogicalSize<f64>) -> Option<PhysicalPosition<i32>> {
+    let monitor = options.monitor.map(|n| {
+        event_loop.available_monitors().nth(n - 1).unwrap_or_else(|| {
+            eprintln!("{} {} (there are {})", "No monitor".red(), n, event_loop.available_monitors().count());
+            std::process::exit(1);
+        })
+    });
+
+    match (options.position, monitor) {
+        (Some((x, y)), Some(monitor)) => Some(PhysicalPosition::new(monitor.position().x + x, monitor.position().y + y)),
+        (Some((x, y)), None) => Some(PhysicalPosition::new(x, y)),
+        (None, Some(monitor)) => {
+            let size = size.to_physical::<i32>(monitor.scale_factor());
+            let x = monitor.position().x + (monitor.size().width as i32 - size.width).max(0) / 2;
+            let y = monitor.position().y + (monitor.size().height as i32 - size.height).max(0) / 2;
+
+            Some(PhysicalPosition::new(x, y))
         }
+        (None, None) => None,
     }
+}
 
-    fn remove_filter(filter_type: u8, x: u8, a: u8, b: u8, c: u8) -> u8 {
-        match filter_type {
-            0 => {
-                x
+/// Applies the display options shared by the window and `--render`, apart from the zoom.
+fn configure_viewer(viewer: &mut Viewer, options: &Options) {
+    viewer.set_background(options.background);
+    viewer.set_filter(options.filter);
+    viewer.set_simulation(options.simulation);
+    viewer.set_invert(options.invert);
+    viewer.set_swap_red_blue(options.swap_red_blue);
+    viewer.set_linear_samples(options.linear_samples);
+    viewer.set_clipping(options.clipping);
+    viewer.set_alpha_view(options.alpha_view);
+
+    if let Some(path) = &options.display_profile {
+        match colour::load_profile(path) {
+            Ok(profile) => viewer.set_output_profile(profile),
+            Err(e) => {
+                eprintln!("{} {}", "Invalid display profile".red(), e);
+                std::process::exit(1);
             }
+        }
+    } else if options.display_p3 {
+        viewer.set_output_profile(colour::display_p3());
+    }
+}
 
-            1 => {
-                (x as i32 + a as i32) as u8
-            }
-            2 => {
-                (x as i32 + b as i32) as u8
-            }
+/// Draws what a window opened on `image_path` at scale factor 1 would first show (`--scale fit`
+/// counting as 1, as there's no monitor to fit) and saves it as
+/// `output`, without needing a display server.
+fn render_to_file(image_path: &str, compare: Option<PngReader>, options: &Options, output: &Path) {
+    let mut viewer = Viewer::new(1, 1, 1.0);
+    configure_viewer(&mut viewer, options);
+    viewer.zoom_by(options.zoom * match options.scale.unwrap_or(Scale::Factor(1.0)) {
+        Scale::Factor(factor) => factor,
+        Scale::Fit => 1.0,
+    });
+    if let Err(e) = show_decoded(&mut viewer, read_png(image_path, options), options) {
+        decode_failed(image_path, &e);
+    }
+
+    if let Some(compare) = compare {
+        viewer.set_compare(transform::orient(&compare.pixel_data, orientation(&compare, options)));
+        viewer.set_compare_profile(colour::image_profile(&compare));
+    }
+
+    let (frame_width, frame_height) = viewer.zoomed_size();
+    viewer.resize(frame_width, frame_height);
+
+    let start = Instant::now();
+    let mut frame = vec![0; frame_width as usize * frame_height as usize * 4];
+    viewer.draw(&mut frame);
+
+    if options.timings {
+        print_timing("First render", start.elapsed());
+    }
+
+    let pixel_data = frame
+        .chunks_exact(frame_width as usize * 4)
+        .map(|row| row.chunks_exact(4).map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] }).collect())
+        .collect();
+
+    let bytes = PngWriter::new(frame_width, frame_height, pixel_data).write();
+
+    match std::fs::write(output, bytes) {
+        Ok(_) => println!("{} {}x{} -> {}", "Rendered".green(), frame_width, frame_height, output.display()),
+        Err(e) => eprintln!("{} {}: {}", "Failed to write".red(), output.display(), e),
+    }
+}
+
+/// Writes the decoded, oriented pixels of `image_path` to stdout row by row with no header, and
+/// `<width>x<height>` to stderr, for piping into other programs.
+fn write_raw(image_path: &str, format: RawFormat, options: &Options) {
+    let mut reader = read_png(image_path, options);
+    decode_or_exit(&mut reader, image_path);
+
+    if options.timings {
+        print_decode_timings(&reader);
+    }
+
+    if options.mem_stats {
+        print_memory(&reader);
+    }
+
+    let orientation = orientation(&reader, options);
+    let (width, height) = transform::oriented_size(reader.info().width, reader.info().height, orientation);
+    let mut pixel_data = transform::orient(&reader.pixel_data, orientation);
+
+    if options.premultiply {
+        export::premultiply(&mut pixel_data);
+    }
+
+    eprintln!("{}x{}", width, height);
+
+    let channels = match format {
+        RawFormat::Rgba8 => 4,
+        RawFormat::Rgb8 => 3,
+    };
+
+    let bytes = pixel_data
+        .iter()
+        .flatten()
+        .flat_map(|p| [p.r, p.g, p.b, p.a].into_iter().take(channels))
+        .collect::<Vec<u8>>();
+
+    if let Err(e) = std::io::stdout().lock().write_all(&bytes) {
+        eprintln!("{} {}", "Failed to write pixels".red(), e);
+    }
+}
+
+/// Reads `image_path` and shows it as `load_image` does, or sends why it can't be read to the
+/// event loop.
+fn open_image(viewer: &mut Viewer, image_path: &str, index: usize, options: &Options, proxy: &EventLoopProxy<UserEvent>, decoding: &mut CancelToken) -> Option<apng::Playback> {
+    match open_png(image_path, options) {
+        Ok(reader) => load_image(viewer, image_path, reader, index, options, proxy, decoding),
+        Err(e) => {
+            let _ = proxy.send_event(UserEvent::LoadFailed(index, e));
+            None
+        }
+    }
+}
+
+/// Shows `reader`'s image, read from `image_path`, decoding it here. Interlaced images are decoded
+/// on a background thread so each Adam7 pass is shown as soon as it completes, until `decoding`
+/// is cancelled by the next call. Returns the playback, already on its first frame, if the image
+/// is animated. An image that can't be decoded leaves the viewer as it was, and the event loop
+/// is sent why, to show from the main thread.
+fn load_image(viewer: &mut Viewer, image_path: &str, mut reader: PngReader, index: usize, options: &Options, proxy: &EventLoopProxy<UserEvent>, decoding: &mut CancelToken) -> Option<apng::Playback> {
+    recent::add(Path::new(image_path));
+
+    // the previous image may still be decoding in the background
+    decoding.cancel();
+    *decoding = CancelToken::new();
+
+    let orientation = orientation(&reader, options);
+
+    // a broken animation still has its default image to show
+    let frames = apng::composite(&reader)
+        .unwrap_or_else(|e| {
+            eprintln!("{} {}", "Failed to decode the animation:".red(), e);
+            vec![]
+        })
+        .into_iter()
+        .map(|frame| apng::Frame { pixel_data: transform::orient(&frame.pixel_data, orientation), ..frame })
+        .collect::<Vec<_>>();
+
+    let playback = reader.info().animation
+        .filter(|_| !frames.is_empty())
+        .map(|animation| apng::Playback::new(frames, animation.num_plays, options.speed));
+
+    // animations are decoded here whether interlaced or not, so passes don't replace frames
+    if !reader.is_interlaced() || playback.is_some() {
+        if let Err(e) = show_decoded(viewer, reader, options) {
+            let _ = proxy.send_event(UserEvent::LoadFailed(index, e));
+            return None;
+        }
+
+        if let Some(playback) = &playback {
+            viewer.set_pixel_data(playback.frame().to_vec());
+        }
+
+        return playback;
+    }
+
+    let (width, height) = transform::oriented_size(reader.info().width, reader.info().height, orientation);
+
+    viewer.set_image(width, height, vec![vec![Pixel::default(); width as usize]; height as usize]);
+    viewer.set_image_profile(colour::image_profile(&reader));
+    viewer.set_metadata(reader.summary());
+
+    let proxy = proxy.clone();
+    let pass_proxy = proxy.clone();
+
+    reader.set_pass_callback(move |pixel_data| {
+        let _ = pass_proxy.send_event(UserEvent::PixelData(index, transform::orient(pixel_data, orientation)));
+    });
+
+    let (stats, timings, mem_stats) = (options.stats, options.timings, options.mem_stats);
 
-            3 => {
-                (x as i32 + ((a as i32 + b as i32) / 2)) as u8
+    reader.set_cancel_token(decoding.clone());
+
+    thread::spawn(move || {
+        if let Err(e) = reader.decode_image_data() {
+            let _ = proxy.send_event(UserEvent::LoadFailed(index, e));
+            return;
+        }
+
+        if reader.is_cancelled() {
+            return;
+        }
+
+        if timings {
+            print_decode_timings(&reader);
+        }
+
+        if mem_stats {
+            print_memory(&reader);
+        }
+
+        if stats {
+            print_statistics(&reader);
+        }
+
+        let filter_map = transform::orient(&reader.filter_map(), orientation);
+        let _ = proxy.send_event(UserEvent::FilterTypes(index, filter_map, reader.filter_statistics()));
+    });
+
+    None
+}
+
+/// Decodes `reader`'s image here and shows it with everything known about it. Leaves the viewer
+/// as it was if the image can't be decoded.
+fn show_decoded(viewer: &mut Viewer, mut reader: PngReader, options: &Options) -> Result<(), String> {
+    reader.decode_image_data()?;
+
+    if options.timings {
+        print_decode_timings(&reader);
+    }
+
+    if options.mem_stats {
+        print_memory(&reader);
+    }
+
+    if options.stats {
+        print_statistics(&reader);
+    }
+
+    let orientation = orientation(&reader, options);
+    let (width, height) = transform::oriented_size(reader.info().width, reader.info().height, orientation);
+
+    viewer.set_image(width, height, transform::orient(&reader.pixel_data, orientation));
+    viewer.set_image_profile(colour::image_profile(&reader));
+    viewer.set_metadata(reader.summary());
+    viewer.set_filter_types(transform::orient(&reader.filter_map(), orientation), reader.filter_statistics());
+
+    Ok(())
+}
+
+/// Decodes `reader`'s image, read from `image_path`, or exits with why it can't be.
+fn decode_or_exit(reader: &mut PngReader, image_path: &str) {
+    if let Err(e) = reader.decode_image_data() {
+        decode_failed(image_path, &e);
+    }
+}
+
+fn decode_failed(image_path: &str, error: &str) -> ! {
+    eprintln!("{} {}: {}", "Failed to decode".red(), image_path, error);
+    std::process::exit(1);
+}
+
+/// Prints every deflate block of the concatenated IDAT data with what it encodes, then totals.
+fn analyze_deflate(reader: &PngReader) {
+    let zlib = reader.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type == "IDAT")
+        .flat_map(|chunk| reader.chunk_data(chunk))
+        .copied()
+        .collect::<Vec<u8>>();
+
+    let analysis = match deflate::analyze(&zlib) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            eprintln!("{} {}", "Invalid image data:".red(), e);
+            return;
+        }
+    };
+
+    let level = ["fastest", "fast", "default", "maximum"][analysis.level as usize];
+    println!("{} {}-byte window, {} compression, {} bytes", "zlib".green(), analysis.window_size, level, zlib.len());
+    println!();
+    println!("{:>5}  {:<8} {:>12} {:>11} {:>9} {:>9} {:>8} {:>9} {:>7} {:>8}", "Block", "Type", "Offset", "Compressed", "Output", "Literals", "Matches", "Matched", "Longest", "Distance");
+
+    for (i, block) in analysis.blocks.iter().enumerate() {
+        println!(
+            "{:>5}  {:<8} {:>10}.{} {:>11} {:>9} {:>9} {:>8} {:>9} {:>7} {:>8}",
+            i,
+            format!("{:?}", block.block_type),
+            // after the two byte zlib header
+            2 + block.start_bit / 8,
+            block.start_bit % 8,
+            block.bits.div_ceil(8),
+            block.output_bytes,
+            block.literals,
+            block.matches,
+            block.matched_bytes,
+            block.longest_match,
+            block.max_distance,
+        );
+    }
+
+    let count = |block_type| analysis.blocks.iter().filter(|block| block.block_type == block_type).count();
+    let output = analysis.blocks.iter().map(|block| block.output_bytes).sum::<usize>();
+    let matched = analysis.blocks.iter().map(|block| block.matched_bytes).sum::<usize>();
+    let max_distance = analysis.blocks.iter().map(|block| block.max_distance).max().unwrap_or(0);
+
+    println!();
+    println!("Blocks: {} stored, {} fixed, {} dynamic", count(deflate::BlockType::Stored), count(deflate::BlockType::Fixed), count(deflate::BlockType::Dynamic));
+    println!("Output: {} bytes, {:.1}% from matches", output, matched as f64 * 100.0 / output.max(1) as f64);
+    println!("Window usage: furthest match {} of {} bytes ({:.1}%)", max_distance, analysis.window_size, max_distance as f64 * 100.0 / analysis.window_size as f64);
+    println!("Adler-32: {}", if analysis.adler32_ok { "OK".green() } else { "mismatch".red() });
+}
+
+/// Writes every frame of an APNG, composited as it's shown, to `dir` as `frame_0001.png` and on.
+fn extract_frames(reader: &PngReader, dir: &Path) {
+    let frames = match apng::composite(reader) {
+        Ok(frames) => frames,
+        Err(e) => {
+            eprintln!("{} {}", "Failed to decode the animation:".red(), e);
+            return;
+        }
+    };
+
+    if frames.is_empty() {
+        eprintln!("{}", "The image isn't animated".red());
+        return;
+    }
+
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("{} {}: {}", "Failed to create".red(), dir.display(), e);
+        return;
+    }
+
+    let digits = frames.len().to_string().len().max(4);
+
+    for (i, frame) in frames.into_iter().enumerate() {
+        let path = dir.join(format!("frame_{:0digits$}.png", i + 1, digits = digits));
+        let bytes = PngWriter::new(reader.info().width, reader.info().height, frame.pixel_data).write();
+
+        match std::fs::write(&path, bytes) {
+            Ok(_) => println!("{} {} ({} ms)", "Wrote".green(), path.display(), frame.delay.as_millis()),
+            Err(e) => {
+                eprintln!("{} {}: {}", "Failed to write".red(), path.display(), e);
+                return;
             }
+        }
+    }
 
-            4 => {
-                (x as i32 + Self::paeth(a, b, c) as i32) as u8
+    if let Some(animation) = reader.info().animation {
+        match animation.num_plays {
+            0 => println!("{} frames, looping forever", animation.num_frames),
+            plays => println!("{} frames, played {} times", animation.num_frames, plays),
+        }
+    }
+}
+
+/// Builds an APNG at `output` from still images of one size, one frame each, in the order given.
+/// Returns whether it was written.
+fn assemble(image_paths: &[String], output: &Path, options: &Options) -> bool {
+    let mut size: Option<(u32, u32)> = None;
+    let mut frames = vec![];
+
+    for image_path in image_paths {
+        let mut reader = read_png(image_path, options);
+
+        if let Err(e) = reader.decode_image_data() {
+            eprintln!("{} {}: {}", "Failed to decode".red(), image_path, e);
+            return false;
+        }
+
+        let frame_size = (reader.info().width, reader.info().height);
+
+        if let Some((width, height)) = size.filter(|&size| size != frame_size) {
+            eprintln!("{} {} is {}x{}, not {}x{} like the first frame", "Can't assemble:".red(), image_path, frame_size.0, frame_size.1, width, height);
+            return false;
+        }
+
+        size = Some(frame_size);
+        frames.push(apng::Frame {
+            pixel_data: reader.pixel_data,
+            delay: Duration::from_millis(options.delay as u64),
+        });
+    }
+
+    let Some((width, height)) = size else {
+        eprintln!("{}", "No frames to assemble".red());
+        return false;
+    };
+
+    let count = frames.len();
+    let bytes = apng::assemble(width, height, frames, 0);
+
+    match std::fs::write(output, bytes) {
+        Ok(_) => {
+            println!("{} {} frames of {}x{}, {} ms each -> {}", "Assembled".green(), count, width, height, options.delay, output.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("{} {}: {}", "Failed to write".red(), output.display(), e);
+            false
+        }
+    }
+}
+
+/// Converts `input`, a GIF or a PNG, to a GIF if `to_gif` and to an APNG otherwise, keeping
+/// frame delays and the loop count. Returns whether it was written.
+fn convert(input: &str, output: &Path, to_gif: bool, options: &Options) -> bool {
+    let mut animation = match load_animation(input, options) {
+        Ok(animation) => animation,
+        Err(e) => {
+            eprintln!("{} {}: {}", "Failed to read".red(), input, e);
+            return false;
+        }
+    };
+
+    for frame in &mut animation.frames {
+        frame.pixel_data = export::turn(&frame.pixel_data, options);
+
+        if options.premultiply {
+            export::premultiply(&mut frame.pixel_data);
+        }
+    }
+
+    if let Some(frame) = animation.frames.first() {
+        (animation.width, animation.height) = (frame.pixel_data.first().map_or(0, |row| row.len()) as u32, frame.pixel_data.len() as u32);
+    }
+
+    let frame_count = animation.frames.len();
+
+    let bytes = match to_gif {
+        true => match gif_file::encode(&animation) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("{} {}", "Failed to encode the GIF:".red(), e);
+                return false;
             }
+        },
+        false => apng::assemble(animation.width, animation.height, animation.frames, animation.num_plays),
+    };
 
-            _ => 0
+    match std::fs::write(output, bytes) {
+        Ok(_) => {
+            println!("{} {} frames -> {}", "Converted".green(), frame_count, output.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("{} {}: {}", "Failed to write".red(), output.display(), e);
+            false
         }
     }
+}
 
-    fn paeth(a: u8, b: u8, c: u8) -> u8 {
-        let a = a as i32;
-        let b = b as i32;
-        let c = c as i32;
-        let p = a + b - c;
+/// Decodes and composites every frame of a GIF or a PNG, a still PNG being a single frame.
+fn load_animation(image_path: &str, options: &Options) -> Result<gif_file::Animation, String> {
+    let bytes = std::fs::read(image_path).map_err(|e| e.to_string())?;
 
-        let pa = (p - a).abs();
-        let pb = (p - b).abs();
-        let pc = (p - c).abs();
+    if gif_file::is_gif(&bytes) {
+        return gif_file::decode(&bytes);
+    }
 
-        return if pa <= pb && pa <= pc {
-            a as u8
-        } else if pb <= pc {
-            b as u8
-        } else {
-            c as u8
-        };
+    let mut reader = read_png(image_path, options);
+    let mut frames = apng::composite(&reader)?;
+
+    if frames.is_empty() {
+        reader.decode_image_data()?;
+        frames.push(apng::Frame { pixel_data: std::mem::take(&mut reader.pixel_data), delay: Duration::ZERO });
+    }
+
+    Ok(gif_file::Animation {
+        width: reader.info().width,
+        height: reader.info().height,
+        frames,
+        num_plays: reader.info().animation.map_or(1, |animation| animation.num_plays),
+    })
+}
+
+/// Prints how long inflating and unfiltering the image data took, for `--timings`.
+fn print_decode_timings(reader: &PngReader) {
+    let timings = reader.timings();
+
+    print_timing("Inflate", timings.inflate);
+    print_timing("Unfilter", timings.unfilter);
+}
+
+/// Prints one `--timings` line, to stderr so it stays out of anything written to stdout.
+fn print_timing(stage: &str, duration: Duration) {
+    eprintln!("{} {:.1} ms", format!("{}:", stage).green(), duration.as_secs_f64() * 1000.0);
+}
+
+/// Prints the peak size of the decoder's buffers, and with the `heap-stats` feature of the whole
+/// heap so far, for `--mem-stats`.
+fn print_memory(reader: &PngReader) {
+    let sizes = reader.buffer_sizes();
+    let print = |name: &str, bytes: usize| eprintln!("{} {} bytes ({:.1} MiB)", format!("{}:", name).green(), bytes, bytes as f64 / (1 << 20) as f64);
+
+    print("File buffer", sizes.file);
+    print("Compressed buffer", sizes.compressed);
+    print("Inflated buffer", sizes.inflated);
+    print("Pixel buffer", sizes.pixels);
+    #[cfg(feature = "heap-stats")]
+    print("Peak heap", mem_stats::peak());
+}
+
+fn print_statistics(reader: &PngReader) {
+    println!("{}", "Decode statistics".green());
+
+    for line in reader.decode_statistics() {
+        println!("{}", line);
     }
-}
\ No newline at end of file
+
+    println!();
+}
+
+/// Resizes the window to `size` in physical pixels, or as close as the monitor allows.
+fn snap_window(window: &winit::window::Window, size: (u32, u32)) {
+    let (mut width, mut height) = size;
+
+    if let Some(monitor) = window.current_monitor() {
+        width = width.min(monitor.size().width);
+        height = height.min(monitor.size().height);
+    }
+
+    window.set_inner_size(PhysicalSize::new(width, height));
+}
+
+/// Shows the image, scaled down and centred on a transparent square, as the window's icon, so the
+/// taskbar or dock tells windows apart. An interlaced image's icon fills in with its passes.
+fn update_window_icon(window: &winit::window::Window, viewer: &Viewer) {
+    let (pixel_data, _) = viewer.images();
+    let (width, height) = (pixel_data.first().map_or(0, |row| row.len()), pixel_data.len());
+
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    // the resize filters visit every source pixel, so a large image is first thinned out to a
+    // few samples per icon pixel
+    let step = (width.max(height) / (ICON_SIZE as usize * 4)).max(1);
+    let sampled = pixel_data.iter().step_by(step).map(|row| row.iter().step_by(step).copied().collect()).collect::<Vec<Vec<Pixel>>>();
+
+    let scale = ICON_SIZE as f64 / width.max(height) as f64;
+    let size = |n: usize| ((n as f64 * scale).round() as u32).clamp(1, ICON_SIZE);
+    let (icon_width, icon_height) = (size(width), size(height));
+    let scaled = resize::resize(&sampled, icon_width, icon_height, resize::ResizeFilter::Box);
+
+    let (left, top) = ((ICON_SIZE - icon_width) / 2, (ICON_SIZE - icon_height) / 2);
+    let mut rgba = vec![0; (ICON_SIZE * ICON_SIZE * 4) as usize];
+
+    for (y, row) in scaled.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            let offset = (((top as usize + y) * ICON_SIZE as usize) + left as usize + x) * 4;
+            rgba[offset..offset + 4].copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+    }
+
+    match Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE) {
+        Ok(icon) => window.set_window_icon(Some(icon)),
+        Err(e) => eprintln!("{} {}", "Failed to set the window icon:".red(), e),
+    }
+}
+
+fn update_selection_title(window: &winit::window::Window, selection: Option<Rect>) {
+    match selection {
+        Some(rect) => window.set_title(&format!("png-viewer - selection {}x{} at ({}, {})", rect.width, rect.height, rect.x, rect.y)),
+        None => window.set_title("png-viewer"),
+    }
+}
+
+/// Returns `<stem>-crop-<n>.png` next to the source image, picking the first unused `n`.
+fn crop_output_path(image_path: &Path) -> PathBuf {
+    let stem = image_path.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+
+    (1..)
+        .map(|n| image_path.with_file_name(format!("{}-crop-{}.png", stem, n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Does what the right-click menu's `item` offers, for mouse-only use; most of it also has a key.
+fn choose_menu_item(item: MenuItem, window: &winit::window::Window, viewer: &mut Viewer, image_path: &Path) {
+    match item {
+        MenuItem::Copy => {
+            let (pixel_data, _) = viewer.images();
+            let whole = Rect { x: 0, y: 0, width: pixel_data.first().map_or(0, |row| row.len()) as u32, height: pixel_data.len() as u32 };
+            let selection = viewer.selection().unwrap_or(whole);
+
+            copy_selection(selection, viewer.crop(selection));
+        }
+        MenuItem::SaveAs => save_as(image_path),
+        MenuItem::OpenFolder => open_folder(image_path),
+        MenuItem::Properties => viewer.toggle_metadata(),
+        MenuItem::ZoomToFit => viewer.zoom_to_fit(),
+        MenuItem::ActualSize | MenuItem::Zoom200 | MenuItem::Zoom300 => {
+            viewer.set_zoom(match item {
+                MenuItem::Zoom200 => 2.0,
+                MenuItem::Zoom300 => 3.0,
+                _ => 1.0,
+            });
+            snap_window(window, viewer.zoomed_size());
+        }
+    }
+}
+
+/// Copies the image's file to where the platform's save dialog says.
+fn save_as(image_path: &Path) {
+    let mut dialog = rfd::FileDialog::new()
+        .set_title("Save as")
+        .add_filter("PNG images", &["png", "apng"]);
+
+    if let Some(name) = image_path.file_name() {
+        dialog = dialog.set_file_name(name.to_string_lossy());
+    }
+
+    let Some(path) = dialog.save_file() else {
+        return;
+    };
+
+    match fs::copy(image_path, &path) {
+        Ok(_) => println!("{} {} -> {}", "Saved".green(), image_path.display(), path.display()),
+        Err(e) => eprintln!("{} {}: {}", "Failed to save".red(), path.display(), e),
+    }
+}
+
+/// Opens the folder the image is in with the platform's file manager.
+fn open_folder(image_path: &Path) {
+    let folder = match image_path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+
+    if let Err(e) = Command::new(opener).arg(folder).spawn() {
+        eprintln!("{} {}: {}", "Failed to open".red(), folder.display(), e);
+    }
+}
+
+fn save_selection(image_path: &Path, selection: Rect, pixel_data: Vec<Vec<Pixel>>) {
+    let path = crop_output_path(image_path);
+    let bytes = PngWriter::new(selection.width, selection.height, pixel_data).write();
+
+    match std::fs::write(&path, bytes) {
+        Ok(_) => println!("{} {}x{} -> {}", "Saved selection".green(), selection.width, selection.height, path.display()),
+        Err(e) => eprintln!("{} {}: {}", "Failed to save selection".red(), path.display(), e),
+    }
+}
+
+/// The image on the clipboard, e.g. a screenshot, or None, saying why, if there isn't one.
+fn paste_image() -> Option<Vec<Vec<Pixel>>> {
+    let image = match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_image()) {
+        Ok(image) if image.width > 0 && image.height > 0 => image,
+        Ok(_) => return None,
+        Err(e) => {
+            eprintln!("{}: {}", "Failed to paste an image".red(), e);
+            return None;
+        }
+    };
+
+    let pixel_data = image.bytes
+        .chunks_exact(image.width * 4)
+        .map(|row| row.chunks_exact(4).map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] }).collect())
+        .collect();
+
+    Some(pixel_data)
+}
+
+/// Saves a pasted image as `clipboard-<n>.png` in the working directory, picking the first
+/// unused `n`.
+fn save_pasted(pixel_data: &[Vec<Pixel>]) {
+    let path = (1..)
+        .map(|n| PathBuf::from(format!("clipboard-{}.png", n)))
+        .find(|path| !path.exists())
+        .unwrap();
+
+    let (width, height) = (pixel_data[0].len() as u32, pixel_data.len() as u32);
+    let bytes = PngWriter::new(width, height, pixel_data.to_vec()).write();
+
+    match fs::write(&path, bytes) {
+        Ok(_) => println!("{} {}x{} -> {}", "Saved pasted image".green(), width, height, path.display()),
+        Err(e) => eprintln!("{} {}: {}", "Failed to save pasted image".red(), path.display(), e),
+    }
+}
+
+/// Prints a region's colour statistics and copies them to the clipboard as text.
+fn copy_sample(sample: &[String]) {
+    let text = sample.join("\n");
+    println!("{}", text);
+
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+        Ok(_) => println!("{}", "Copied region statistics".green()),
+        Err(e) => eprintln!("{}: {}", "Failed to copy region statistics".red(), e),
+    }
+}
+
+fn copy_selection(selection: Rect, pixel_data: Vec<Vec<Pixel>>) {
+    let bytes = pixel_data
+        .iter()
+        .flatten()
+        .flat_map(|p| [p.r, p.g, p.b, p.a])
+        .collect::<Vec<u8>>();
+
+    let image = arboard::ImageData {
+        width: selection.width as usize,
+        height: selection.height as usize,
+        bytes: Cow::Owned(bytes),
+    };
+
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_image(image)) {
+        Ok(_) => println!("{} {}x{}", "Copied selection".green(), selection.width, selection.height),
+        Err(e) => eprintln!("{}: {}", "Failed to copy selection".red(), e),
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::Colorize;
+use crate::options::Options;
+use crate::png_reader::PngReader;
+use crate::png_writer::PngWriter;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Rewrites the file, or writes a copy to `--out`, with the tEXt, tIME and pHYs changes from
+/// `--set-text`, `--remove-text`, `--touch-time` and `--set-dpi` and the chunks of `--add-chunk`.
+/// Every other chunk, the image data included, is copied byte for byte. A set keyword replaces
+/// the first tEXt chunk with that keyword and drops any others; new keywords, and a new tIME or
+/// pHYs, go before the image data, and added chunks just before IEND.
+pub fn rewrite(image_path: &str, reader: &PngReader, options: &Options) -> Result<(), String> {
+    let mut texts = options.set_text
+        .iter()
+        .map(|(keyword, text)| Ok((keyword.as_str(), text_chunk(keyword, text)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut time = options.touch_time.then(now);
+    let mut phys = options.set_dpi.map(phys);
+
+    let added = options.add_chunk
+        .iter()
+        .map(|(chunk_type, path)| Ok((chunk_type, fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut bytes = SIGNATURE.to_vec();
+
+    for chunk in reader.chunks() {
+        let data = reader.chunk_data(chunk);
+
+        match chunk.chunk_type.as_str() {
+            "tEXt" => {
+                let keyword = data.split(|&b| b == 0).next().unwrap_or_default();
+                let keyword = latin1(keyword);
+
+                if options.remove_text.contains(&keyword) {
+                    println!("Removed {}", keyword);
+                    continue;
+                }
+
+                match options.set_text.iter().any(|(k, _)| *k == keyword) {
+                    true => {
+                        if let Some(i) = texts.iter().position(|(k, _)| *k == keyword) {
+                            println!("Set {}", keyword);
+                            PngWriter::write_chunk(&mut bytes, b"tEXt", &texts.remove(i).1);
+                        }
+                    }
+                    false => bytes.extend_from_slice(reader.chunk_bytes(chunk)),
+                }
+            }
+            "tIME" if options.touch_time => {
+                if let Some(time) = time.take() {
+                    PngWriter::write_chunk(&mut bytes, b"tIME", &time);
+                }
+            }
+            "pHYs" if options.set_dpi.is_some() => {
+                if let Some(phys) = phys.take() {
+                    PngWriter::write_chunk(&mut bytes, b"pHYs", &phys);
+                }
+            }
+            "IDAT" if !texts.is_empty() || time.is_some() || phys.is_some() => {
+                for (keyword, text) in texts.drain(..) {
+                    println!("Added {}", keyword);
+                    PngWriter::write_chunk(&mut bytes, b"tEXt", &text);
+                }
+
+                if let Some(time) = time.take() {
+                    PngWriter::write_chunk(&mut bytes, b"tIME", &time);
+                }
+
+                if let Some(phys) = phys.take() {
+                    PngWriter::write_chunk(&mut bytes, b"pHYs", &phys);
+                }
+
+                bytes.extend_from_slice(reader.chunk_bytes(chunk));
+            }
+            "IEND" => {
+                for (chunk_type, data) in &added {
+                    println!("Added {} ({} bytes)", String::from_utf8_lossy(&chunk_type[..]), data.len());
+                    PngWriter::write_chunk(&mut bytes, chunk_type, data);
+                }
+
+                bytes.extend_from_slice(reader.chunk_bytes(chunk));
+            }
+            _ => bytes.extend_from_slice(reader.chunk_bytes(chunk)),
+        }
+    }
+
+    if options.touch_time {
+        println!("Set the modification time");
+    }
+
+    if let Some(dpi) = options.set_dpi {
+        println!("Set the density to {} DPI", dpi);
+    }
+
+    let output = options.out.as_deref().unwrap_or(Path::new(image_path));
+
+    fs::write(output, &bytes).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{} {} ({} bytes)", "Wrote".green(), output.display(), bytes.len());
+
+    Ok(())
+}
+
+/// tEXt data: a keyword of 1 to 79 Latin-1 characters, a null separator and Latin-1 text.
+fn text_chunk(keyword: &str, text: &str) -> Result<Vec<u8>, String> {
+    let encode = |s: &str| s.chars().map(|c| u8::try_from(c as u32).ok()).collect::<Option<Vec<u8>>>();
+
+    let keyword_bytes = encode(keyword).filter(|k| (1..=79).contains(&k.len()) && !k.contains(&0));
+    let keyword_bytes = keyword_bytes.ok_or_else(|| format!("Invalid keyword \"{}\": tEXt keywords are 1 to 79 Latin-1 characters", keyword))?;
+    let text_bytes = encode(text).filter(|t| !t.contains(&0)).ok_or_else(|| format!("The text for {} isn't Latin-1", keyword))?;
+
+    Ok([keyword_bytes, vec![0], text_bytes].concat())
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// pHYs data for `dpi` dots per inch, as the chunk can only say in pixels per metre.
+pub fn phys(dpi: f64) -> Vec<u8> {
+    let pixels_per_metre = ((dpi / 0.0254).round() as u32).to_be_bytes();
+
+    // the same density across and down, and the unit is the metre
+    [&pixels_per_metre[..], &pixels_per_metre, &[1]].concat()
+}
+
+/// tIME data for the current time in UTC.
+fn now() -> [u8; 7] {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // days since 1970-01-01 to a civil date, after Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    let year = (year as u16).to_be_bytes();
+
+    [year[0], year[1], month as u8, day as u8, (seconds / 3600) as u8, (seconds / 60 % 60) as u8, (seconds % 60) as u8]
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::Path;
+use colored::Colorize;
+use crate::font;
+use crate::options::Options;
+use crate::png_reader::Pixel;
+use crate::png_writer::PngWriter;
+use crate::resize;
+use crate::transform;
+
+/// Space around and between tiles.
+const GAP: usize = 8;
+/// Space between a tile's image and its label.
+const LABEL_GAP: usize = 4;
+const LABEL_COLOUR: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// Lays the images out in a grid of `columns` (by default about as many as rows) and writes it
+/// to `output` as a contact sheet. Each image is scaled down to fit a `tile` pixel square, centred
+/// in it, composited over the background and labelled with its file name underneath.
+pub fn run(image_paths: &[String], columns: Option<usize>, tile: u32, options: &Options, output: &Path) -> Result<(), String> {
+    if image_paths.is_empty() {
+        return Err("No images to lay out".to_string());
+    }
+
+    let columns = columns.unwrap_or_else(|| (image_paths.len() as f64).sqrt().ceil() as usize).clamp(1, image_paths.len());
+    let rows = image_paths.len().div_ceil(columns);
+    let tile = tile as usize;
+
+    let cell_width = tile + GAP;
+    let cell_height = tile + LABEL_GAP + font::GLYPH_HEIGHT + GAP;
+    let (width, height) = (GAP + columns * cell_width, GAP + rows * cell_height);
+
+    let mut frame = [options.background[0], options.background[1], options.background[2], 0xFF].repeat(width * height);
+
+    for (i, image_path) in image_paths.iter().enumerate() {
+        let (x, y) = (GAP + i % columns * cell_width, GAP + i / columns * cell_height);
+        let thumbnail = thumbnail(image_path, tile, options)?;
+        let (thumbnail_width, thumbnail_height) = (thumbnail[0].len(), thumbnail.len());
+
+        // centred horizontally, and at the bottom of the tile so labels line up under images
+        let left = x + (tile - thumbnail_width) / 2;
+        let top = y + tile - thumbnail_height;
+
+        for (ty, row) in thumbnail.iter().enumerate() {
+            for (tx, pixel) in row.iter().enumerate() {
+                let idx = ((top + ty) * width + left + tx) * 4;
+                let alpha = pixel.a as u32;
+
+                for (c, value) in [pixel.r, pixel.g, pixel.b].into_iter().enumerate() {
+                    frame[idx + c] = ((value as u32 * alpha + frame[idx + c] as u32 * (255 - alpha)) / 255) as u8;
+                }
+            }
+        }
+
+        let label = label(image_path, tile);
+        let label_x = x + (tile - font::text_width(&label, 1)) / 2;
+        font::draw_text(&mut frame, width, label_x, y + tile + LABEL_GAP, &label, LABEL_COLOUR, 1);
+    }
+
+    let pixel_data = frame
+        .chunks_exact(width * 4)
+        .map(|row| row.chunks_exact(4).map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] }).collect())
+        .collect();
+
+    let bytes = PngWriter::new(width as u32, height as u32, pixel_data).write();
+
+    fs::write(output, &bytes).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{} {} images, {} columns, {}x{} -> {}", "Montaged".green(), image_paths.len(), columns, width, height, output.display());
+
+    Ok(())
+}
+
+/// The upright image, scaled down with `--resize-filter` to fit in a `tile` pixel square if it
+/// doesn't already.
+fn thumbnail(image_path: &str, tile: usize, options: &Options) -> Result<Vec<Vec<Pixel>>, String> {
+    let mut reader = crate::open_png(image_path, options).map_err(|e| format!("{}: {}", image_path, e))?;
+    reader.decode_image_data().map_err(|e| format!("{}: {}", image_path, e))?;
+
+    let pixel_data = transform::orient(&reader.pixel_data, crate::orientation(&reader, options));
+    let (width, height) = (pixel_data[0].len(), pixel_data.len());
+
+    if width <= tile && height <= tile {
+        return Ok(pixel_data);
+    }
+
+    let scale = tile as f64 / width.max(height) as f64;
+    let size = |n: usize| ((n as f64 * scale).round() as u32).clamp(1, tile as u32);
+
+    Ok(resize::resize(&pixel_data, size(width), size(height), options.resize_filter))
+}
+
+/// The file name, shortened with an ellipsis to fit under a tile.
+fn label(image_path: &str, tile: usize) -> String {
+    let name = Path::new(image_path).file_name().map_or_else(|| image_path.into(), |name| name.to_string_lossy());
+    let fits = tile / font::GLYPH_WIDTH;
+
+    match name.chars().count() {
+        count if count <= fits => name.into_owned(),
+        _ => name.chars().take(fits.saturating_sub(3)).chain("...".chars()).take(fits).collect(),
+    }
+}
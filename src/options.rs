@@ -0,0 +1,756 @@
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use crate::config::Config;
+use crate::gallery::SortOrder;
+use crate::keys::KeyBindings;
+use crate::png_reader::Dither;
+use crate::resize::{Resize, ResizeFilter};
+use crate::transform::Flip;
+use crate::viewer::{AlphaView, Rect, ScalingFilter, Simulation};
+
+/// How big the window opens relative to the image.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Scale {
+    Factor(f64),
+    /// As large as fits on the monitor, but no larger than the image.
+    Fit,
+}
+
+impl Scale {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "fit" => Some(Self::Fit),
+            _ => parse_positive(text).map(Self::Factor),
+        }
+    }
+}
+
+/// Pixel layout written by `--raw-out`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RawFormat {
+    Rgba8,
+    Rgb8,
+}
+
+impl RawFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "rgba8" => Some(Self::Rgba8),
+            "rgb8" => Some(Self::Rgb8),
+            _ => None,
+        }
+    }
+}
+
+pub struct Options {
+    /// Image files given on the command line.
+    pub paths: Vec<String>,
+    /// `diff a.png b.png`: show two images side by side.
+    pub compare: bool,
+    /// Rotate/flip according to the eXIf Orientation tag; `--no-exif-orientation` shows the stored pixels as-is.
+    pub exif_orientation: bool,
+    /// `--tui`: browse the chunks and a preview in the terminal instead of opening a window.
+    pub tui: bool,
+    /// `selftest`: check the decoder against the embedded PngSuite corpus instead of opening a window.
+    pub selftest: bool,
+    /// `assemble`: build an APNG from the image files, in order, instead of opening a window.
+    pub assemble: bool,
+    /// `assemble --delay MS`: how long each frame shows for.
+    pub delay: u16,
+    /// `--out PATH`: where `assemble` writes the APNG, `montage` the contact sheet and `--slice`
+    /// the tiles.
+    pub out: Option<PathBuf>,
+    /// `montage`: lay the images out in a labelled grid instead of opening a window.
+    pub montage: bool,
+    /// `montage --columns N`: images per row.
+    pub columns: Option<usize>,
+    /// `montage --tile PIXELS`: size of the square each image is scaled down to fit.
+    pub tile: u32,
+    /// `convert IN OUT`: convert an animation between GIF and APNG instead of opening a window.
+    pub convert: bool,
+    /// `check`: report on the files' chunks in pngcheck's format instead of opening a window.
+    pub check: bool,
+    /// `hex`: explore the file's bytes, annotated with its structure, in the terminal instead of
+    /// opening a window.
+    pub hex: bool,
+    /// `info`: print the files' chunks and a summary instead of opening a window.
+    pub info: bool,
+    /// `--bookmarks`: pick an image to open from the bookmarked ones.
+    pub bookmarks: bool,
+    /// `--recent`: pick an image to open from the recently viewed ones.
+    pub recent: bool,
+    /// `--render out.png`: write what the window would show to a file instead of opening it.
+    pub render: Option<PathBuf>,
+    /// `--wallpaper`: show the image as the desktop background on Wayland instead of in a window.
+    pub wallpaper: bool,
+    /// `--raw-out rgba8|rgb8`: write the decoded pixels to stdout, and the size to stderr,
+    /// instead of opening a window.
+    pub raw_out: Option<RawFormat>,
+    /// `--premultiply`: multiply colours by alpha in what `--raw-out`, `--export` and `convert` write.
+    pub premultiply: bool,
+    /// `--to-srgb`: with `--export`, convert colours from the colour space the file declares, by
+    /// cICP, iCCP or gAMA and cHRM, to sRGB and write an sRGB-tagged file.
+    pub to_srgb: bool,
+    /// `--stats`: print compression and filter statistics after decoding.
+    pub stats: bool,
+    /// `--timings`: print to stderr how long reading the file, parsing its chunks, inflating,
+    /// unfiltering and the first render took.
+    pub timings: bool,
+    /// `--mem-stats`: print to stderr the size of the decoder's buffers at their largest, and with
+    /// the `heap-stats` feature the most memory allocated at once, after decoding.
+    pub mem_stats: bool,
+    /// `--analyze-deflate`: report the deflate blocks of the image data instead of opening a window.
+    pub analyze_deflate: bool,
+    /// `--recompress`: report how much smaller re-deflating the image data would make the file
+    /// instead of opening a window.
+    pub recompress: bool,
+    /// `--zopfli`: also try zopfli with `--recompress`, which is much slower.
+    pub zopfli: bool,
+    /// `--write`: with `--recompress`, rewrite the file if that makes it smaller.
+    pub write: bool,
+    /// `--extract-frames DIR`: write every composited APNG frame to DIR instead of opening a window.
+    pub extract_frames: Option<PathBuf>,
+    /// `--to-gif OUT`: write the animation, quantised, as an animated GIF instead of opening a window.
+    pub to_gif: Option<PathBuf>,
+    /// `--to-video OUT`: encode the animation over the background as a video with ffmpeg
+    /// instead of opening a window.
+    pub to_video: Option<PathBuf>,
+    /// `--report OUT`: write an HTML page about the file, with a preview, its chunks, histograms
+    /// and the check's findings, instead of opening a window.
+    pub report: Option<PathBuf>,
+    /// `--export OUT` (or `--save`): write the decoded image, with the edits below, as a PNG
+    /// instead of opening a window.
+    pub export: Option<PathBuf>,
+    /// `--crop X,Y,WxH`: with `--export`, keep only that part of the image.
+    pub crop: Option<Rect>,
+    /// `--extract-alpha OUT`: write the alpha channel as a greyscale PNG instead of opening a window.
+    pub extract_alpha: Option<PathBuf>,
+    /// `--extract-idat OUT`: write the image's zlib stream, its IDAT chunks joined, instead of
+    /// opening a window.
+    pub extract_idat: Option<PathBuf>,
+    /// `--extract-raw OUT`: write the inflated scanlines, filter bytes and all, instead of opening
+    /// a window.
+    pub extract_raw: Option<PathBuf>,
+    /// `--apply-alpha MASK`: with `--export`, replace the alpha channel with a greyscale mask of
+    /// the same size, before rotating or cropping.
+    pub apply_alpha: Option<PathBuf>,
+    /// `--slice WxH`: cut the image into tiles of that size and write each to a PNG in the
+    /// `--out` directory instead of opening a window.
+    pub slice: Option<(u32, u32)>,
+    /// `--skip-transparent`: with `--slice`, don't write tiles with nothing but transparent pixels.
+    pub skip_transparent: bool,
+    /// `--rotate 90|180|270`: with `--export` or `convert`, turn the image clockwise, before
+    /// cropping. 0 leaves it alone.
+    pub rotate: u16,
+    /// `--flip h|v`: with `--export` or `convert`, mirror the image after rotating it.
+    pub flip: Option<Flip>,
+    /// `--resize WxH|Wx|xH|N%`: with `--export`, scale the image to that size, after cropping.
+    pub resize: Option<Resize>,
+    /// `--resize-filter box|bilinear|lanczos`: how `--resize` resamples, Lanczos by default.
+    pub resize_filter: ResizeFilter,
+    /// `--set-text KEY=VALUE`, repeatable: rewrite the file with a tEXt chunk setting KEY.
+    pub set_text: Vec<(String, String)>,
+    /// `--remove-text KEY`, repeatable: rewrite the file without the tEXt chunks for KEY.
+    pub remove_text: Vec<String>,
+    /// `--touch-time`: rewrite the file with its tIME set to now.
+    pub touch_time: bool,
+    /// `--set-dpi N`: give the file a pHYs chunk of N dots per inch, rewriting it in place, or
+    /// in what `--export` writes.
+    pub set_dpi: Option<f64>,
+    /// `--add-chunk TYPE=FILE`, repeatable: rewrite the file, or write a copy to `--out`, with a
+    /// chunk of that type holding the file's bytes just before IEND.
+    pub add_chunk: Vec<([u8; 4], PathBuf)>,
+    /// `--background COLOUR`: colour around the image.
+    pub background: [u8; 4],
+    /// `--filter nearest|linear|bicubic`: how the image is sampled when zoomed.
+    pub filter: ScalingFilter,
+    /// `--simulate protanopia|deuteranopia|tritanopia`: show the image as seen with that colour
+    /// vision deficiency.
+    pub simulation: Option<Simulation>,
+    /// `--invert`: show the image's colours inverted, keeping its alpha.
+    pub invert: bool,
+    /// `--swap-rb`: show red as blue and blue as red, to spot BGR data read as RGB.
+    pub swap_red_blue: bool,
+    /// `--linear`: treat the samples as linear light rather than sRGB-encoded.
+    pub linear_samples: bool,
+    /// `--alpha-view greyscale|partial`: show the alpha channel instead of the image, as grey or
+    /// with partial alpha picked out in colour.
+    pub alpha_view: Option<AlphaView>,
+    /// `--clipping`: stripe pure white pixels red and pure black ones blue.
+    pub clipping: bool,
+    /// `--dither none|ordered|diffusion`: how 16-bit samples are reduced to 8 bits, ordered by default.
+    pub dither: Dither,
+    /// `--zoom N`: startup zoom, 1.0 showing one image pixel per logical pixel.
+    pub zoom: f64,
+    /// `--scale N|fit`: startup window size relative to the zoomed image, the image being scaled with it.
+    /// None when not given, which is a factor of 1 unless the saved window size is restored.
+    pub scale: Option<Scale>,
+    /// `--speed N`: play animations N times as fast.
+    pub speed: f64,
+    /// `--slideshow SECONDS`: time each image is shown for in a slideshow.
+    pub slideshow_interval: Duration,
+    /// `--position X,Y`: outer position of the window in physical pixels, relative to `--monitor`
+    /// if given and otherwise to the desktop.
+    pub position: Option<(i32, i32)>,
+    /// `--monitor N`: open on the Nth monitor, counting from 1; centred unless `--position` is given.
+    pub monitor: Option<usize>,
+    /// `--lock-views`: mirror pan and zoom with other windows that have their views locked.
+    pub lock_views: bool,
+    /// `--watch`: reload the image whenever its file changes, e.g. when a render replaces it.
+    pub watch: bool,
+    /// `--recursive`: open every image beneath the directories given, stepping through them in
+    /// the window with an index showing which is shown.
+    pub recursive: bool,
+    /// `--sort name|mtime|size|random`: the order to step through the images in, instead of the
+    /// order given.
+    pub sort: Option<SortOrder>,
+    /// `--reverse`: step through the images in reverse order.
+    pub reverse: bool,
+    /// `--always-on-top`: keep the window above other windows.
+    pub always_on_top: bool,
+    /// `--borderless`: open the window without decorations. Dragging the image then moves the
+    /// window, and selecting takes Ctrl.
+    pub borderless: bool,
+    /// `--transparent`: make the window see-through wherever the image is, so its alpha shows
+    /// the desktop behind.
+    pub transparent: bool,
+    /// `--click-through`: let clicks and the cursor pass through the window to whatever is behind
+    /// it, and don't take focus on opening, for using the image as an overlay.
+    pub click_through: bool,
+    /// `--display-profile disp.icc`: ICC profile of the display, to convert colours into.
+    pub display_profile: Option<PathBuf>,
+    /// `--display-p3`: the display is Display P3 rather than sRGB. Ignored with `--display-profile`.
+    pub display_p3: bool,
+    pub key_bindings: KeyBindings,
+}
+
+/// The command line, parsed into `Options` on top of the config file.
+#[derive(Parser)]
+#[command(name = "png-viewer", version, about = "A PNG viewer and toolbox")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Images to open, the same as `view`
+    paths: Vec<String>,
+    #[command(flatten)]
+    flags: Flags,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Open images in a window, stepping through them in order (the default)
+    View {
+        /// Images, or directories with --recursive
+        paths: Vec<String>,
+    },
+    /// Print each file's chunks and a summary
+    Info {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Report on each file's chunks in pngcheck's format
+    Check {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Explore the file's bytes in the terminal, coloured by chunk field
+    Hex {
+        path: String,
+    },
+    /// Convert an animation between GIF and APNG, by the output's extension
+    Convert {
+        input: String,
+        output: String,
+    },
+    /// Show two images side by side
+    Diff {
+        a: String,
+        b: String,
+    },
+    /// Build an APNG from still images of one size, a frame each in the order given
+    Assemble {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// How long each frame shows for, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 100)]
+        delay: u16,
+    },
+    /// Lay images out in a labelled grid, as a contact sheet
+    Montage {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Images per row, by default about as many as there are rows
+        #[arg(long, value_name = "N", value_parser = parser(|text| text.parse().ok().filter(|&n: &usize| n > 0), "a number from 1"))]
+        columns: Option<usize>,
+        /// Size of the square each image is scaled down to fit
+        #[arg(long, value_name = "PIXELS", value_parser = parser(|text| text.parse().ok().filter(|&n: &u32| n > 0), "a number from 1"), default_value = "160")]
+        tile: u32,
+    },
+    /// Check the decoder against a PngSuite-style corpus
+    Selftest,
+    /// Print a completion script for the shell to stdout
+    Completions {
+        shell: Shell,
+    },
+}
+
+/// Options taken by every subcommand. Those that default to a config file setting are None
+/// when not given.
+#[derive(Args)]
+struct Flags {
+    /// The same as `check`, for scripts written for pngcheck
+    #[arg(long, global = true, hide = true)]
+    check: bool,
+    /// The same as `diff A B`
+    #[arg(long, global = true, hide = true, num_args = 2, value_names = ["A", "B"])]
+    compare: Option<Vec<String>>,
+    /// Pick an image to open from the bookmarked ones, added with Ctrl+B in the window
+    #[arg(long, global = true)]
+    bookmarks: bool,
+    /// Pick an image to open from the recently viewed ones
+    #[arg(long, global = true)]
+    recent: bool,
+    /// Open every image beneath the directories given
+    #[arg(long, global = true)]
+    recursive: bool,
+    /// Order to step through the images in
+    #[arg(long, global = true, value_parser = choices(&["name", "mtime", "size", "random"], SortOrder::parse))]
+    sort: Option<SortOrder>,
+    /// Step through the images in reverse order
+    #[arg(long, global = true)]
+    reverse: bool,
+    /// Show the stored pixels as they are, ignoring the eXIf orientation
+    #[arg(long, global = true)]
+    no_exif_orientation: bool,
+    /// Browse the chunks and a preview in the terminal
+    #[arg(long, global = true)]
+    tui: bool,
+    /// Write what the window would show to a PNG instead of opening it
+    #[arg(long, global = true, value_name = "OUT")]
+    render: Option<PathBuf>,
+    /// Show the image as the desktop background on Wayland
+    #[arg(long, global = true)]
+    wallpaper: bool,
+    /// Write the decoded pixels to stdout, and the size to stderr
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = choices(&["rgba8", "rgb8"], RawFormat::parse))]
+    raw_out: Option<RawFormat>,
+    /// Multiply colours by alpha in what --raw-out, --export and convert write
+    #[arg(long, global = true)]
+    premultiply: bool,
+    /// With --export, convert colours from the colour space the file declares to sRGB
+    #[arg(long, global = true)]
+    to_srgb: bool,
+    /// Print compression and filter statistics after decoding
+    #[arg(long, global = true)]
+    stats: bool,
+    /// Print how long each stage of reading, decoding and the first render took
+    #[arg(long, global = true)]
+    timings: bool,
+    /// Print the peak size of the compressed, inflated and pixel buffers
+    #[arg(long, global = true)]
+    mem_stats: bool,
+    /// Report the deflate blocks of the image data
+    #[arg(long, global = true)]
+    analyze_deflate: bool,
+    /// Report how much smaller re-deflating the image data would make the file
+    #[arg(long, global = true)]
+    recompress: bool,
+    /// Also try zopfli with --recompress, which is much slower
+    #[arg(long, global = true)]
+    zopfli: bool,
+    /// With --recompress, rewrite the file if that makes it smaller
+    #[arg(long, global = true)]
+    write: bool,
+    /// Write every composited APNG frame to a directory
+    #[arg(long, global = true, value_name = "DIR")]
+    extract_frames: Option<PathBuf>,
+    /// Write the animation as an animated GIF
+    #[arg(long, global = true, value_name = "OUT")]
+    to_gif: Option<PathBuf>,
+    /// Encode the animation over the background as a video with ffmpeg
+    #[arg(long, global = true, value_name = "OUT")]
+    to_video: Option<PathBuf>,
+    /// Write an HTML report on the file: preview, metadata, chunks, histograms and check results
+    #[arg(long, global = true, value_name = "OUT")]
+    report: Option<PathBuf>,
+    /// Write the decoded image, with any edits, as a PNG
+    #[arg(long, global = true, visible_alias = "save", value_name = "OUT")]
+    export: Option<PathBuf>,
+    /// With --export or convert, turn the image clockwise, before cropping
+    #[arg(long, global = true, value_name = "DEGREES", value_parser = choices(&["90", "180", "270"], |degrees| degrees.parse::<u16>().ok()))]
+    rotate: Option<u16>,
+    /// With --export or convert, mirror the image horizontally or vertically, after rotating
+    #[arg(long, global = true, value_parser = choices(&["h", "v"], Flip::parse))]
+    flip: Option<Flip>,
+    /// With --export, keep only that part of the image
+    #[arg(long, global = true, value_name = "X,Y,WxH", value_parser = parser(parse_crop, "X,Y,WxH"))]
+    crop: Option<Rect>,
+    /// With --export, scale the image to WxH, to Wx or xH keeping its aspect ratio, or by N%
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parser(Resize::parse, "WxH, Wx, xH or N%"))]
+    resize: Option<Resize>,
+    /// How --resize resamples
+    #[arg(long, global = true, value_name = "FILTER", value_parser = choices(&["box", "bilinear", "lanczos"], ResizeFilter::parse), default_value = "lanczos")]
+    resize_filter: ResizeFilter,
+    /// Write the alpha channel as a greyscale PNG
+    #[arg(long, global = true, value_name = "OUT")]
+    extract_alpha: Option<PathBuf>,
+    /// Write the zlib stream of the IDAT chunks joined together
+    #[arg(long, global = true, value_name = "OUT")]
+    extract_idat: Option<PathBuf>,
+    /// Write the inflated image data, still filtered, with each scanline's filter type byte
+    #[arg(long, global = true, value_name = "OUT")]
+    extract_raw: Option<PathBuf>,
+    /// With --export, replace the alpha channel with a greyscale mask of the same size
+    #[arg(long, global = true, value_name = "MASK")]
+    apply_alpha: Option<PathBuf>,
+    /// Where assemble writes the APNG, montage the contact sheet and --slice the tiles
+    #[arg(long, global = true, value_name = "PATH")]
+    out: Option<PathBuf>,
+    /// Cut the image into tiles of this size, written to the --out directory
+    #[arg(long, global = true, value_name = "WxH", value_parser = parser(parse_size, "WxH"), requires = "out")]
+    slice: Option<(u32, u32)>,
+    /// With --slice, don't write tiles with nothing but transparent pixels
+    #[arg(long, global = true)]
+    skip_transparent: bool,
+    /// Rewrite the file with a tEXt chunk setting KEY
+    #[arg(long, global = true, value_name = "KEY=VALUE", value_parser = parser(parse_text, "KEY=VALUE"))]
+    set_text: Vec<(String, String)>,
+    /// Rewrite the file without the tEXt chunks for KEY
+    #[arg(long, global = true, value_name = "KEY")]
+    remove_text: Vec<String>,
+    /// Rewrite the file with its tIME set to now
+    #[arg(long, global = true)]
+    touch_time: bool,
+    /// Set the pHYs density to N dots per inch, in the file or in what --export writes
+    #[arg(long, global = true, value_name = "N", value_parser = parser(|text| text.parse().ok().filter(|&dpi: &f64| dpi > 0.0), "a positive number"))]
+    set_dpi: Option<f64>,
+    /// Add a chunk of an ancillary TYPE holding FILE's bytes, in the file or in a copy at --out
+    #[arg(long, global = true, value_name = "TYPE=FILE", value_parser = parse_added_chunk)]
+    add_chunk: Vec<([u8; 4], PathBuf)>,
+    /// Colour around the image, #rgb or #rrggbb
+    #[arg(long, global = true, value_name = "COLOUR", value_parser = parser(parse_colour, "#rgb or #rrggbb"))]
+    background: Option<[u8; 4]>,
+    /// How the image is sampled when zoomed
+    #[arg(long, global = true, value_parser = choices(&["nearest", "linear", "bicubic"], ScalingFilter::parse))]
+    filter: Option<ScalingFilter>,
+    /// Show the image as seen with a colour vision deficiency
+    #[arg(long, global = true, value_parser = choices(&["protanopia", "deuteranopia", "tritanopia"], Simulation::parse))]
+    simulate: Option<Simulation>,
+    /// Show the image's colours inverted, keeping its alpha
+    #[arg(long, global = true)]
+    invert: bool,
+    /// Show red as blue and blue as red
+    #[arg(long, global = true)]
+    swap_rb: bool,
+    /// Treat the samples as linear light rather than sRGB-encoded
+    #[arg(long, global = true)]
+    linear: bool,
+    /// Show the alpha channel instead of the image
+    #[arg(long, global = true, value_parser = choices(&["greyscale", "partial"], AlphaView::parse))]
+    alpha_view: Option<AlphaView>,
+    /// Stripe pure white pixels red and pure black ones blue
+    #[arg(long, global = true)]
+    clipping: bool,
+    /// How 16-bit samples are reduced to 8 bits
+    #[arg(long, global = true, value_parser = choices(&["none", "ordered", "diffusion"], Dither::parse), default_value = "ordered")]
+    dither: Dither,
+    /// Startup zoom, 1 showing one image pixel per logical pixel
+    #[arg(long, global = true, value_name = "N", value_parser = parser(parse_positive, "a positive number"))]
+    zoom: Option<f64>,
+    /// Startup window size relative to the zoomed image
+    #[arg(long, global = true, value_name = "N|fit", value_parser = parser(Scale::parse, "a positive number or fit"))]
+    scale: Option<Scale>,
+    /// Play animations N times as fast
+    #[arg(long, global = true, value_name = "N", value_parser = parser(parse_positive, "a positive number"), default_value = "1")]
+    speed: f64,
+    /// Time each image is shown for in a slideshow
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = parser(parse_positive, "a positive number"))]
+    slideshow: Option<f64>,
+    /// Outer position of the window in physical pixels
+    #[arg(long, global = true, value_name = "X,Y", value_parser = parser(parse_position, "X,Y"))]
+    position: Option<(i32, i32)>,
+    /// Open on the Nth monitor, counting from 1
+    #[arg(long, global = true, value_name = "N", value_parser = parser(|text| text.parse().ok().filter(|&n: &usize| n > 0), "a number from 1"))]
+    monitor: Option<usize>,
+    /// Mirror pan and zoom with other windows that have their views locked
+    #[arg(long, global = true)]
+    lock_views: bool,
+    /// Reload the image whenever its file changes
+    #[arg(long, global = true)]
+    watch: bool,
+    /// Keep the window above other windows
+    #[arg(long, global = true)]
+    always_on_top: bool,
+    /// Open the window without decorations; drag the image to move it, and Ctrl+drag to select
+    #[arg(long, global = true)]
+    borderless: bool,
+    /// Show the desktop through the image's transparent pixels and around it (needs a compositor; drawn on the CPU)
+    #[arg(long, global = true)]
+    transparent: bool,
+    /// Pass clicks through the window to the applications behind it, e.g. with --transparent and --always-on-top to trace over them
+    #[arg(long, global = true)]
+    click_through: bool,
+    /// The display is Display P3 rather than sRGB
+    #[arg(long, global = true)]
+    display_p3: bool,
+    /// ICC profile of the display, to convert colours into
+    #[arg(long, global = true, value_name = "ICC")]
+    display_profile: Option<PathBuf>,
+}
+
+impl Options {
+    /// Parses the command line on top of the defaults from the config file. Prints the
+    /// completion script and exits for `completions`.
+    pub fn parse() -> Self {
+        let config = Config::load();
+        let Cli { command, paths, flags } = Cli::parse();
+
+        let mut options = Self {
+            paths,
+            compare: false,
+            exif_orientation: !flags.no_exif_orientation,
+            tui: flags.tui,
+            selftest: false,
+            assemble: false,
+            delay: 100,
+            out: flags.out,
+            montage: false,
+            columns: None,
+            tile: 160,
+            convert: false,
+            check: false,
+            hex: false,
+            info: false,
+            bookmarks: flags.bookmarks,
+            recent: flags.recent,
+            render: flags.render,
+            wallpaper: flags.wallpaper,
+            raw_out: flags.raw_out,
+            premultiply: flags.premultiply,
+            to_srgb: flags.to_srgb,
+            stats: flags.stats,
+            timings: flags.timings,
+            mem_stats: flags.mem_stats,
+            analyze_deflate: flags.analyze_deflate,
+            recompress: flags.recompress,
+            zopfli: flags.zopfli,
+            write: flags.write,
+            extract_frames: flags.extract_frames,
+            to_gif: flags.to_gif,
+            to_video: flags.to_video,
+            export: flags.export,
+            crop: flags.crop,
+            extract_alpha: flags.extract_alpha,
+            report: flags.report,
+            extract_idat: flags.extract_idat,
+            extract_raw: flags.extract_raw,
+            apply_alpha: flags.apply_alpha,
+            slice: flags.slice,
+            skip_transparent: flags.skip_transparent,
+            rotate: flags.rotate.unwrap_or(0),
+            flip: flags.flip,
+            resize: flags.resize,
+            resize_filter: flags.resize_filter,
+            set_text: flags.set_text,
+            remove_text: flags.remove_text,
+            touch_time: flags.touch_time,
+            set_dpi: flags.set_dpi,
+            add_chunk: flags.add_chunk,
+            background: flags.background.unwrap_or_else(|| config.background.map_or([0x20, 0x20, 0x20, 0xFF], |c| parse_colour(&c).expect("Config::load checks the background"))),
+            filter: flags.filter.unwrap_or_else(|| config.filter.map_or(ScalingFilter::Nearest, |f| ScalingFilter::parse(&f).expect("Config::load checks the filter"))),
+            simulation: flags.simulate,
+            invert: flags.invert,
+            swap_red_blue: flags.swap_rb,
+            linear_samples: flags.linear,
+            alpha_view: flags.alpha_view,
+            clipping: flags.clipping,
+            dither: flags.dither,
+            zoom: flags.zoom.or(config.zoom).unwrap_or(1.0),
+            scale: flags.scale,
+            speed: flags.speed,
+            slideshow_interval: Duration::from_secs_f64(flags.slideshow.or(config.slideshow_interval).unwrap_or(5.0)),
+            position: flags.position,
+            monitor: flags.monitor,
+            lock_views: flags.lock_views,
+            watch: flags.watch,
+            recursive: flags.recursive,
+            sort: flags.sort,
+            reverse: flags.reverse,
+            always_on_top: flags.always_on_top,
+            borderless: flags.borderless,
+            transparent: flags.transparent,
+            click_through: flags.click_through,
+            display_profile: flags.display_profile.or(config.display_profile),
+            display_p3: flags.display_p3 || config.display_p3.unwrap_or(false),
+            key_bindings: KeyBindings::new(&config.keys).expect("Config::load checks the keys"),
+        };
+
+        match command {
+            None => (),
+            Some(Command::View { paths }) => options.paths.extend(paths),
+            Some(Command::Info { paths }) => {
+                options.info = true;
+                options.paths = paths;
+            }
+            Some(Command::Check { paths }) => {
+                options.check = true;
+                options.paths = paths;
+            }
+            Some(Command::Hex { path }) => {
+                options.hex = true;
+                options.paths = vec![path];
+            }
+            Some(Command::Convert { input, output }) => {
+                options.convert = true;
+                options.paths = vec![input, output];
+            }
+            Some(Command::Diff { a, b }) => {
+                options.compare = true;
+                options.paths = vec![a, b];
+            }
+            Some(Command::Assemble { paths, delay }) => {
+                options.assemble = true;
+                options.paths = paths;
+                options.delay = delay;
+            }
+            Some(Command::Montage { paths, columns, tile }) => {
+                options.montage = true;
+                options.paths = paths;
+                options.columns = columns;
+                options.tile = tile;
+            }
+            Some(Command::Selftest) => options.selftest = true,
+            Some(Command::Completions { shell }) => {
+                clap_complete::generate(shell, &mut Cli::command(), "png-viewer", &mut io::stdout());
+                process::exit(0);
+            }
+        }
+
+        // the flags these subcommands replaced
+        if flags.check {
+            options.check = true;
+        }
+
+        if let Some(paths) = flags.compare {
+            options.compare = true;
+            options.paths = paths;
+        }
+
+        // --out is global, for --slice too, so clap can't require it of these two alone
+        if (options.assemble || options.montage) && options.out.is_none() {
+            usage_error(Some(if options.assemble { "assemble" } else { "montage" }), ErrorKind::MissingRequiredArgument, "the following required arguments were not provided:\n  --out <PATH>");
+        }
+
+        if options.check && options.paths.is_empty() {
+            usage_error(Some("check"), ErrorKind::MissingRequiredArgument, "the following required arguments were not provided:\n  <PATHS>...");
+        }
+
+        // a window asks for an image when none is given, but the modes that work on a file can't
+        if options.paths.is_empty() && !options.dump_chunks() && !options.recent && !options.bookmarks {
+            usage_error(None, ErrorKind::MissingRequiredArgument, "the options given need an image file");
+        }
+
+        options
+    }
+
+    /// Whether to rewrite the file with changed metadata instead of opening a window. An export
+    /// gets the changes that apply to it instead.
+    pub fn edits_metadata(&self) -> bool {
+        (!self.set_text.is_empty() || !self.remove_text.is_empty() || self.touch_time || self.set_dpi.is_some() || !self.add_chunk.is_empty()) && self.export.is_none()
+    }
+
+    /// Whether reading a file should dump its chunks to stdout, which only the window shows.
+    pub fn dump_chunks(&self) -> bool {
+        !self.tui && !self.assemble && !self.montage && !self.convert && !self.wallpaper && !self.analyze_deflate && !self.recompress && !self.edits_metadata() && self.extract_frames.is_none() && self.to_gif.is_none() && self.to_video.is_none() && self.export.is_none() && self.report.is_none() && self.slice.is_none() && self.extract_alpha.is_none() && self.extract_idat.is_none() && self.extract_raw.is_none() && self.render.is_none() && self.raw_out.is_none()
+    }
+}
+
+/// Prints `message` with the usage of the subcommand, if any, and exits, as clap does for the
+/// arguments it checks itself.
+fn usage_error(subcommand: Option<&str>, kind: ErrorKind, message: &str) -> ! {
+    let mut command = Cli::command();
+    command.build();
+
+    match subcommand.and_then(|name| command.find_subcommand_mut(name)) {
+        Some(subcommand) => subcommand.error(kind, message).exit(),
+        None => command.error(kind, message).exit(),
+    }
+}
+
+/// Parses `#rgb` or `#rrggbb` into an opaque RGBA colour.
+pub fn parse_colour(text: &str) -> Option<[u8; 4]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+    let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+
+    match hex.len() {
+        3 => Some([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17, 0xFF]),
+        6 => Some([byte(0)?, byte(2)?, byte(4)?, 0xFF]),
+        _ => None,
+    }
+}
+
+/// Parses `KEY=VALUE`.
+fn parse_text(text: &str) -> Option<(String, String)> {
+    text.split_once('=').map(|(keyword, text)| (keyword.to_string(), text.to_string()))
+}
+
+/// Parses `TYPE=FILE`, where TYPE is a chunk type that decoders skip when they don't know it:
+/// four letters, the first lowercase (ancillary) and the third uppercase (as the reserved bit
+/// must be).
+fn parse_added_chunk(text: &str) -> Result<([u8; 4], PathBuf), String> {
+    let (chunk_type, path) = text.split_once('=').ok_or("expected TYPE=FILE")?;
+    let chunk_type = <[u8; 4]>::try_from(chunk_type.as_bytes()).map_err(|_| format!("{} isn't four letters", chunk_type))?;
+
+    match chunk_type {
+        _ if !chunk_type.iter().all(u8::is_ascii_alphabetic) => Err(format!("{} isn't four letters", String::from_utf8_lossy(&chunk_type))),
+        _ if chunk_type[0].is_ascii_uppercase() => Err("an uppercase first letter makes the chunk critical, so decoders that don't know it reject the file".to_string()),
+        _ if chunk_type[2].is_ascii_lowercase() => Err("the third letter must be uppercase".to_string()),
+        _ => Ok((chunk_type, PathBuf::from(path))),
+    }
+}
+
+/// Adapts one of the `parse` functions, None meaning invalid, into a clap value parser.
+fn parser<T: Clone + Send + Sync + 'static>(parse: fn(&str) -> Option<T>, expected: &'static str) -> impl Fn(&str) -> Result<T, String> + Clone + Send + Sync + 'static {
+    move |text| parse(text).ok_or_else(|| format!("expected {}", expected))
+}
+
+/// A clap value parser accepting only `names`, which completions offer, parsed with `parse`.
+fn choices<T: Clone + Send + Sync + 'static>(names: &'static [&'static str], parse: fn(&str) -> Option<T>) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(names).map(move |name| parse(&name).expect("Every choice parses"))
+}
+
+/// Parses a finite number above zero.
+fn parse_positive(text: &str) -> Option<f64> {
+    text.parse().ok().filter(|n: &f64| n.is_finite() && *n > 0.0)
+}
+
+/// Parses `X,Y,WxH`.
+fn parse_crop(text: &str) -> Option<Rect> {
+    let (x, rest) = text.split_once(',')?;
+    let (y, size) = rest.split_once(',')?;
+    let (width, height) = size.split_once('x')?;
+    let rect = Rect { x: x.trim().parse().ok()?, y: y.trim().parse().ok()?, width: width.trim().parse().ok()?, height: height.trim().parse().ok()? };
+
+    (rect.width > 0 && rect.height > 0).then_some(rect)
+}
+
+/// Parses `WxH`, both at least 1.
+fn parse_size(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.split_once('x')?;
+
+    Some((width.trim().parse().ok().filter(|&n| n > 0)?, height.trim().parse().ok().filter(|&n| n > 0)?))
+}
+
+/// Parses `X,Y`.
+fn parse_position(text: &str) -> Option<(i32, i32)> {
+    let (x, y) = text.split_once(',')?;
+
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
@@ -0,0 +1,27 @@
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use crate::png_reader::{DecodedImage, OutputFormat, PngInfo, PngReader};
+
+/// Reads a whole PNG from `reader` without blocking, then parses and decodes it on tokio's
+/// blocking thread pool, so async services can decode uploads without stalling their executor.
+/// This doesn't stream: the decoder needs the whole file, so it's buffered in full before
+/// decoding starts and memory use grows with the upload. Invalid files are an `InvalidData`
+/// error rather than a panic.
+pub async fn decode_async(mut reader: impl AsyncRead + Unpin, format: OutputFormat) -> io::Result<(PngInfo, DecodedImage)> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes).await?;
+
+    let decode = move || {
+        let mut png = PngReader::new(bytes);
+        png.set_verbose(false);
+        png.read_chunks()?;
+
+        png.decode(format).map(|image| (png.info().clone(), image))
+    };
+
+    tokio::task::spawn_blocking(decode)
+        .await
+        .map_err(|e| format!("Failed to decode: {}", e))
+        .and_then(|result| result)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
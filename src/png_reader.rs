@@ -0,0 +1,1816 @@
+#[cfg(not(feature = "pure-inflate"))]
+use std::io::Read;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::crc;
+#[cfg(not(feature = "pure-inflate"))]
+use flate2::read::{ZlibDecoder};
+#[cfg(feature = "pure-inflate")]
+use crate::deflate;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Chunk types in the PNG specification, third edition, and the APNG extension.
+const STANDARD_CHUNKS: [&str; 25] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "tRNS", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV",
+    "cLLI", "tEXt", "zTXt", "iTXt", "bKGD", "hIST", "pHYs", "sPLT", "eXIf", "tIME", "acTL", "fcTL", "fdAT",
+];
+
+pub const FILTER_NAMES: [&str; 5] = ["None", "Sub", "Up", "Average", "Paeth"];
+
+type PassCallback = Box<dyn FnMut(&[Vec<Pixel>]) + Send>;
+type ProgressCallback = Box<dyn FnMut(Stage, f64) + Send>;
+/// Pixels, and the filter type of every scanline per pass.
+type DecodedPixels = (Vec<Vec<Pixel>>, Vec<Vec<u8>>);
+
+/// Bytes of image data inflated between progress reports.
+#[cfg(not(feature = "pure-inflate"))]
+const INFLATE_BLOCK: u64 = 1 << 20;
+
+/// A stage of reading an image, as reported to the progress callback.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// Reading the chunks, in `read_chunks`.
+    Parsing,
+    /// Decompressing the image data, in `decode_image_data`.
+    Inflating,
+    /// Reversing the scanline filters and converting to pixels, in `decode_image_data`.
+    Unfiltering,
+}
+
+/// Decodes chunks the reader doesn't parse itself, such as proprietary ones. Registered with
+/// `PngReader::add_chunk_handler`; the first handler that handles a chunk's type parses it.
+pub trait ChunkHandler: Send {
+    fn handles(&self, chunk_type: [u8; 4]) -> bool;
+
+    /// Parses a chunk's data into (title, text) pairs, recorded as the chunk's fields and
+    /// printed in the dump, or explains why the data is invalid.
+    fn parse(&mut self, data: &[u8]) -> Result<Vec<(String, String)>, String>;
+}
+
+/// Origin, spacing and replicated block size of the pixels decoded in one interlace pass.
+struct Pass {
+    x: usize,
+    y: usize,
+    x_step: usize,
+    y_step: usize,
+    block_width: usize,
+    block_height: usize,
+}
+
+impl Pass {
+    /// Size in pixels of the pass over an image of the given size.
+    fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        let pass_width = (width + self.x_step - 1).saturating_sub(self.x) / self.x_step;
+        let pass_height = (height + self.y_step - 1).saturating_sub(self.y) / self.y_step;
+
+        (pass_width, pass_height)
+    }
+}
+
+const NON_INTERLACED: Pass = Pass { x: 0, y: 0, x_step: 1, y_step: 1, block_width: 1, block_height: 1 };
+
+const ADAM7: [Pass; 7] = [
+    Pass { x: 0, y: 0, x_step: 8, y_step: 8, block_width: 8, block_height: 8 },
+    Pass { x: 4, y: 0, x_step: 8, y_step: 8, block_width: 4, block_height: 8 },
+    Pass { x: 0, y: 4, x_step: 4, y_step: 8, block_width: 4, block_height: 4 },
+    Pass { x: 2, y: 0, x_step: 4, y_step: 4, block_width: 2, block_height: 4 },
+    Pass { x: 0, y: 2, x_step: 2, y_step: 4, block_width: 2, block_height: 2 },
+    Pass { x: 1, y: 0, x_step: 2, y_step: 2, block_width: 1, block_height: 2 },
+    Pass { x: 0, y: 1, x_step: 1, y_step: 2, block_width: 1, block_height: 1 },
+];
+
+/// How 16-bit samples are reduced to the 8 bits pixels hold.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Dither {
+    /// Keep the high byte.
+    None,
+    /// Add a 4x4 Bayer threshold pattern before rounding down.
+    Ordered,
+    /// Floyd-Steinberg error diffusion, within each interlace pass.
+    Diffusion,
+}
+
+impl Dither {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::None),
+            "ordered" => Some(Self::Ordered),
+            "diffusion" => Some(Self::Diffusion),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Ordered => "ordered",
+            Self::Diffusion => "diffusion",
+        }
+    }
+
+    /// The method after `self`, cycling back to none.
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Ordered,
+            Self::Ordered => Self::Diffusion,
+            Self::Diffusion => Self::None,
+        }
+    }
+}
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Reduces a pass's 16-bit scanlines to 8 bits, one scanline at a time and in order, so error
+/// diffusion can carry each sample's rounding error right and down.
+struct Downconverter {
+    dither: Dither,
+    channels: usize,
+    /// Error carried into the current and next scanlines, per sample.
+    errors: Vec<f32>,
+    next_errors: Vec<f32>,
+}
+
+impl Downconverter {
+    fn new(dither: Dither, samples_per_line: usize, channels: usize) -> Self {
+        Self {
+            dither,
+            channels,
+            errors: vec![0.0; samples_per_line],
+            next_errors: vec![0.0; samples_per_line],
+        }
+    }
+
+    /// Converts one scanline of big-endian 16-bit samples, whose pixels sit at `x` then every
+    /// `x_step` image pixels along row `y`.
+    fn scanline(&mut self, bytes: &[u8], x: usize, x_step: usize, y: usize) -> Vec<u8> {
+        let samples = bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+        match self.dither {
+            Dither::None => samples.map(|s| (s >> 8) as u8).collect(),
+            Dither::Ordered => samples
+                .enumerate()
+                .map(|(i, s)| {
+                    let threshold = (BAYER[y % 4][(x + i / self.channels * x_step) % 4] as f32 + 0.5) / 16.0;
+                    (s as f32 / 257.0 + threshold).floor().min(255.0) as u8
+                })
+                .collect(),
+            Dither::Diffusion => {
+                let channels = self.channels;
+                let len = self.errors.len();
+
+                let line = samples
+                    .enumerate()
+                    .map(|(i, s)| {
+                        let value = s as f32 / 257.0 + self.errors[i];
+                        let quantised = value.round().clamp(0.0, 255.0);
+                        let error = value - quantised;
+
+                        if i + channels < len {
+                            self.errors[i + channels] += error * 7.0 / 16.0;
+                            self.next_errors[i + channels] += error / 16.0;
+                        }
+                        if i >= channels {
+                            self.next_errors[i - channels] += error * 3.0 / 16.0;
+                        }
+                        self.next_errors[i] += error * 5.0 / 16.0;
+
+                        quantised as u8
+                    })
+                    .collect();
+
+                std::mem::swap(&mut self.errors, &mut self.next_errors);
+                self.next_errors.fill(0.0);
+
+                line
+            }
+        }
+    }
+}
+
+/// Stops a reader part way through reading or decoding, from any thread, e.g. when the user
+/// moves on from an image still being decoded. Clones share one flag.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Pixel layout written by `decode_into`: rows top to bottom with no padding between them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OutputFormat {
+    /// Luma, weighted as in Rec. 709, dropping alpha.
+    Gray8,
+    /// Red, green and blue, dropping alpha.
+    Rgb8,
+    /// Red, green, blue and alpha, 8 bits each and not premultiplied.
+    Rgba8,
+    /// Red, green, blue and alpha, 16 bits each in native byte order. 8-bit samples are scaled
+    /// up, so 0xFF becomes 0xFFFF.
+    Rgba16,
+    /// Blue, green, red and alpha, the order many GPU and window system surfaces use.
+    Bgra8,
+}
+
+impl OutputFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Gray8 => 1,
+            Self::Rgb8 => 3,
+            Self::Rgba8 | Self::Bgra8 => 4,
+            Self::Rgba16 => 8,
+        }
+    }
+
+    fn write(self, pixel: Pixel, out: &mut [u8]) {
+        match self {
+            Self::Gray8 => out[0] = (0.2126 * pixel.r as f64 + 0.7152 * pixel.g as f64 + 0.0722 * pixel.b as f64).round() as u8,
+            Self::Rgb8 => out.copy_from_slice(&[pixel.r, pixel.g, pixel.b]),
+            Self::Rgba8 => out.copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]),
+            Self::Rgba16 => Self::write_rgba16([pixel.r, pixel.g, pixel.b, pixel.a].map(|c| c as u16 * 257), out),
+            Self::Bgra8 => out.copy_from_slice(&[pixel.b, pixel.g, pixel.r, pixel.a]),
+        }
+    }
+
+    fn write_rgba16(rgba: [u16; 4], out: &mut [u8]) {
+        for (bytes, channel) in out.chunks_exact_mut(2).zip(rgba) {
+            bytes.copy_from_slice(&channel.to_ne_bytes());
+        }
+    }
+}
+
+/// A whole image decoded into one output format, from `PngReader::decode`.
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: OutputFormat,
+    /// Rows top to bottom with no padding between them.
+    pub data: Vec<u8>,
+}
+
+impl DecodedImage {
+    /// Bytes per row.
+    pub fn stride(&self) -> usize {
+        self.width as usize * self.format.bytes_per_pixel()
+    }
+
+    /// Each row's bytes, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.stride().max(1))
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [u8]> {
+        let stride = self.stride().max(1);
+
+        self.data.chunks_exact_mut(stride)
+    }
+}
+
+/// How long each stage of reading took, from the most recent `read_chunks` and decode.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Timings {
+    pub parse: Duration,
+    pub inflate: Duration,
+    /// Reversing the scanline filters, including converting to pixels.
+    pub unfilter: Duration,
+}
+
+/// Bytes held by each of the reader's buffers at their largest, from the most recent decode.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct BufferSizes {
+    /// The whole file, as given to `new`.
+    pub file: usize,
+    /// The IDAT data joined together.
+    pub compressed: usize,
+    /// The inflated, still filtered, scanlines, freed once decoded.
+    pub inflated: usize,
+    /// The decoded pixels, or the caller's buffer for `decode_into`.
+    pub pixels: usize,
+}
+
+/// Location and parsed content of one chunk in the file.
+pub struct Chunk {
+    pub chunk_type: String,
+    /// Offset of the chunk's length field from the start of the file.
+    pub offset: usize,
+    pub data_range: Range<usize>,
+    /// CRC as stored after the data.
+    pub crc: u32,
+    /// CRC of the type and data as read, which differs from `crc` if the chunk is corrupt.
+    pub computed_crc: u32,
+    /// Parsed content as (title, text) pairs, as printed in the dump.
+    pub fields: Vec<(String, String)>,
+}
+
+/// Frame count and loop count of an APNG, from acTL.
+#[derive(Copy, Clone, Debug)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// How many times to play the animation, 0 meaning forever.
+    pub num_plays: u32,
+}
+
+/// Region, delay and compositing of one APNG frame, from its fcTL chunk.
+#[derive(Copy, Clone)]
+pub struct FrameControl {
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    /// Delay before the next frame, as a fraction of a second.
+    pub delay_num: u16,
+    pub delay_den: u16,
+    /// What happens to the frame's region before the next frame: 0 none, 1 background, 2 previous.
+    pub dispose_op: u8,
+    /// 0 to replace the region, 1 to alpha blend over it.
+    pub blend_op: u8,
+}
+
+/// One APNG frame's control and the pixels of its region, before compositing.
+pub type FrameRegion = (FrameControl, Vec<Vec<Pixel>>);
+
+/// A palette entry.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Simple transparency from tRNS, whose form depends on the colour type.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Trns {
+    /// Alpha of the first palette entries, in order; entries past the end are opaque.
+    Palette(Vec<u8>),
+    /// The greyscale sample, at the image's bit depth, whose pixels are fully transparent.
+    Grey(u16),
+    /// The colour, at the image's bit depth, whose pixels are fully transparent.
+    Rgb(u16, u16, u16),
+}
+
+/// Pixel density from pHYs.
+#[derive(Copy, Clone, Debug)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    /// Whether `x` and `y` are pixels per metre; otherwise they only give the pixel aspect ratio.
+    pub in_metres: bool,
+}
+
+/// White point and primaries from cHRM, as CIE 1931 xy coordinates.
+#[derive(Copy, Clone, Debug)]
+pub struct Chromaticities {
+    pub white: (f64, f64),
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+}
+
+/// What the header and ancillary chunks say about an image, as read by `read_chunks`.
+#[derive(Clone, Default, Debug)]
+pub struct PngInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    /// 0 greyscale, 2 truecolour, 3 indexed-colour, 4 greyscale with alpha, 6 truecolour with alpha.
+    pub colour_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    /// 0 none, 1 Adam7.
+    pub interlace_method: u8,
+    /// From gAMA, e.g. 0.45455 for sRGB-like encoding.
+    pub gamma: Option<f64>,
+    pub chromaticities: Option<Chromaticities>,
+    /// Rendering intent from sRGB, whose presence says the image is sRGB.
+    pub srgb_intent: Option<u8>,
+    /// Entries in PLTE, 0 without one.
+    pub palette_size: usize,
+    /// Keyword and text of each tEXt chunk, in file order.
+    pub text: Vec<(String, String)>,
+    pub physical_dimensions: Option<PhysicalDimensions>,
+    pub exif_orientation: Option<u16>,
+    /// Embedded ICC profile from iCCP, decompressed.
+    pub icc_profile: Option<Vec<u8>>,
+    /// Colour primaries, transfer function, matrix coefficients and full-range flag from cICP.
+    pub cicp: Option<[u8; 4]>,
+    pub animation: Option<AnimationControl>,
+}
+
+impl PngInfo {
+    /// Samples per pixel: one palette index for indexed-colour.
+    pub fn channels(&self) -> usize {
+        match self.colour_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// One interlace pass's scanlines as stored, after inflating but before unfiltering.
+pub struct FilteredPass {
+    /// Size of the pass in pixels.
+    pub width: usize,
+    pub height: usize,
+    /// Each scanline's filter type byte followed by its filtered bytes.
+    pub data: Vec<u8>,
+}
+
+impl FilteredPass {
+    /// Bytes per scanline, not counting the filter type.
+    pub fn stride(&self) -> usize {
+        self.data.len() / self.height.max(1) - 1
+    }
+
+    /// Each scanline's filter type and filtered bytes.
+    pub fn scanlines(&self) -> impl Iterator<Item = (u8, &[u8])> {
+        self.data.chunks(self.stride() + 1).map(|line| (line[0], &line[1..]))
+    }
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+pub struct PngReader {
+    bytes: Vec<u8>,
+    /// Print each chunk as it is read.
+    verbose: bool,
+    chunks: Vec<Chunk>,
+
+    info: PngInfo,
+    /// Entries of PLTE.
+    palette: Option<Vec<Rgb>>,
+    transparency: Option<Trns>,
+    /// How 16-bit samples are reduced to 8 bits.
+    dither: Dither,
+    /// Every APNG frame's control and its compressed data: the IDAT data for a default image
+    /// with an fcTL, fdAT data otherwise.
+    frames: Vec<(FrameControl, Vec<u8>)>,
+
+    image_data: Vec<u8>,
+    /// Size of the image data after inflating, once decoded.
+    decompressed_size: usize,
+    timings: Timings,
+    buffer_sizes: BufferSizes,
+    pub pixel_data: Vec<Vec<Pixel>>,
+    /// Filter type of every scanline, per interlace pass (a single pass when not interlaced).
+    filter_types: Vec<Vec<u8>>,
+
+    /// Called with the whole image after each interlace pass (once for non-interlaced images),
+    /// with not-yet-decoded pixels replicated from the preceding passes.
+    pass_callback: Option<PassCallback>,
+    /// Called with the stage and the fraction of it done, from 0 to 1, as reading goes on.
+    progress: Option<ProgressCallback>,
+    /// Checked between chunks, blocks of inflated data and scanlines.
+    cancel_token: Option<CancelToken>,
+    chunk_handlers: Vec<Box<dyn ChunkHandler>>,
+}
+
+impl PngReader {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            verbose: true,
+            chunks: vec![],
+            info: PngInfo::default(),
+            palette: None,
+            transparency: None,
+            dither: Dither::None,
+            frames: vec![],
+            image_data: vec![],
+            decompressed_size: 0,
+            timings: Timings::default(),
+            buffer_sizes: BufferSizes::default(),
+            pixel_data: vec![],
+            filter_types: vec![],
+            pass_callback: None,
+            progress: None,
+            cancel_token: None,
+            chunk_handlers: vec![],
+        }
+    }
+
+    /// Reads what the header and the ancillary chunks before the image data say about an image,
+    /// without reading any further, e.g. to scan a directory or size thumbnails. None if the
+    /// bytes don't start with a signature and a complete, valid IHDR.
+    pub fn probe(bytes: &[u8]) -> Option<PngInfo> {
+        // the signature, then IHDR's length, type, 13 bytes of data and CRC
+        if bytes.len() < 33 || bytes[..SIGNATURE.len()] != SIGNATURE || bytes[8..16] != *b"\0\0\0\x0dIHDR" {
+            return None;
+        }
+
+        // stop at the image data or the first chunk that's cut off or misnamed
+        let mut end = SIGNATURE.len();
+
+        while let Some(header) = bytes.get(end..end + 8) {
+            let data_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+
+            if &header[4..] == b"IDAT" || !header[4..].iter().all(u8::is_ascii_alphabetic) || bytes.len() < end + 12 + data_len {
+                break;
+            }
+
+            end += 12 + data_len;
+        }
+
+        let mut reader = Self::new(bytes[..end].to_vec());
+        reader.set_verbose(false);
+        reader.read_chunks().ok()?;
+
+        Some(reader.info)
+    }
+
+    /// Parses the signature and every chunk without decoding the image data. Fails, saying
+    /// where, on a file that isn't a PNG, a chunk cut off by the end of the file or a header the
+    /// image can't be decoded with.
+    pub fn read_chunks(&mut self) -> Result<(), String> {
+        let start = Instant::now();
+        let mut idx = self.read_signature()?;
+
+        while idx < self.bytes.len() && !self.is_cancelled() {
+            idx = self.read_chunk(idx)?;
+
+            if let Some(progress) = &mut self.progress {
+                progress(Stage::Parsing, idx as f64 / self.bytes.len() as f64);
+            }
+        }
+
+        self.timings.parse = start.elapsed();
+        self.print_content("Chunk map", self.chunk_map().join("\n"));
+
+        self.check_header()
+    }
+
+    /// Whether reading logs each chunk's bytes and parsed content as `tracing` info events. On by
+    /// default.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    pub fn info(&self) -> &PngInfo {
+        &self.info
+    }
+
+    pub fn palette(&self) -> Option<&[Rgb]> {
+        self.palette.as_deref()
+    }
+
+    pub fn transparency(&self) -> Option<&Trns> {
+        self.transparency.as_ref()
+    }
+
+    pub fn is_interlaced(&self) -> bool {
+        self.info.interlace_method == 1
+    }
+
+    pub fn set_pass_callback(&mut self, callback: impl FnMut(&[Vec<Pixel>]) + Send + 'static) {
+        self.pass_callback = Some(Box::new(callback));
+    }
+
+    /// Reports progress through each stage of reading, e.g. for a progress bar: called after
+    /// every chunk, every megabyte of inflated image data and every scanline.
+    pub fn set_progress(&mut self, callback: impl FnMut(Stage, f64) + Send + 'static) {
+        self.progress = Some(Box::new(callback));
+    }
+
+    /// Lets `token` stop reading and decoding part way. A cancelled `read_chunks` leaves the
+    /// remaining chunks unread and a cancelled `decode_image_data` leaves `pixel_data` partly
+    /// decoded, so check `is_cancelled` after either; `decode_into` returns an error instead.
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel_token = Some(token);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_token.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Registers a handler for chunks the reader doesn't parse itself. Must be called before
+    /// `read_chunks`.
+    pub fn add_chunk_handler(&mut self, handler: impl ChunkHandler + 'static) {
+        self.chunk_handlers.push(Box::new(handler));
+    }
+
+    fn read_signature(&self) -> Result<usize, String> {
+        if self.bytes.get(..SIGNATURE.len()) != Some(&SIGNATURE[..]) {
+            return Err(error_at(0, "no PNG signature"));
+        }
+
+        self.print("Signature", &self.bytes[0..SIGNATURE.len()]);
+
+        Ok(SIGNATURE.len())
+    }
+
+    fn read_chunk(&mut self, idx: usize) -> Result<usize, String> {
+        let offset = idx;
+        let mut idx = idx;
+
+        let Some(header) = self.bytes.get(idx..idx + 8) else {
+            return Err(error_at(offset, "chunk cut off by the end of the file"));
+        };
+
+        // length
+        let data_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        idx += 4;
+
+        // chunk type
+        let chunk_type = String::from_utf8_lossy(&header[4..]).into_owned();
+        idx += 4;
+
+        if self.bytes.len() < idx + data_len + 4 {
+            return Err(error_at(offset, &format!("{} chunk of {} bytes cut off by the end of the file", chunk_type, data_len)));
+        }
+
+        if self.chunks.is_empty() && chunk_type != "IHDR" {
+            return Err(error_at(offset, &format!("first chunk is {}, not IHDR", chunk_type)));
+        }
+
+        // chunk data
+        let data_range = idx..idx + data_len;
+        idx += data_len;
+
+        // crc
+        let crc = u32::from_be_bytes([self.bytes[idx], self.bytes[idx + 1], self.bytes[idx + 2], self.bytes[idx + 3]]);
+        let computed_crc = crc::chunk_crc(&self.bytes[offset + 4..offset + 8], &self.bytes[data_range.clone()]);
+        idx += 4;
+
+        self.chunks.push(Chunk {
+            chunk_type: chunk_type.clone(),
+            offset,
+            data_range: data_range.clone(),
+            crc,
+            computed_crc,
+            fields: vec![],
+        });
+
+        if self.verbose {
+            tracing::info!(
+                chunk = %chunk_type,
+                offset,
+                length = data_range.len(),
+                data = ?Self::leading_bytes(&self.bytes[data_range.clone()]),
+                crc = %format_args!("{:08x}", crc),
+                computed_crc = %format_args!("{:08x}", computed_crc),
+                crc_ok = crc == computed_crc,
+            );
+        }
+
+        let min_len = Self::min_chunk_len(&chunk_type);
+
+        match chunk_type.as_str() {
+            _ if data_range.len() < min_len => self.print_chunk_content("Invalid chunk", format!("{} bytes of data, at least {} expected", data_range.len(), min_len)),
+            "IHDR" => self.read_chunk_ihdr(&data_range),
+            "PLTE" => self.read_chunk_plte(&data_range),
+            "tRNS" => self.read_chunk_trns(&data_range),
+            "IDAT" => self.read_chunk_idat(&data_range),
+            "tEXt" => self.read_chunk_text(&data_range),
+            "tIME" => self.read_chunk_time(&data_range),
+            "gAMA" => self.read_chunk_gama(&data_range),
+            "cHRM" => self.read_chunk_chrm(&data_range),
+            "sRGB" => self.read_chunk_srgb(&data_range),
+            "pHYs" => self.read_chunk_phys(&data_range),
+            "eXIf" => self.read_chunk_exif(&data_range),
+            "iCCP" => self.read_chunk_iccp(&data_range),
+            "cICP" => self.read_chunk_cicp(&data_range),
+            "acTL" => self.read_chunk_actl(&data_range),
+            "fcTL" => self.read_chunk_fctl(&data_range),
+            "fdAT" => self.read_chunk_fdat(&data_range),
+            _ => self.read_chunk_custom(&data_range, &chunk_type),
+        };
+
+        Ok(idx)
+    }
+
+    /// Checks that IHDR describes an image that can be decoded: not empty, with a bit depth
+    /// allowed for its colour type and methods the specification defines.
+    fn check_header(&self) -> Result<(), String> {
+        let info = &self.info;
+        let bit_depths: &[u8] = match info.colour_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => return Err(error_at(SIGNATURE.len(), &format!("invalid colour type {}", info.colour_type))),
+        };
+
+        let problem = if info.width == 0 || info.height == 0 {
+            format!("invalid size {}x{}", info.width, info.height)
+        } else if !bit_depths.contains(&info.bit_depth) {
+            format!("invalid bit depth {} for colour type {}", info.bit_depth, info.colour_type)
+        } else if info.compression_method != 0 || info.filter_method != 0 || info.interlace_method > 1 {
+            format!("unknown compression, filter or interlace method {}, {}, {}", info.compression_method, info.filter_method, info.interlace_method)
+        } else {
+            return Ok(());
+        };
+
+        Err(error_at(SIGNATURE.len(), &problem))
+    }
+
+    /// The least data a chunk type that's read at fixed offsets can have, so a short chunk is
+    /// reported rather than read past.
+    fn min_chunk_len(chunk_type: &str) -> usize {
+        match chunk_type {
+            "IHDR" => 13,
+            "tIME" => 7,
+            "pHYs" => 9,
+            "acTL" => 8,
+            "fcTL" => 26,
+            "gAMA" | "fdAT" => 4,
+            _ => 0,
+        }
+    }
+
+    fn read_chunk_ihdr(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        self.info.width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        self.info.height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        self.info.bit_depth = data[8];
+        self.info.colour_type = data[9];
+        self.info.compression_method = data[10];
+        self.info.filter_method = data[11];
+        self.info.interlace_method = data[12];
+
+        self.print_chunk_content(
+            "Image header",
+            format!(r#"[Size] {}x{}
+[Bit depth] {}
+[Colour type] {}
+[Compression method] {}
+[Filter method] {}
+[Interlace method] {}"#,
+                    self.info.width, self.info.height, self.info.bit_depth, self.info.colour_type, self.info.compression_method, self.info.filter_method, self.info.interlace_method),
+        );
+    }
+
+    fn read_chunk_plte(&mut self, data_range: &Range<usize>) {
+        let palette = self.bytes[data_range.clone()]
+            .chunks_exact(3)
+            .map(|entry| Rgb { r: entry[0], g: entry[1], b: entry[2] })
+            .collect::<Vec<_>>();
+
+        self.info.palette_size = palette.len();
+        self.palette = Some(palette);
+
+        self.print_chunk_content("Palette", format!("[Entries] {}", self.info.palette_size));
+    }
+
+    fn read_chunk_trns(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let read_u16 = |i: usize| data.get(i..i + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+        let transparency = match self.info.colour_type {
+            3 => Some(Trns::Palette(data.to_vec())),
+            0 => read_u16(0).map(Trns::Grey),
+            2 => read_u16(0).zip(read_u16(2)).zip(read_u16(4)).map(|((r, g), b)| Trns::Rgb(r, g, b)),
+            _ => None,
+        };
+
+        let content = match &transparency {
+            Some(Trns::Palette(alphas)) => format!("[Palette alphas] {}", alphas.len()),
+            Some(Trns::Grey(grey)) => format!("[Transparent grey] {}", grey),
+            Some(Trns::Rgb(r, g, b)) => format!("[Transparent colour] {} {} {}", r, g, b),
+            None => "invalid for the colour type".to_string(),
+        };
+
+        self.print_chunk_content("Transparency", content);
+        self.transparency = transparency;
+    }
+
+    fn read_chunk_idat(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        self.image_data.append(&mut data.to_vec());
+
+        // an fcTL before the image data makes the default image the first frame
+        if let Some((_, frame_data)) = self.frames.last_mut() {
+            frame_data.extend_from_slice(data);
+        }
+
+        /*
+        let mut decompressed_data = Vec::<u8>::new();
+        let data_len = ZlibDecoder::new(data).read_to_end(&mut decompressed_data).unwrap();
+        */
+
+        self.print_chunk_content("Image data", format!("{} bytes", data_range.len()));
+    }
+
+    fn read_chunk_text(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let (keyword, text) = match data.iter().position(|&b| b == 0) {
+            Some(separator_idx) => (&data[..separator_idx], &data[separator_idx + 1..]),
+            None => (data, &[][..]),
+        };
+
+        // both are Latin-1, whose code points are Unicode's first 256
+        let latin1 = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+        let (keyword, text) = (latin1(keyword), latin1(text));
+
+        self.print_chunk_content("Textual data", format!("[keyword] {}\n[text] {}", keyword, text));
+
+        self.info.text.push((keyword, text));
+    }
+
+    fn read_chunk_time(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let year = u16::from_be_bytes([data[0], data[1]]);
+        let month = data[2];
+        let day = data[3];
+        let hour = data[4];
+        let minutes = data[5];
+        let second = data[6];
+
+        self.print_chunk_content("Image last-modification time", format!("{}/{}/{} {:<02}:{:<02}:{:<02}", year, month, day, hour, minutes, second));
+    }
+
+    fn read_chunk_gama(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let gamma = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64 / 100000.0;
+        self.info.gamma = Some(gamma);
+
+        self.print_chunk_content("Image gamma", format!("{:.5}", gamma));
+    }
+
+    fn read_chunk_chrm(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+
+        if data.len() < 32 {
+            return;
+        }
+
+        let value = |i: usize| u32::from_be_bytes([data[i * 4], data[i * 4 + 1], data[i * 4 + 2], data[i * 4 + 3]]) as f64 / 100000.0;
+        let chromaticities = Chromaticities {
+            white: (value(0), value(1)),
+            red: (value(2), value(3)),
+            green: (value(4), value(5)),
+            blue: (value(6), value(7)),
+        };
+        self.info.chromaticities = Some(chromaticities);
+
+        self.print_chunk_content("Primary chromaticities", format!(
+            "white {:.5},{:.5} red {:.5},{:.5} green {:.5},{:.5} blue {:.5},{:.5}",
+            value(0), value(1), value(2), value(3), value(4), value(5), value(6), value(7),
+        ));
+    }
+
+    fn read_chunk_srgb(&mut self, data_range: &Range<usize>) {
+        let Some(&intent) = self.bytes[data_range.clone()].first() else {
+            return;
+        };
+
+        self.info.srgb_intent = Some(intent);
+
+        let name = match intent {
+            0 => "Perceptual",
+            1 => "Relative colorimetric",
+            2 => "Saturation",
+            3 => "Absolute colorimetric",
+            _ => "Unknown",
+        };
+
+        self.print_chunk_content("Rendering intent", format!("{} ({})", name, intent));
+    }
+
+    fn read_chunk_phys(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let unit = data[8];
+        self.info.physical_dimensions = Some(PhysicalDimensions { x, y, in_metres: unit == 1 });
+
+        self.print_chunk_content(
+            "Physical pixel dimensions",
+            format!("[Pixels per unit] {}x{}\n[Unit] {}", x, y, if unit == 1 { "metre" } else { "unknown" }),
+        );
+    }
+
+    fn read_chunk_exif(&mut self, data_range: &Range<usize>) {
+        self.info.exif_orientation = Self::exif_orientation(&self.bytes[data_range.clone()]);
+
+        self.print_chunk_content(
+            "Exchangeable image file format",
+            format!("[Orientation] {}", self.info.exif_orientation.map_or("none".to_string(), |o| o.to_string())),
+        );
+    }
+
+    fn read_chunk_iccp(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let separator_idx = data.iter().position(|&b| b == 0).unwrap_or(0);
+
+        let name = String::from_utf8_lossy(&data[0..separator_idx]).to_string();
+
+        // a null separator, then the compression method (always 0, zlib)
+        let profile = data.get(separator_idx + 2..).and_then(|compressed| self.decompress(compressed).ok());
+
+        self.print_chunk_content(
+            "Embedded ICC profile",
+            format!("[Name] {}\n[Profile] {}", name, profile.as_ref().map_or_else(|| "corrupt".to_string(), |profile| format!("{} bytes", profile.len()))),
+        );
+
+        if profile.is_some() {
+            self.info.icc_profile = profile;
+        }
+    }
+
+    fn read_chunk_cicp(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+
+        let Ok(cicp) = <[u8; 4]>::try_from(data) else {
+            return;
+        };
+
+        self.print_chunk_content(
+            "Coding-independent code points",
+            format!("[Colour primaries] {}\n[Transfer function] {}\n[Matrix coefficients] {}\n[Full range] {}", cicp[0], cicp[1], cicp[2], cicp[3]),
+        );
+
+        self.info.cicp = Some(cicp);
+    }
+
+    fn read_chunk_actl(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let num_frames = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let num_plays = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        self.info.animation = Some(AnimationControl { num_frames, num_plays });
+
+        self.print_chunk_content(
+            "Animation control",
+            format!("[Frames] {}\n[Plays] {}", num_frames, if num_plays == 0 { "forever".to_string() } else { num_plays.to_string() }),
+        );
+    }
+
+    fn read_chunk_fctl(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+        let read_u32 = |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let read_u16 = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+
+        // after the sequence number
+        let control = FrameControl {
+            width: read_u32(4),
+            height: read_u32(8),
+            x_offset: read_u32(12),
+            y_offset: read_u32(16),
+            delay_num: read_u16(20),
+            delay_den: read_u16(22),
+            dispose_op: data[24],
+            blend_op: data[25],
+        };
+
+        self.frames.push((control, vec![]));
+
+        self.print_chunk_content(
+            "Frame control",
+            format!(
+                "[Sequence number] {}\n[Region] {}x{} at {},{}\n[Delay] {}/{} s\n[Dispose] {}\n[Blend] {}",
+                read_u32(0), control.width, control.height, control.x_offset, control.y_offset, control.delay_num, control.delay_den,
+                ["none", "background", "previous"].get(control.dispose_op as usize).unwrap_or(&"invalid"),
+                ["source", "over"].get(control.blend_op as usize).unwrap_or(&"invalid"),
+            ),
+        );
+    }
+
+    fn read_chunk_fdat(&mut self, data_range: &Range<usize>) {
+        let data = &self.bytes[data_range.clone()];
+
+        if let Some((_, frame_data)) = self.frames.last_mut() {
+            frame_data.extend_from_slice(&data[4..]);
+        }
+
+        self.print_chunk_content("Frame data", format!("[Sequence number] {}\n{} bytes", u32::from_be_bytes([data[0], data[1], data[2], data[3]]), data.len() - 4));
+    }
+
+    /// Passes an unknown chunk to the first registered handler for its type, if any.
+    fn read_chunk_custom(&mut self, data_range: &Range<usize>, chunk_type: &str) {
+        let Ok(ty) = <[u8; 4]>::try_from(chunk_type.as_bytes()) else {
+            return;
+        };
+        let Some(handler) = self.chunk_handlers.iter_mut().find(|handler| handler.handles(ty)) else {
+            return;
+        };
+
+        match handler.parse(&self.bytes[data_range.clone()]) {
+            Ok(fields) => {
+                for (title, content) in fields {
+                    self.print_chunk_content(&title, content);
+                }
+            }
+            Err(e) => self.print_chunk_content("Invalid chunk", e),
+        }
+    }
+
+    /// Extracts the Orientation tag (0x0112) from IFD0 of the TIFF-structured eXIf data.
+    fn exif_orientation(data: &[u8]) -> Option<u16> {
+        let big_endian = match data.get(0..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+
+        let read_u16 = |i: usize| -> Option<u16> {
+            let b = data.get(i..i + 2)?;
+            Some(if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) })
+        };
+        let read_u32 = |i: usize| -> Option<u32> {
+            let b = data.get(i..i + 4)?;
+            Some(if big_endian { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) })
+        };
+
+        let ifd = read_u32(4)? as usize;
+        let entries = read_u16(ifd)? as usize;
+
+        (0..entries)
+            .map(|n| ifd + 2 + n * 12)
+            .find(|&entry| read_u16(entry) == Some(0x0112))
+            .and_then(|entry| read_u16(entry + 8))
+    }
+
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.buffer_sizes
+    }
+
+    fn record_buffer_sizes(&mut self, inflated: &Vec<u8>) {
+        self.buffer_sizes.file = self.bytes.capacity();
+        self.buffer_sizes.compressed = self.image_data.capacity();
+        self.buffer_sizes.inflated = inflated.capacity();
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub fn chunk_data(&self, chunk: &Chunk) -> &[u8] {
+        &self.bytes[chunk.data_range.clone()]
+    }
+
+    /// The whole chunk as stored: length, type, data and CRC.
+    pub fn chunk_bytes(&self, chunk: &Chunk) -> &[u8] {
+        &self.bytes[chunk.offset..chunk.data_range.end + 4]
+    }
+
+    /// The file's layout as a table of chunks with their offsets, data lengths and whether their
+    /// CRCs match, noting chunks this decoder doesn't know. A run of chunks of one type, like
+    /// the image data split into IDATs, is one row with the first offset and the total length.
+    pub fn chunk_map(&self) -> Vec<String> {
+        let mut lines = vec![format!("{:<6} {:>10} {:>10}  {:<4}  {}", "Type", "Offset", "Length", "CRC", "Note")];
+
+        for run in self.chunks.chunk_by(|a, b| a.chunk_type == b.chunk_type) {
+            let chunk_type = &run[0].chunk_type;
+            let length = run.iter().map(|chunk| chunk.data_range.len()).sum::<usize>();
+            let bad_crcs = run.iter().filter(|chunk| chunk.crc != chunk.computed_crc).count();
+
+            let crc = match (bad_crcs, run.len()) {
+                (0, _) => "ok".to_string(),
+                (_, 1) => "BAD".to_string(),
+                _ => format!("{} BAD", bad_crcs),
+            };
+
+            let mut notes = vec![];
+
+            if run.len() > 1 {
+                notes.push(format!("{} chunks", run.len()));
+            }
+
+            let bytes = chunk_type.as_bytes();
+
+            if !STANDARD_CHUNKS.contains(&chunk_type.as_str()) {
+                notes.push(match (bytes[0].is_ascii_uppercase(), bytes[1].is_ascii_lowercase()) {
+                    (true, _) => "unknown critical chunk".to_string(),
+                    (false, true) => "private".to_string(),
+                    (false, false) => "unknown".to_string(),
+                });
+            }
+
+            lines.push(format!("{:<6} {:>10} {:>10}  {:<4}  {}", chunk_type, run[0].offset, length, crc, notes.join(", ")).trim_end().to_string());
+        }
+
+        lines
+    }
+
+    /// Key metadata as short lines, for the on-image overlay.
+    pub fn summary(&self) -> Vec<String> {
+        let colour_type = match self.info.colour_type {
+            0 => "Greyscale",
+            2 => "Truecolour",
+            3 => "Indexed-colour",
+            4 => "Greyscale with alpha",
+            6 => "Truecolour with alpha",
+            _ => "Unknown",
+        };
+
+        let mut lines = vec![
+            format!("Size: {}x{}", self.info.width, self.info.height),
+            format!("Colour type: {} ({})", colour_type, self.info.colour_type),
+            format!("Bit depth: {}", self.info.bit_depth),
+        ];
+
+        if let Some(gamma) = self.info.gamma {
+            lines.push(format!("Gamma: {:.5}", gamma));
+        }
+
+        match self.info.physical_dimensions {
+            Some(PhysicalDimensions { x, y, in_metres: true }) => lines.push(format!("DPI: {:.0}x{:.0}", x as f64 * 0.0254, y as f64 * 0.0254)),
+            Some(PhysicalDimensions { x, y, in_metres: false }) => lines.push(format!("Pixel aspect: {}:{}", x, y)),
+            None => (),
+        }
+
+        if let Some(orientation) = self.info.exif_orientation {
+            lines.push(format!("EXIF orientation: {}", orientation));
+        }
+
+        if let Some(cicp) = self.info.cicp {
+            lines.push(format!("cICP: {}/{}/{}/{}", cicp[0], cicp[1], cicp[2], cicp[3]));
+        }
+
+        if let Some(profile) = &self.info.icc_profile {
+            lines.push(format!("ICC profile: {} bytes", profile.len()));
+        }
+
+        lines.push(format!("File size: {} bytes", self.bytes.len()));
+
+        for (keyword, text) in &self.info.text {
+            lines.push(format!("{}: {}", keyword, text));
+        }
+
+        lines
+    }
+
+    fn print(&self, title: &str, data: &[u8]) {
+        if self.verbose {
+            tracing::info!(section = title, data = ?Self::leading_bytes(data));
+        }
+    }
+
+    fn print_content(&self, title: &str, content: String) {
+        if self.verbose {
+            tracing::info!(section = title, content = %content);
+        }
+    }
+
+    /// The first bytes of some data, as much of it as the chunk dump shows.
+    fn leading_bytes(data: &[u8]) -> &[u8] {
+        &data[..data.len().min(30)]
+    }
+
+    /// Prints parsed content and records it as a field of the chunk being read.
+    fn print_chunk_content(&mut self, title: &str, content: String) {
+        let Some(chunk) = self.chunks.last_mut() else {
+            return self.print_content(title, content);
+        };
+
+        if self.verbose {
+            tracing::info!(chunk = %chunk.chunk_type, section = title, content = %content);
+        }
+
+        chunk.fields.push((title.to_string(), content));
+    }
+
+    /// Decodes the image data into `pixel_data`. Fails, saying where in the file, if it doesn't
+    /// inflate or is too short for the image.
+    pub fn decode_image_data(&mut self) -> Result<(), String> {
+        let _span = tracing::debug_span!("decode", width = self.info.width, height = self.info.height).entered();
+        let start = Instant::now();
+        let data = self.inflate()?;
+        self.timings.inflate = start.elapsed();
+        self.record_buffer_sizes(&data);
+
+        let mut callback = self.pass_callback.take();
+        let on_pass = |pixel_data: &[Vec<Pixel>]| {
+            if let Some(callback) = &mut callback {
+                callback(pixel_data);
+            }
+        };
+        let mut progress = self.progress.take();
+        let on_progress = |fraction| {
+            if let Some(progress) = &mut progress {
+                progress(Stage::Unfiltering, fraction);
+            }
+        };
+        let start = Instant::now();
+        let decoded = self.decode_pixels(&data, self.info.width as usize, self.info.height as usize, on_pass, on_progress);
+        self.timings.unfilter = start.elapsed();
+
+        self.pass_callback = callback;
+        self.progress = progress;
+        let (pixel_data, filter_types) = decoded.map_err(|e| error_at(self.image_data_offset(self.image_data.len()), &e))?;
+        self.buffer_sizes.pixels = pixel_data.capacity() * size_of::<Vec<Pixel>>() + pixel_data.iter().map(|row| row.capacity() * size_of::<Pixel>()).sum::<usize>();
+        self.pixel_data = pixel_data;
+        self.filter_types = filter_types;
+
+        if !self.is_cancelled() {
+            self.print_content("Scanline filters", self.filter_statistics().join("\n"));
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the image data straight into `out` in the given format, converting each scanline
+    /// as it's unfiltered and without building `pixel_data`, e.g. to fill a GPU staging buffer.
+    /// `out` must be exactly the size of the image in that format. 16-bit samples keep their
+    /// precision in `Rgba16` and are reduced as `set_dither` chooses for the other formats. The
+    /// pass callback isn't called.
+    pub fn decode_into(&mut self, out: &mut [u8], format: OutputFormat) -> Result<(), String> {
+        let (width, height) = (self.info.width as usize, self.info.height as usize);
+        let pixel_len = format.bytes_per_pixel();
+
+        if out.len() != width * height * pixel_len {
+            return Err(format!("The buffer is {} bytes, but {}x{} as {:?} takes {}", out.len(), width, height, format, width * height * pixel_len));
+        }
+
+        let _span = tracing::debug_span!("decode", width, height, ?format).entered();
+        let start = Instant::now();
+        let data = self.inflate()?;
+        self.timings.inflate = start.elapsed();
+        self.record_buffer_sizes(&data);
+        self.buffer_sizes.pixels = out.len();
+
+        let mut progress = self.progress.take();
+        let mut downconverter = None;
+        let channels = self.info.channels();
+        let on_scanline = |pass: &Pass, py: usize, scanline: &[u8]| {
+            let offset = |px: usize| ((pass.y + py * pass.y_step) * width + pass.x + px * pass.x_step) * pixel_len;
+
+            if format == OutputFormat::Rgba16 && self.info.bit_depth == 16 {
+                for (px, samples) in scanline.chunks_exact(channels * 2).enumerate() {
+                    let i = offset(px);
+                    OutputFormat::write_rgba16(self.to_rgba16(samples), &mut out[i..i + pixel_len]);
+                }
+
+                return;
+            }
+
+            for (px, pixel) in self.scanline_pixels(&mut downconverter, pass, width, py, scanline).into_iter().enumerate() {
+                let i = offset(px);
+                format.write(pixel, &mut out[i..i + pixel_len]);
+            }
+        };
+        let on_progress = |fraction| {
+            if let Some(progress) = &mut progress {
+                progress(Stage::Unfiltering, fraction);
+            }
+        };
+
+        let start = Instant::now();
+        let filter_types = self.unfilter(&data, width, height, on_scanline, on_progress);
+        self.timings.unfilter = start.elapsed();
+        self.progress = progress;
+        self.filter_types = filter_types.map_err(|e| error_at(self.image_data_offset(self.image_data.len()), &e))?;
+
+        match self.is_cancelled() {
+            true => Err("Decoding was cancelled".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    /// Decodes the image data into a new image in the given format, as `decode_into` does.
+    pub fn decode(&mut self, format: OutputFormat) -> Result<DecodedImage, String> {
+        let (width, height) = (self.info.width, self.info.height);
+        let mut data = vec![0; width as usize * height as usize * format.bytes_per_pixel()];
+
+        self.decode_into(&mut data, format)?;
+
+        Ok(DecodedImage { width, height, format, data })
+    }
+
+    /// Decompresses the image data, reporting progress.
+    #[cfg(not(feature = "pure-inflate"))]
+    fn inflate(&mut self) -> Result<Vec<u8>, String> {
+        let _span = tracing::debug_span!("inflate", compressed = self.image_data.len()).entered();
+        let mut data = Vec::<u8>::new();
+        let mut decoder = ZlibDecoder::new(self.image_data.as_slice());
+
+        while !self.is_cancelled() {
+            let read = decoder.by_ref().take(INFLATE_BLOCK).read_to_end(&mut data);
+
+            if read.map_err(|e| error_at(self.image_data_offset(decoder.total_in() as usize), &format!("corrupt image data: {}", e)))? == 0 {
+                break;
+            }
+
+            if let Some(progress) = &mut self.progress {
+                progress(Stage::Inflating, decoder.total_in() as f64 / self.image_data.len() as f64);
+            }
+        }
+
+        self.decompressed_size = data.len();
+
+        Ok(data)
+    }
+
+    /// Decompresses the image data in one go with the crate's own inflater, reporting progress
+    /// once it's done.
+    #[cfg(feature = "pure-inflate")]
+    fn inflate(&mut self) -> Result<Vec<u8>, String> {
+        let _span = tracing::debug_span!("inflate", compressed = self.image_data.len()).entered();
+        if self.is_cancelled() {
+            return Ok(vec![]);
+        }
+
+        // a block that doesn't decode starts where the last one that did ends, after the zlib header
+        let mut position = 2;
+        let data = self
+            .decompress_traced(&self.image_data, |block| position = 2 + (block.start_bit + block.bits) / 8)
+            .map_err(|e| error_at(self.image_data_offset(position), &format!("corrupt image data: {}", e)))?;
+
+        if let Some(progress) = &mut self.progress {
+            progress(Stage::Inflating, 1.0);
+        }
+
+        self.decompressed_size = data.len();
+
+        Ok(data)
+    }
+
+    /// Inflates a zlib stream.
+    #[cfg(not(feature = "pure-inflate"))]
+    fn decompress(&self, zlib: &[u8]) -> Result<Vec<u8>, String> {
+        let mut data = Vec::<u8>::new();
+        ZlibDecoder::new(zlib).read_to_end(&mut data).map_err(|e| e.to_string())?;
+
+        Ok(data)
+    }
+
+    /// Inflates a zlib stream with `deflate::inflate` instead of flate2, printing each block
+    /// as it's decoded when verbose.
+    #[cfg(feature = "pure-inflate")]
+    fn decompress(&self, zlib: &[u8]) -> Result<Vec<u8>, String> {
+        self.decompress_traced(zlib, |_| ())
+    }
+
+    /// Inflates a zlib stream as `decompress` does, also calling `on_block` with each block once
+    /// it's decoded.
+    #[cfg(feature = "pure-inflate")]
+    fn decompress_traced(&self, zlib: &[u8], mut on_block: impl FnMut(&deflate::Block)) -> Result<Vec<u8>, String> {
+        let mut index = 0;
+
+        deflate::inflate(zlib, |block| {
+            let content = format!(
+                "#{} {:?}, {} bits at bit {}: {} bytes out, {} literals, {} matches covering {} bytes (longest {}, furthest back {})",
+                index, block.block_type, block.bits, block.start_bit, block.output_bytes, block.literals, block.matches, block.matched_bytes, block.longest_match, block.max_distance,
+            );
+
+            self.print_content("Deflate block", content);
+            index += 1;
+            on_block(block);
+        })
+    }
+
+    /// Where byte `position` of the image data, which is split across the IDAT chunks, is in the
+    /// file; the end of the last IDAT chunk for a position past the end, or of the file if there
+    /// are none.
+    fn image_data_offset(&self, position: usize) -> usize {
+        let mut position = position;
+        let mut end = self.bytes.len();
+
+        for chunk in self.chunks.iter().filter(|chunk| chunk.chunk_type == "IDAT") {
+            if position < chunk.data_range.len() {
+                return chunk.data_range.start + position;
+            }
+
+            position -= chunk.data_range.len();
+            end = chunk.data_range.end;
+        }
+
+        end
+    }
+
+    /// The image data inflated but still filtered, split into interlace passes (a single pass
+    /// when not interlaced) and leaving out passes without pixels. Stops at the first pass the
+    /// data is too short for, and fails if the data doesn't inflate.
+    pub fn filtered_scanlines(&self) -> Result<Vec<FilteredPass>, String> {
+        let data = self.decompress(&self.image_data)?;
+
+        let bits_per_pixel = self.info.channels() * self.info.bit_depth as usize;
+        let (width, height) = (self.info.width as usize, self.info.height as usize);
+        let mut passes = vec![];
+        let mut idx = 0;
+
+        for pass in self.passes() {
+            let (pass_width, pass_height) = pass.size(width, height);
+
+            if pass_width == 0 || pass_height == 0 {
+                continue;
+            }
+
+            let len = ((pass_width * bits_per_pixel).div_ceil(8) + 1) * pass_height;
+            let Some(pass_data) = data.get(idx..idx + len) else {
+                break;
+            };
+
+            passes.push(FilteredPass { width: pass_width, height: pass_height, data: pass_data.to_vec() });
+            idx += len;
+        }
+
+        Ok(passes)
+    }
+
+    fn passes(&self) -> &'static [Pass] {
+        if self.info.interlace_method == 1 { &ADAM7 } else { &[NON_INTERLACED] }
+    }
+
+    /// Unfilters inflated image data of the given size into pixels, calling `on_pass` after each
+    /// interlace pass and `on_progress` with the fraction of the data done after each scanline.
+    /// Also returns the filter type of every scanline, per pass.
+    fn decode_pixels(
+        &self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        mut on_pass: impl FnMut(&[Vec<Pixel>]),
+        on_progress: impl FnMut(f64),
+    ) -> Result<DecodedPixels, String> {
+        let mut pixel_data = vec![vec![Default::default(); width]; height];
+        let mut downconverter = None;
+
+        let filter_types = self.unfilter(data, width, height, |pass, py, scanline| {
+            let y = pass.y + py * pass.y_step;
+
+            for (px, pixel) in self.scanline_pixels(&mut downconverter, pass, width, py, scanline).into_iter().enumerate() {
+                let x = pass.x + px * pass.x_step;
+
+                // replicate into the block this pixel stands for until later passes refine it
+                for row in &mut pixel_data[y..(y + pass.block_height).min(height)] {
+                    row[x..(x + pass.block_width).min(width)].fill(pixel);
+                }
+            }
+
+            if py + 1 == pass.size(width, height).1 {
+                on_pass(&pixel_data);
+            }
+        }, on_progress)?;
+
+        Ok((pixel_data, filter_types))
+    }
+
+    /// Splits inflated image data of the given size into interlace passes and reverses their
+    /// filters, calling `on_scanline` with the pass, the row within it and the unfiltered bytes
+    /// of each scanline, then `on_progress` with the fraction of the data done. Returns the filter
+    /// type of every scanline, per pass.
+    fn unfilter(
+        &self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        mut on_scanline: impl FnMut(&Pass, usize, &[u8]),
+        mut on_progress: impl FnMut(f64),
+    ) -> Result<Vec<Vec<u8>>, String> {
+        let _span = tracing::debug_span!("unfilter", width, height).entered();
+        let bits_per_pixel = self.info.channels() * self.info.bit_depth as usize;
+
+        // filters work on bytes, so pixels smaller than a byte are compared with the byte before
+        let bpp = bits_per_pixel.div_ceil(8);
+
+        let mut filter_types = vec![];
+        let mut idx = 0;
+
+        for pass in self.passes() {
+            let (pass_width, pass_height) = pass.size(width, height);
+
+            // image data cut short by cancelling inflation never gets sliced
+            if self.is_cancelled() {
+                break;
+            }
+
+            // passes without pixels have no scanlines at all, not even filter bytes
+            if pass_width == 0 || pass_height == 0 {
+                filter_types.push(vec![]);
+                continue;
+            }
+
+            let stride = (pass_width * bits_per_pixel).div_ceil(8);
+            let Some(pass_data) = data.get(idx..idx + (stride + 1) * pass_height) else {
+                return Err(format!("image data inflates to {} bytes, too few for a {}x{} image", data.len(), width, height));
+            };
+            let scanlines = Self::remove_filters(pass_data, stride, bpp);
+            filter_types.push(pass_data.chunks(stride + 1).map(|line| line[0]).collect());
+
+            for (py, scanline) in scanlines.iter().enumerate().take_while(|_| !self.is_cancelled()) {
+                on_scanline(pass, py, scanline);
+                on_progress((idx + (py + 1) * (stride + 1)) as f64 / data.len() as f64);
+            }
+
+            idx += (stride + 1) * pass_height;
+        }
+
+        Ok(filter_types)
+    }
+
+    /// The pixels of one unfiltered scanline of the pass, over an image `width` wide. 16-bit
+    /// samples are reduced with `downconverter`, which starts afresh at the top of each pass.
+    /// Pixels matching the tRNS colour key are made transparent, compared before reducing.
+    fn scanline_pixels(&self, downconverter: &mut Option<Downconverter>, pass: &Pass, width: usize, py: usize, scanline: &[u8]) -> Vec<Pixel> {
+        let channels = self.info.channels();
+
+        let converted;
+        let samples = match self.info.bit_depth {
+            16 => {
+                if py == 0 {
+                    *downconverter = Some(Downconverter::new(self.dither, pass.size(width, 0).0 * channels, channels));
+                }
+
+                converted = downconverter
+                    .as_mut()
+                    .expect("the downconverter starts with the pass")
+                    .scanline(scanline, pass.x, pass.x_step, pass.y + py * pass.y_step);
+                &converted
+            }
+            1 | 2 | 4 => {
+                converted = self.unpack_samples(scanline, pass.size(width, 0).0 * channels);
+                &converted
+            }
+            _ => scanline,
+        };
+
+        let mut pixels = samples.chunks_exact(channels).map(|bytes| self.to_pixel(bytes)).collect::<Vec<_>>();
+
+        if let Some(key) = self.colour_key() {
+            for (px, pixel) in pixels.iter_mut().enumerate() {
+                if (0..channels).all(|c| self.raw_sample(scanline, px * channels + c) == key[c]) {
+                    pixel.a = 0;
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// The tRNS colour, at the image's bit depth, whose greyscale or truecolour pixels are fully
+    /// transparent; the grey level repeated for greyscale.
+    fn colour_key(&self) -> Option<[u16; 3]> {
+        match self.transparency {
+            Some(Trns::Grey(grey)) => Some([grey; 3]),
+            Some(Trns::Rgb(r, g, b)) => Some([r, g, b]),
+            _ => None,
+        }
+    }
+
+    /// Sample `i` of an unfiltered scanline as stored, at the image's bit depth.
+    fn raw_sample(&self, scanline: &[u8], i: usize) -> u16 {
+        match self.info.bit_depth {
+            16 => u16::from_be_bytes([scanline[i * 2], scanline[i * 2 + 1]]),
+            8 => scanline[i] as u16,
+            depth => {
+                let depth = depth as usize;
+                let mask = (1u16 << depth) - 1;
+                (scanline[i * depth / 8] as u16 >> (8 - depth - i * depth % 8)) & mask
+            }
+        }
+    }
+
+    /// Decodes the region of every APNG frame, in order, without compositing them; none if the
+    /// file isn't animated. Fails on the first frame whose data doesn't inflate.
+    pub fn decode_frames(&self) -> Result<Vec<FrameRegion>, String> {
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(i, (control, compressed))| {
+                let data = self.decompress(compressed).map_err(|e| format!("Frame {}: {}", i + 1, e))?;
+
+                let (pixel_data, _) = self.decode_pixels(&data, control.width as usize, control.height as usize, |_| (), |_| ()).map_err(|e| format!("Frame {}: {}", i + 1, e))?;
+
+                Ok((*control, pixel_data))
+            })
+            .collect()
+    }
+
+    /// The filter type of the scanline each pixel was decoded from.
+    pub fn filter_map(&self) -> Vec<Vec<u8>> {
+        let passes = self.passes();
+        let mut map = vec![vec![0; self.info.width as usize]; self.info.height as usize];
+
+        for (pass, filter_types) in passes.iter().zip(&self.filter_types) {
+            for (py, &filter_type) in filter_types.iter().enumerate() {
+                let y = pass.y + py * pass.y_step;
+
+                for x in (pass.x..self.info.width as usize).step_by(pass.x_step) {
+                    map[y][x] = filter_type;
+                }
+            }
+        }
+
+        map
+    }
+
+    /// How many scanlines use each filter type, one line per type.
+    pub fn filter_statistics(&self) -> Vec<String> {
+        let mut counts = [0usize; 5];
+        let mut invalid = 0;
+
+        for &filter_type in self.filter_types.iter().flatten() {
+            match counts.get_mut(filter_type as usize) {
+                Some(count) => *count += 1,
+                None => invalid += 1,
+            }
+        }
+
+        let total = (counts.iter().sum::<usize>() + invalid).max(1);
+
+        let mut lines = FILTER_NAMES
+            .iter()
+            .zip(counts)
+            .map(|(name, count)| format!("{}: {} ({:.1}%)", name, count, count as f64 * 100.0 / total as f64))
+            .collect::<Vec<_>>();
+
+        if invalid > 0 {
+            lines.push(format!("Invalid: {}", invalid));
+        }
+
+        lines
+    }
+
+    /// Compression and filtering statistics, one per line, for evaluating optimisers. Only
+    /// complete after `decode_image_data`.
+    pub fn decode_statistics(&self) -> Vec<String> {
+        let idat_sizes = self.chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type == "IDAT")
+            .map(|chunk| chunk.data_range.len())
+            .collect::<Vec<_>>();
+
+        let bits_per_pixel = self.info.channels() * self.info.bit_depth as usize;
+        let compressed_size = self.image_data.len();
+
+        let mut lines = vec![
+            format!("IDAT chunks: {} ({})", idat_sizes.len(), idat_sizes.iter().map(|size| size.to_string()).collect::<Vec<_>>().join(", ")),
+            format!("Compressed: {} bytes", compressed_size),
+            format!("Decompressed: {} bytes", self.decompressed_size),
+            format!("Ratio: {:.1}%", compressed_size as f64 * 100.0 / self.decompressed_size.max(1) as f64),
+            format!("Bytes per pixel: {}", bits_per_pixel as f64 / 8.0),
+            format!("Compressed bits per pixel: {:.3}", compressed_size as f64 * 8.0 / (self.info.width as f64 * self.info.height as f64).max(1.0)),
+        ];
+
+        lines.extend(self.filter_statistics());
+
+        // runs of the same filter type, in file order
+        let mut runs: Vec<(u8, usize)> = vec![];
+
+        for &filter_type in self.filter_types.iter().flatten() {
+            match runs.last_mut() {
+                Some((last, count)) if *last == filter_type => *count += 1,
+                _ => runs.push((filter_type, 1)),
+            }
+        }
+
+        let runs = runs
+            .iter()
+            .map(|(filter_type, count)| match FILTER_NAMES.get(*filter_type as usize) {
+                Some(name) => format!("{} {}", count, name),
+                None => format!("{} invalid ({})", count, filter_type),
+            })
+            .collect::<Vec<_>>();
+
+        lines.push(format!("Per scanline: {}", runs.join(", ")));
+
+        lines
+    }
+
+    /// Reverses the per-scanline filters, returning the raw bytes of each scanline.
+    fn remove_filters(data: &[u8], stride: usize, bpp: usize) -> Vec<Vec<u8>> {
+        let mut scanlines: Vec<Vec<u8>> = Vec::with_capacity(data.len() / (stride + 1));
+
+        for line in data.chunks(stride + 1) {
+            let filter_type = line[0];
+            let mut scanline = line[1..].to_vec();
+
+            for i in 0..stride {
+                let prior = scanlines.last();
+                let a = if i >= bpp { scanline[i - bpp] } else { 0 };
+                let b = prior.map_or(0, |prior| prior[i]);
+                let c = if i >= bpp { prior.map_or(0, |prior| prior[i - bpp]) } else { 0 };
+
+                scanline[i] = Self::remove_filter(filter_type, scanline[i], a, b, c);
+            }
+
+            scanlines.push(scanline);
+        }
+
+        scanlines
+    }
+
+    /// Spreads the first `count` samples of a scanline packed several to a byte out to one a
+    /// byte, scaling grey levels up to 8 bits; palette indices are kept as they are. The unused
+    /// bits at the end of the scanline are left out.
+    fn unpack_samples(&self, scanline: &[u8], count: usize) -> Vec<u8> {
+        let depth = self.info.bit_depth as usize;
+        let mask = ((1u16 << depth) - 1) as u8;
+        let scale = if self.info.colour_type == 3 { 1 } else { 0xFF / mask };
+
+        (0..count)
+            .map(|i| {
+                let shift = 8 - depth - i * depth % 8;
+                (scanline[i * depth / 8] >> shift & mask) * scale
+            })
+            .collect()
+    }
+
+    fn to_pixel(&self, bytes: &[u8]) -> Pixel {
+        match self.info.colour_type {
+            0 => Pixel { r: bytes[0], g: bytes[0], b: bytes[0], a: 0xFF },
+            3 => self.palette_pixel(bytes[0]),
+            4 => Pixel { r: bytes[0], g: bytes[0], b: bytes[0], a: bytes[1] },
+            2 => Pixel { r: bytes[0], g: bytes[1], b: bytes[2], a: 0xFF },
+            6 => Pixel { r: bytes[0], g: bytes[1], b: bytes[2], a: bytes[3] },
+            _ => Default::default()
+        }
+    }
+
+    /// The palette entry at `index`, with its alpha from tRNS. An index past the end of the
+    /// palette is opaque black.
+    fn palette_pixel(&self, index: u8) -> Pixel {
+        let Some(entry) = self.palette.as_ref().and_then(|palette| palette.get(index as usize)) else {
+            return Pixel { r: 0, g: 0, b: 0, a: 0xFF };
+        };
+
+        let a = match &self.transparency {
+            Some(Trns::Palette(alphas)) => alphas.get(index as usize).copied().unwrap_or(0xFF),
+            _ => 0xFF,
+        };
+
+        Pixel { r: entry.r, g: entry.g, b: entry.b, a }
+    }
+
+    /// One pixel's big-endian 16-bit samples as red, green, blue and alpha, transparent if they
+    /// match the tRNS colour key.
+    fn to_rgba16(&self, bytes: &[u8]) -> [u16; 4] {
+        let sample = |i: usize| u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]]);
+        let keyed = self.colour_key().is_some_and(|key| (0..self.info.channels()).all(|c| sample(c) == key[c]));
+        let alpha = if keyed { 0 } else { 0xFFFF };
+
+        match self.info.colour_type {
+            0 => [sample(0), sample(0), sample(0), alpha],
+            4 => [sample(0), sample(0), sample(0), sample(1)],
+            2 => [sample(0), sample(1), sample(2), alpha],
+            6 => [sample(0), sample(1), sample(2), sample(3)],
+            _ => Default::default()
+        }
+    }
+
+    fn remove_filter(filter_type: u8, x: u8, a: u8, b: u8, c: u8) -> u8 {
+        match filter_type {
+            0 => {
+                x
+            }
+
+            1 => {
+                (x as i32 + a as i32) as u8
+            }
+            2 => {
+                (x as i32 + b as i32) as u8
+            }
+
+            3 => {
+                (x as i32 + ((a as i32 + b as i32) / 2)) as u8
+            }
+
+            4 => {
+                (x as i32 + Self::paeth(a, b, c) as i32) as u8
+            }
+
+            _ => 0
+        }
+    }
+
+    fn paeth(a: u8, b: u8, c: u8) -> u8 {
+        let a = a as i32;
+        let b = b as i32;
+        let c = c as i32;
+        let p = a + b - c;
+
+        let pa = (p - a).abs();
+        let pb = (p - b).abs();
+        let pc = (p - c).abs();
+
+        if pa <= pb && pa <= pc {
+            a as u8
+        } else if pb <= pc {
+            b as u8
+        } else {
+            c as u8
+        }
+    }
+}
+
+/// An error about the file at `offset`, saying where as `check` does.
+fn error_at(offset: usize, message: &str) -> String {
+    format!("At offset {} (0x{:x}): {}", offset, offset, message)
+}
@@ -0,0 +1,112 @@
+use std::io::Write;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use crate::crc::Crc;
+use crate::png_reader::Pixel;
+
+pub struct PngWriter {
+    width: u32,
+    height: u32,
+    pixel_data: Vec<Vec<Pixel>>,
+    /// Chunks written between IHDR and IDAT, in the order added.
+    ancillary: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl PngWriter {
+    pub fn new(width: u32, height: u32, pixel_data: Vec<Vec<Pixel>>) -> Self {
+        Self {
+            width,
+            height,
+            pixel_data,
+            ancillary: vec![],
+        }
+    }
+
+    /// Adds a chunk to write before the image data, e.g. sRGB or tEXt.
+    pub fn add_chunk(&mut self, chunk_type: [u8; 4], data: Vec<u8>) {
+        self.ancillary.push((chunk_type, data));
+    }
+
+    /// Encodes the pixels as an 8-bit RGBA, non-interlaced PNG.
+    pub fn write(&self) -> Vec<u8> {
+        let mut bytes = vec![137, 80, 78, 71, 13, 10, 26, 10];
+
+        Self::write_chunk(&mut bytes, b"IHDR", &self.ihdr());
+
+        for (chunk_type, data) in &self.ancillary {
+            Self::write_chunk(&mut bytes, chunk_type, data);
+        }
+
+        Self::write_chunk(&mut bytes, b"IDAT", &self.idat());
+        Self::write_chunk(&mut bytes, b"IEND", &[]);
+
+        bytes
+    }
+
+    /// IHDR data for 8-bit RGBA.
+    pub fn ihdr(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(13);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+
+        // bit depth, colour type, compression method, filter method, interlace method
+        data.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        data
+    }
+
+    /// The pixels filtered and deflated, as IDAT (or fdAT) data.
+    pub fn idat(&self) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+        for row in &self.pixel_data {
+            let mut scanline = Vec::with_capacity(1 + row.len() * 4);
+
+            // filter type: None
+            scanline.push(0);
+
+            for pixel in row {
+                scanline.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+            }
+
+            encoder.write_all(&scanline).unwrap();
+        }
+
+        encoder.finish().unwrap()
+    }
+
+    /// Encodes one 8-bit sample per pixel as a greyscale, non-interlaced PNG, e.g. for a mask.
+    pub fn write_grey(width: u32, height: u32, samples: &[Vec<u8>]) -> Vec<u8> {
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+        for row in samples {
+            // filter type: None
+            encoder.write_all(&[0]).unwrap();
+            encoder.write_all(row).unwrap();
+        }
+
+        let mut bytes = vec![137, 80, 78, 71, 13, 10, 26, 10];
+
+        Self::write_chunk(&mut bytes, b"IHDR", &ihdr);
+        Self::write_chunk(&mut bytes, b"IDAT", &encoder.finish().unwrap());
+        Self::write_chunk(&mut bytes, b"IEND", &[]);
+
+        bytes
+    }
+
+    pub fn write_chunk(bytes: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(chunk_type);
+        bytes.extend_from_slice(data);
+
+        let mut crc = Crc::new();
+        crc.update(chunk_type);
+        crc.update(data);
+        bytes.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use colored::Colorize;
+use crate::config::Config;
+
+/// Longest the recent files list grows before the oldest entries are dropped.
+const MAX_RECENT: usize = 20;
+
+fn path() -> Option<PathBuf> {
+    Config::dir().map(|dir| dir.join("recent.txt"))
+}
+
+/// The recently viewed images, most recent first, skipping any that no longer exist.
+pub fn load() -> Vec<PathBuf> {
+    let Some(text) = path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return vec![];
+    };
+
+    text.lines()
+        .map(PathBuf::from)
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Moves `image_path` to the front of the recent files list.
+pub fn add(image_path: &Path) {
+    let Some(path) = path() else {
+        return;
+    };
+
+    let image_path = image_path.canonicalize().unwrap_or_else(|_| image_path.to_path_buf());
+
+    let mut recent = load();
+    recent.retain(|p| *p != image_path);
+    recent.insert(0, image_path);
+    recent.truncate(MAX_RECENT);
+
+    let text = recent
+        .iter()
+        .map(|p| format!("{}\n", p.display()))
+        .collect::<String>();
+
+    let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(&path, text));
+
+    if let Err(e) = result {
+        eprintln!("{} {}: {}", "Failed to save recent files".red(), path.display(), e);
+    }
+}
+
+/// Lists the recent files and asks which to open, defaulting to the most recent.
+/// Returns None if there are none or the answer isn't one of them.
+pub fn pick() -> Option<PathBuf> {
+    let recent = load();
+
+    if recent.is_empty() {
+        eprintln!("{}", "No recent files".red());
+        return None;
+    }
+
+    for (i, path) in recent.iter().enumerate() {
+        println!("{:>3}  {}", (i + 1).to_string().green(), path.display());
+    }
+
+    print!("Open [1]: ");
+    io::stdout().flush().ok()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+
+    let choice = match answer.trim() {
+        "" => 1,
+        answer => answer.parse::<usize>().ok()?,
+    };
+
+    recent.get(choice.checked_sub(1)?).cloned()
+}
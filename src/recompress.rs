@@ -0,0 +1,86 @@
+use std::fs;
+use std::io::{Read, Write};
+use colored::Colorize;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use crate::png_reader::PngReader;
+use crate::png_writer::PngWriter;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Re-deflates the image data at every zlib level, and with zopfli if asked, and reports how
+/// much smaller each would make the file. With `write` the smallest result replaces the image
+/// data in the file as a single IDAT, every other chunk kept byte for byte, but only if it saves
+/// anything.
+pub fn run(image_path: &str, reader: &PngReader, zopfli: bool, write: bool) -> Result<(), String> {
+    let original = reader.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type == "IDAT")
+        .flat_map(|chunk| reader.chunk_data(chunk))
+        .copied()
+        .collect::<Vec<u8>>();
+
+    let mut data = vec![];
+    ZlibDecoder::new(&original[..]).read_to_end(&mut data).map_err(|e| format!("Invalid image data: {}", e))?;
+
+    println!("{} {} bytes of image data, {} bytes inflated", "IDAT".green(), original.len(), data.len());
+    println!();
+    println!("{:<8} {:>12} {:>12}", "Level", "Size", "Change");
+
+    let mut candidates = (0..=9)
+        .map(|level| {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+            encoder.write_all(&data).and_then(|_| encoder.finish()).map(|compressed| (level.to_string(), compressed))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    if zopfli {
+        let mut compressed = vec![];
+        zopfli::compress(zopfli::Options::default(), zopfli::Format::Zlib, &data[..], &mut compressed).map_err(|e| e.to_string())?;
+        candidates.push(("zopfli".to_string(), compressed));
+    }
+
+    for (name, compressed) in &candidates {
+        let saving = original.len() as i64 - compressed.len() as i64;
+        let text = format!("{:<8} {:>12} {:>+12} ({:+.1}%)", name, compressed.len(), -saving, -saving as f64 * 100.0 / original.len().max(1) as f64);
+
+        println!("{}", if saving > 0 { text.green() } else { text.normal() });
+    }
+
+    let (name, best) = candidates.iter().min_by_key(|(_, compressed)| compressed.len()).expect("No candidates");
+
+    println!();
+
+    if best.len() >= original.len() {
+        println!("The image data is already as small as recompressing makes it");
+        return Ok(());
+    }
+
+    println!("Best: {}, saving {} bytes", name, original.len() - best.len());
+
+    if !write {
+        println!("Pass --write to rewrite {}", image_path);
+        return Ok(());
+    }
+
+    let mut bytes = SIGNATURE.to_vec();
+    let mut wrote_idat = false;
+
+    for chunk in reader.chunks() {
+        match chunk.chunk_type.as_str() {
+            "IDAT" if wrote_idat => (),
+            "IDAT" => {
+                PngWriter::write_chunk(&mut bytes, b"IDAT", best);
+                wrote_idat = true;
+            }
+            _ => bytes.extend_from_slice(reader.chunk_bytes(chunk)),
+        }
+    }
+
+    fs::write(image_path, &bytes).map_err(|e| format!("Failed to write {}: {}", image_path, e))?;
+    println!("{} {} ({} bytes)", "Wrote".green(), image_path, bytes.len());
+
+    Ok(())
+}
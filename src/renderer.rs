@@ -0,0 +1,201 @@
+use std::num::{NonZeroIsize, NonZeroU32};
+use std::ptr::NonNull;
+use std::rc::Rc;
+use colored::Colorize;
+use pixels::raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle};
+use pixels::wgpu::SurfaceError;
+use pixels::{Pixels, SurfaceTexture};
+use rwh_06::{DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, WindowHandle};
+use softbuffer::{Context, Surface};
+use winit::window::Window;
+use crate::gpu::GpuRenderer;
+use crate::viewer::Viewer;
+
+/// Frames in a row the GPU may fail to get a surface for, each time reconfiguring it, before
+/// drawing falls back to the CPU.
+const LOST_FRAME_LIMIT: u32 = 3;
+
+/// Puts the viewer's frames on screen: with wgpu when there's a usable adapter, otherwise by
+/// drawing on the CPU and blitting with softbuffer, so the viewer still works in VMs, CI and on
+/// old hardware. A transparent window is always drawn on the CPU, as `pixels` leaves its surface
+/// opaque.
+pub enum Renderer {
+    Gpu {
+        window: Rc<Window>,
+        pixels: Box<Pixels>,
+        gpu: Box<GpuRenderer>,
+        /// Frames in a row lost along with the surface.
+        lost_frames: u32,
+    },
+    Software {
+        surface: Surface<SoftbufferWindow, SoftbufferWindow>,
+        frame: Vec<u8>,
+        /// Whether the frame's alpha goes to the window, which then needs a 32-bit visual. Only
+        /// X11 takes it; elsewhere the window stays opaque.
+        alpha: bool,
+    },
+}
+
+impl Renderer {
+    pub fn new(window: &Rc<Window>, transparent: bool) -> Self {
+        if transparent {
+            return Self::software(window, true);
+        }
+
+        let size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(size.width, size.height, window.as_ref());
+
+        match Pixels::new(size.width, size.height, surface_texture) {
+            Ok(pixels) => {
+                let gpu = Box::new(GpuRenderer::new(&pixels));
+                Self::Gpu { window: window.clone(), pixels: Box::new(pixels), gpu, lost_frames: 0 }
+            }
+            Err(e) => {
+                eprintln!("{} ({}), falling back to software rendering", "No usable GPU".red(), e);
+                Self::software(window, false)
+            }
+        }
+    }
+
+    fn software(window: &Rc<Window>, alpha: bool) -> Self {
+        let size = window.inner_size();
+        let context = Context::new(SoftbufferWindow(window.clone())).unwrap();
+        let mut surface = Surface::new(&context, SoftbufferWindow(window.clone())).unwrap();
+        resize_surface(&mut surface, size.width, size.height);
+
+        Self::Software {
+            surface,
+            frame: vec![0; size.width as usize * size.height as usize * 4],
+            alpha,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        match self {
+            Self::Gpu { window, pixels, .. } => {
+                // a window larger than the biggest texture the GPU takes can only be drawn on the CPU
+                if let Err(e) = pixels.resize_surface(width, height).and_then(|_| pixels.resize_buffer(width, height)) {
+                    eprintln!("{} ({}), falling back to software rendering", "Failed to resize the GPU surface".red(), e);
+                    *self = Self::software(&window.clone(), false);
+                }
+            }
+            Self::Software { surface, frame, .. } => {
+                resize_surface(surface, width, height);
+                frame.resize(width as usize * height as usize * 4, 0);
+            }
+        }
+    }
+
+    pub fn render(&mut self, viewer: &Viewer) {
+        match self {
+            Self::Gpu { window, pixels, gpu, lost_frames } => {
+                let result = if gpu.can_render(viewer) {
+                    viewer.draw_overlays_only(pixels.frame_mut());
+                    gpu.render(pixels, viewer)
+                } else {
+                    viewer.draw(pixels.frame_mut());
+                    pixels.render()
+                };
+
+                match result {
+                    Ok(()) => *lost_frames = 0,
+                    // the GPU was too busy to hand out a frame in time; the next one may get one
+                    Err(pixels::Error::Surface(SurfaceError::Timeout)) => window.request_redraw(),
+                    // the surface no longer matches the window, e.g. after a display change or the
+                    // window going between monitors, so it's configured afresh for the next frame
+                    Err(pixels::Error::Surface(SurfaceError::Lost | SurfaceError::Outdated)) if *lost_frames < LOST_FRAME_LIMIT => {
+                        *lost_frames += 1;
+
+                        let size = window.inner_size();
+                        let _ = pixels.resize_surface(size.width, size.height);
+                        window.request_redraw();
+                    }
+                    Err(e) => {
+                        eprintln!("{} ({}), falling back to software rendering", "GPU rendering failed".red(), e);
+
+                        let window = window.clone();
+                        *self = Self::software(&window, false);
+                        window.request_redraw();
+                    }
+                }
+            }
+            Self::Software { surface, frame, alpha } => {
+                viewer.draw(frame);
+
+                let Ok(mut buffer) = surface.buffer_mut() else {
+                    return;
+                };
+
+                // softbuffer wants 0RGB, or premultiplied ARGB for a 32-bit visual; the frame may be
+                // a resize behind the window, so copy only what both have
+                let alpha_mask = if *alpha { 0xFF } else { 0 };
+
+                for (dst, src) in buffer.iter_mut().zip(frame.chunks_exact(4)) {
+                    *dst = ((src[3] & alpha_mask) as u32) << 24 | (src[0] as u32) << 16 | (src[1] as u32) << 8 | src[2] as u32;
+                }
+
+                let _ = buffer.present();
+            }
+        }
+    }
+}
+
+fn resize_surface(surface: &mut Surface<SoftbufferWindow, SoftbufferWindow>, width: u32, height: u32) {
+    if let (Some(width), Some(height)) = (NonZeroU32::new(width), NonZeroU32::new(height)) {
+        let _ = surface.resize(width, height);
+    }
+}
+
+/// The window, with its handles converted to the `raw-window-handle` version softbuffer
+/// uses, which is newer than the one winit provides.
+pub struct SoftbufferWindow(Rc<Window>);
+
+impl HasWindowHandle for SoftbufferWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let raw: rwh_06::RawWindowHandle = match self.0.raw_window_handle() {
+            RawWindowHandle::Xlib(handle) => {
+                let mut converted = rwh_06::XlibWindowHandle::new(handle.window);
+                converted.visual_id = handle.visual_id;
+                converted.into()
+            }
+            RawWindowHandle::Xcb(handle) => {
+                let mut converted = rwh_06::XcbWindowHandle::new(NonZeroU32::new(handle.window).ok_or(HandleError::Unavailable)?);
+                converted.visual_id = NonZeroU32::new(handle.visual_id);
+                converted.into()
+            }
+            RawWindowHandle::Wayland(handle) => {
+                rwh_06::WaylandWindowHandle::new(NonNull::new(handle.surface).ok_or(HandleError::Unavailable)?).into()
+            }
+            RawWindowHandle::Win32(handle) => {
+                let mut converted = rwh_06::Win32WindowHandle::new(NonZeroIsize::new(handle.hwnd as isize).ok_or(HandleError::Unavailable)?);
+                converted.hinstance = NonZeroIsize::new(handle.hinstance as isize);
+                converted.into()
+            }
+            RawWindowHandle::AppKit(handle) => {
+                rwh_06::AppKitWindowHandle::new(NonNull::new(handle.ns_view).ok_or(HandleError::Unavailable)?).into()
+            }
+            _ => return Err(HandleError::NotSupported),
+        };
+
+        // SAFETY: the handle belongs to the window this owns, which outlives the borrow
+        Ok(unsafe { WindowHandle::borrow_raw(raw) })
+    }
+}
+
+impl HasDisplayHandle for SoftbufferWindow {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let raw: rwh_06::RawDisplayHandle = match self.0.raw_display_handle() {
+            RawDisplayHandle::Xlib(handle) => rwh_06::XlibDisplayHandle::new(NonNull::new(handle.display), handle.screen).into(),
+            RawDisplayHandle::Xcb(handle) => rwh_06::XcbDisplayHandle::new(NonNull::new(handle.connection), handle.screen).into(),
+            RawDisplayHandle::Wayland(handle) => {
+                rwh_06::WaylandDisplayHandle::new(NonNull::new(handle.display).ok_or(HandleError::Unavailable)?).into()
+            }
+            RawDisplayHandle::Windows(_) => rwh_06::WindowsDisplayHandle::new().into(),
+            RawDisplayHandle::AppKit(_) => rwh_06::AppKitDisplayHandle::new().into(),
+            _ => return Err(HandleError::NotSupported),
+        };
+
+        // SAFETY: as above, the display connection lives as long as the window
+        Ok(unsafe { DisplayHandle::borrow_raw(raw) })
+    }
+}
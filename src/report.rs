@@ -0,0 +1,195 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use colored::Colorize;
+use crate::check;
+use crate::options::Options;
+use crate::png_reader::{Pixel, PngReader};
+use crate::png_writer::PngWriter;
+use crate::resize;
+use crate::transform;
+
+/// Longest side of the embedded preview.
+const PREVIEW_SIZE: u32 = 512;
+const HISTOGRAM_HEIGHT: u32 = 100;
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const STYLE: &str = "\
+body { font: 14px sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; word-break: break-all; }
+h2 { font-size: 1.1em; margin-top: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+td.number { text-align: right; font-family: monospace; }
+.ok { color: #080; }
+.bad { color: #c00; font-weight: bold; }
+.preview { background: repeating-conic-gradient(#ccc 0% 25%, #fff 0% 50%) 0 0 / 16px 16px; border: 1px solid #ccc; }
+.histograms svg { margin-right: 1em; border: 1px solid #ccc; background: #fafafa; }
+pre { background: #f4f4f4; padding: 0.8em; overflow-x: auto; }
+";
+
+/// Writes a standalone HTML page about the file to `output`, for attaching to bug reports: the
+/// check's findings, and, if the decoder can read the file, a preview, the metadata, every chunk
+/// with its parsed fields and the histogram of each channel.
+pub fn run(image_path: &str, options: &Options, output: &Path) -> Result<(), String> {
+    let bytes = fs::read(image_path).map_err(|e| format!("Failed to open {}: {}", image_path, e))?;
+    let validation = check::report(image_path, &bytes);
+
+    let mut reader = PngReader::new(bytes.clone());
+    reader.set_verbose(false);
+    reader.set_dither(options.dither);
+    let decoded = reader.read_chunks().and_then(|()| reader.decode_image_data());
+
+    let mut html = String::new();
+    let title = escape(&Path::new(image_path).file_name().map_or_else(|| image_path.into(), |name| name.to_string_lossy()));
+
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>", title, STYLE).unwrap();
+    writeln!(html, "<h1>{}</h1>\n<p>{} bytes</p>", escape(image_path), bytes.len()).unwrap();
+
+    match decoded {
+        Ok(()) => {
+            let pixel_data = transform::orient(&reader.pixel_data, crate::orientation(&reader, options));
+
+            preview(&mut html, &pixel_data, options);
+            metadata(&mut html, &reader);
+            chunks(&mut html, &reader);
+            histograms(&mut html, &pixel_data);
+        }
+        Err(e) => writeln!(html, "<p class=\"bad\">The decoder couldn't read this file ({}), so there's no preview; see the check below.</p>", escape(&e)).unwrap(),
+    }
+
+    let (class, verdict) = match validation.errors.len() {
+        0 => ("ok", "No errors detected".to_string()),
+        1 => ("bad", "1 error".to_string()),
+        n => ("bad", format!("{} errors", n)),
+    };
+
+    writeln!(html, "<h2>Check</h2>\n<p class=\"{}\">{}</p>\n<pre>{}</pre>", class, verdict, escape(&validation.lines.join("\n"))).unwrap();
+    html.push_str("</body>\n</html>\n");
+
+    fs::write(output, &html).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{} {} -> {}", "Reported".green(), image_path, output.display());
+
+    Ok(())
+}
+
+/// The image, scaled down to fit `PREVIEW_SIZE` if it's larger, as an embedded PNG.
+fn preview(html: &mut String, pixel_data: &[Vec<Pixel>], options: &Options) {
+    let (width, height) = (pixel_data.first().map_or(0, |row| row.len()) as u32, pixel_data.len() as u32);
+
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let scale = (PREVIEW_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let size = |n: u32| ((n as f64 * scale).round() as u32).max(1);
+    let (preview_width, preview_height) = (size(width), size(height));
+
+    let pixel_data = match scale < 1.0 {
+        true => resize::resize(pixel_data, preview_width, preview_height, options.resize_filter),
+        false => pixel_data.to_vec(),
+    };
+
+    let png = PngWriter::new(preview_width, preview_height, pixel_data).write();
+
+    writeln!(
+        html,
+        "<h2>Preview</h2>\n<img class=\"preview\" width=\"{}\" height=\"{}\" alt=\"preview\" src=\"data:image/png;base64,{}\">",
+        preview_width, preview_height, base64(&png),
+    ).unwrap();
+}
+
+fn metadata(html: &mut String, reader: &PngReader) {
+    html.push_str("<h2>Metadata</h2>\n<table>\n");
+
+    for line in reader.summary() {
+        let (name, value) = line.split_once(": ").unwrap_or((&line, ""));
+        writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value)).unwrap();
+    }
+
+    html.push_str("</table>\n");
+}
+
+fn chunks(html: &mut String, reader: &PngReader) {
+    html.push_str("<h2>Chunks</h2>\n<table>\n<tr><th>Type</th><th>Offset</th><th>Length</th><th>CRC</th><th>Content</th></tr>\n");
+
+    for chunk in reader.chunks() {
+        let crc = match chunk.crc == chunk.computed_crc {
+            true => format!("<span class=\"ok\">{:08x}</span>", chunk.crc),
+            false => format!("<span class=\"bad\">{:08x}, computed {:08x}</span>", chunk.crc, chunk.computed_crc),
+        };
+
+        let fields = chunk.fields
+            .iter()
+            .map(|(title, content)| format!("<b>{}</b><br>{}", escape(title), escape(content).replace('\n', "<br>")))
+            .collect::<Vec<_>>()
+            .join("<br>");
+
+        writeln!(
+            html,
+            "<tr><td><code>{}</code></td><td class=\"number\">{}</td><td class=\"number\">{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&chunk.chunk_type), chunk.offset, chunk.data_range.len(), crc, fields,
+        ).unwrap();
+    }
+
+    html.push_str("</table>\n");
+}
+
+/// A bar chart of each channel's 8-bit values, alpha only when something isn't opaque. Each is
+/// scaled to its own tallest bar.
+fn histograms(html: &mut String, pixel_data: &[Vec<Pixel>]) {
+    let mut counts = [[0u32; 256]; 4];
+
+    for pixel in pixel_data.iter().flatten() {
+        for (channel, value) in [pixel.r, pixel.g, pixel.b, pixel.a].into_iter().enumerate() {
+            counts[channel][value as usize] += 1;
+        }
+    }
+
+    let opaque = counts[3][255] as usize == pixel_data.iter().map(|row| row.len()).sum::<usize>();
+    let channels = [("Red", "#d33"), ("Green", "#3a3"), ("Blue", "#36d"), ("Alpha", "#666")];
+
+    html.push_str("<h2>Histograms</h2>\n<div class=\"histograms\">\n");
+
+    for ((name, colour), counts) in channels.iter().zip(&counts).take(if opaque { 3 } else { 4 }) {
+        let tallest = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let mut path = String::new();
+
+        for (value, &count) in counts.iter().enumerate() {
+            let height = (count as f64 / tallest * HISTOGRAM_HEIGHT as f64).round();
+
+            if height > 0.0 {
+                write!(path, "M{} {}v{}h1v{}z", value, HISTOGRAM_HEIGHT, -height, height).unwrap();
+            }
+        }
+
+        writeln!(
+            html,
+            "<svg width=\"256\" height=\"{}\" viewBox=\"0 0 256 {}\"><title>{}</title><path fill=\"{}\" d=\"{}\"/></svg>",
+            HISTOGRAM_HEIGHT, HISTOGRAM_HEIGHT, name, colour, path,
+        ).unwrap();
+    }
+
+    html.push_str("</div>\n");
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for group in bytes.chunks(3) {
+        let n = (group[0] as u32) << 16 | (*group.get(1).unwrap_or(&0) as u32) << 8 | *group.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            match i <= group.len() {
+                true => encoded.push(BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+
+    encoded
+}
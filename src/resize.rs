@@ -0,0 +1,163 @@
+use std::f64::consts::PI;
+use crate::png_reader::Pixel;
+
+/// Target size for `--resize`: a percentage of the image's size, or a width and height with
+/// either left out to keep the aspect ratio.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Resize {
+    Percent(f64),
+    Size(Option<u32>, Option<u32>),
+}
+
+impl Resize {
+    /// Parses `50%`, `WxH`, `Wx` or `xH`.
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(percent) = text.strip_suffix('%') {
+            return percent.trim().parse().ok().filter(|&percent: &f64| percent > 0.0).map(Self::Percent);
+        }
+
+        let (width, height) = text.split_once('x')?;
+        let dimension = |text: &str| match text.trim() {
+            "" => Some(None),
+            text => text.parse().ok().filter(|&n: &u32| n > 0).map(Some),
+        };
+
+        match (dimension(width)?, dimension(height)?) {
+            (None, None) => None,
+            (width, height) => Some(Self::Size(width, height)),
+        }
+    }
+
+    /// The size an image of `width` x `height` is resized to, at least 1x1.
+    pub fn target(self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |n: u32, factor: f64| ((n as f64 * factor).round() as u32).max(1);
+
+        match self {
+            Self::Percent(percent) => (scaled(width, percent / 100.0), scaled(height, percent / 100.0)),
+            Self::Size(Some(w), Some(h)) => (w, h),
+            Self::Size(Some(w), None) => (w, scaled(height, w as f64 / width.max(1) as f64)),
+            Self::Size(None, Some(h)) => (scaled(width, h as f64 / height.max(1) as f64), h),
+            Self::Size(None, None) => (width, height),
+        }
+    }
+}
+
+/// How `--resize` resamples.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ResizeFilter {
+    /// The average of the pixels each output pixel covers; blocky when enlarging.
+    Box,
+    /// A triangle filter: bilinear interpolation when enlarging, and a soft average when shrinking.
+    Bilinear,
+    /// Lanczos with three lobes, the sharpest, at the cost of faint ringing along hard edges.
+    Lanczos,
+}
+
+impl ResizeFilter {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "box" => Some(Self::Box),
+            "bilinear" => Some(Self::Bilinear),
+            "lanczos" => Some(Self::Lanczos),
+            _ => None,
+        }
+    }
+
+    /// Half the kernel's width, in source pixels when not shrinking.
+    fn support(self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Bilinear => 1.0,
+            Self::Lanczos => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+
+        match self {
+            Self::Box => if x <= 0.5 { 1.0 } else { 0.0 },
+            Self::Bilinear => (1.0 - x).max(0.0),
+            Self::Lanczos => match x {
+                x if x < 1e-9 => 1.0,
+                x if x < 3.0 => 3.0 * (PI * x).sin() * (PI * x / 3.0).sin() / (PI * PI * x * x),
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// Resamples the pixels to `width` x `height`, one axis at a time. Colours are premultiplied by
+/// alpha while filtering so transparent pixels' colours don't bleed into their neighbours.
+pub fn resize(pixel_data: &[Vec<Pixel>], width: u32, height: u32, filter: ResizeFilter) -> Vec<Vec<Pixel>> {
+    let premultiplied = pixel_data
+        .iter()
+        .map(|row| row.iter().map(|p| {
+            let a = p.a as f64 / 255.0;
+            [p.r as f64 * a, p.g as f64 * a, p.b as f64 * a, p.a as f64]
+        }).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let source_width = premultiplied.first().map_or(0, |row| row.len());
+    let columns = weights(source_width, width as usize, filter);
+    let rows = weights(premultiplied.len(), height as usize, filter);
+
+    let horizontal = premultiplied
+        .iter()
+        .map(|row| columns.iter().map(|(start, weights)| convolve(weights.iter().enumerate().map(|(i, w)| (row[start + i], *w)))).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    rows.iter()
+        .map(|(start, weights)| {
+            (0..width as usize)
+                .map(|x| {
+                    let [r, g, b, a] = convolve(weights.iter().enumerate().map(|(i, w)| (horizontal[start + i][x], *w)));
+                    let unpremultiply = |c: f64| if a > 0.0 { (c * 255.0 / a).round().clamp(0.0, 255.0) as u8 } else { 0 };
+
+                    Pixel { r: unpremultiply(r), g: unpremultiply(g), b: unpremultiply(b), a: a.round().clamp(0.0, 255.0) as u8 }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn convolve(taps: impl Iterator<Item = ([f64; 4], f64)>) -> [f64; 4] {
+    taps.fold([0.0; 4], |mut sum, (sample, weight)| {
+        for (s, c) in sum.iter_mut().zip(sample) {
+            *s += c * weight;
+        }
+
+        sum
+    })
+}
+
+/// For each of `to` output pixels along an axis of `from` pixels, the first source pixel it
+/// draws on and the normalised weights of it and those after. Shrinking widens the kernel so
+/// every source pixel contributes.
+fn weights(from: usize, to: usize, filter: ResizeFilter) -> Vec<(usize, Vec<f64>)> {
+    let ratio = from as f64 / to as f64;
+    let scale = ratio.max(1.0);
+    let support = filter.support() * scale;
+
+    (0..to)
+        .map(|i| {
+            let centre = (i as f64 + 0.5) * ratio;
+            let start = ((centre - support).floor().max(0.0) as usize).min(from - 1);
+            let end = ((centre + support).ceil() as usize).clamp(start + 1, from);
+
+            let mut weights = (start..end).map(|j| filter.weight((j as f64 + 0.5 - centre) / scale)).collect::<Vec<_>>();
+            let total = weights.iter().sum::<f64>();
+
+            // a box narrower than the pixel spacing can fall between samples; take the nearest
+            match total.abs() > 1e-9 {
+                true => weights.iter_mut().for_each(|w| *w /= total),
+                false => {
+                    weights.fill(0.0);
+                    weights[((centre - 0.5).round().max(0.0) as usize).clamp(start, end - 1) - start] = 1.0;
+                }
+            }
+
+            (start, weights)
+        })
+        .collect()
+}
@@ -0,0 +1,356 @@
+use std::io::Write;
+use colored::Colorize;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use crate::apng;
+use png_viewer::crc::Crc;
+use crate::png_reader::{Pixel, PngReader};
+use crate::png_writer::PngWriter;
+use Expected::*;
+
+/// Side of every corpus image, as in PngSuite.
+const SIZE: usize = 32;
+
+/// What decoding an image should give.
+#[derive(Copy, Clone)]
+enum Expected {
+    /// `SIZE`x`SIZE` pixels whose 8-bit RGBA, row by row, has this CRC-32.
+    Pixels(u32),
+    /// As `Pixels`, with this gamma reported from gAMA but not applied to the pixels.
+    Gamma(u32, f64),
+    /// An error from reading the chunks or decoding the image data.
+    Error,
+    /// A chunk whose stored CRC doesn't match its type and data.
+    BadCrc,
+}
+
+
+/// One image of the corpus.
+struct Case {
+    /// PngSuite name, e.g. `basi3p02` for an interlaced 2-bit palette image: the series, then
+    /// `n` or `i` for interlacing, the colour type, a letter for it and the bit depth.
+    name: &'static str,
+    bytes: &'static [u8],
+    expected: Expected,
+}
+
+impl Case {
+    /// The character of the name that `FEATURES` reads a feature from.
+    fn field(&self, i: usize) -> &'static str {
+        &self.name[i..i + 1]
+    }
+}
+
+macro_rules! case {
+    ($name:literal, $expected:expr) => {
+        Case {
+            name: $name,
+            bytes: include_bytes!(concat!("../images/pngsuite/", $name, ".png")),
+            expected: $expected,
+        }
+    };
+}
+
+/// The corpus in `images/pngsuite`: the basic formats, every filter type on its own,
+/// transparency, gamma and corrupt files. The CRCs were computed from the samples each file was
+/// written from, scaled as the decoder scales them, so they have to be recomputed if a file
+/// changes.
+static CASES: &[Case] = &[
+    case!("basn0g01", Pixels(0x2BC1151A)),
+    case!("basn0g02", Pixels(0xA9BF798A)),
+    case!("basn0g04", Pixels(0xDED5F03F)),
+    case!("basn0g08", Pixels(0xD8A9EED5)),
+    case!("basn0g16", Pixels(0x19736001)),
+    case!("basn2c08", Pixels(0x67B0F33C)),
+    case!("basn2c16", Pixels(0xE3FFE69F)),
+    case!("basn3p01", Pixels(0xBCFF1187)),
+    case!("basn3p02", Pixels(0x38D79A9D)),
+    case!("basn3p04", Pixels(0x837A5872)),
+    case!("basn3p08", Pixels(0x737F9A18)),
+    case!("basn4a08", Pixels(0x80A70D51)),
+    case!("basn4a16", Pixels(0x0D14ED45)),
+    case!("basn6a08", Pixels(0x2D077EDC)),
+    case!("basn6a16", Pixels(0xFEF004CA)),
+    case!("basi0g01", Pixels(0x2BC1151A)),
+    case!("basi0g02", Pixels(0xA9BF798A)),
+    case!("basi0g04", Pixels(0xDED5F03F)),
+    case!("basi0g08", Pixels(0xD8A9EED5)),
+    case!("basi0g16", Pixels(0x19736001)),
+    case!("basi2c08", Pixels(0x67B0F33C)),
+    case!("basi2c16", Pixels(0xE3FFE69F)),
+    case!("basi3p01", Pixels(0xBCFF1187)),
+    case!("basi3p02", Pixels(0x38D79A9D)),
+    case!("basi3p04", Pixels(0x837A5872)),
+    case!("basi3p08", Pixels(0x737F9A18)),
+    case!("basi4a08", Pixels(0x80A70D51)),
+    case!("basi4a16", Pixels(0x0D14ED45)),
+    case!("basi6a08", Pixels(0x2D077EDC)),
+    case!("basi6a16", Pixels(0xFEF004CA)),
+    case!("f00n0g08", Pixels(0xD8A9EED5)),
+    case!("f01n0g08", Pixels(0xD8A9EED5)),
+    case!("f02n0g08", Pixels(0xD8A9EED5)),
+    case!("f03n0g08", Pixels(0xD8A9EED5)),
+    case!("f04n0g08", Pixels(0xD8A9EED5)),
+    case!("f00n2c08", Pixels(0x67B0F33C)),
+    case!("f01n2c08", Pixels(0x67B0F33C)),
+    case!("f02n2c08", Pixels(0x67B0F33C)),
+    case!("f03n2c08", Pixels(0x67B0F33C)),
+    case!("f04n2c08", Pixels(0x67B0F33C)),
+    case!("tbbn0g04", Pixels(0xBF34774E)),
+    case!("tbwn0g16", Pixels(0x625D7416)),
+    case!("tbrn2c08", Pixels(0x9D8CEB4A)),
+    case!("tbbn2c16", Pixels(0xA3036DD0)),
+    case!("tbgn2c16", Pixels(0xE40D78BF)),
+    case!("tbbn3p08", Pixels(0x67F17C92)),
+    case!("tbwn3p08", Pixels(0xE53FBA34)),
+    case!("tp0n0g08", Pixels(0xD8A9EED5)),
+    case!("tp0n2c08", Pixels(0x67B0F33C)),
+    case!("tp0n3p08", Pixels(0x737F9A18)),
+    case!("tp1n3p08", Pixels(0x813EEAAC)),
+    case!("tm3n3p02", Pixels(0x5AD89033)),
+    case!("g03n0g16", Gamma(0x19736001, 0.35)),
+    case!("g03n2c08", Gamma(0x67B0F33C, 0.35)),
+    case!("g03n3p04", Gamma(0x837A5872, 0.35)),
+    case!("g10n0g16", Gamma(0x19736001, 1.0)),
+    case!("g10n2c08", Gamma(0x67B0F33C, 1.0)),
+    case!("g10n3p04", Gamma(0x837A5872, 1.0)),
+    case!("g25n0g16", Gamma(0x19736001, 2.5)),
+    case!("g25n2c08", Gamma(0x67B0F33C, 2.5)),
+    case!("g25n3p04", Gamma(0x837A5872, 2.5)),
+    case!("xs1n0g01", Error),
+    case!("xs2n0g01", Error),
+    case!("xs4n0g01", Error),
+    case!("xs7n0g01", Error),
+    case!("xcrn0g04", Error),
+    case!("xlfn0g04", Error),
+    case!("xhdn0g08", BadCrc),
+    case!("xcsn0g01", BadCrc),
+    case!("xc1n0g08", Error),
+    case!("xc9n2c08", Error),
+    case!("xd0n2c08", Error),
+    case!("xd3n2c08", Error),
+    case!("xd9n2c08", Error),
+    case!("xdtn0g01", Error),
+];
+
+/// A feature the summary groups images by, and an image's value of it.
+type Feature = (&'static str, fn(&Case) -> &'static str);
+
+const FEATURES: [Feature; 4] = [
+    ("Series", |case| match case.field(0) {
+        "b" => "basic",
+        "f" => "filter",
+        "t" => "transparency",
+        "g" => "gamma",
+        _ => "corrupt",
+    }),
+    ("Colour type", |case| case.field(4)),
+    ("Bit depth", |case| &case.name[6..8]),
+    ("Interlace", |case| if case.field(3) == "i" { "Adam7" } else { "none" }),
+];
+
+/// APNG dispose ops, blend ops and their names.
+const DISPOSE_OPS: [(u8, &str); 3] = [(0, "none"), (1, "background"), (2, "previous")];
+const BLEND_OPS: [(u8, &str); 2] = [(0, "source"), (1, "over")];
+
+const RED: Pixel = Pixel { r: 0xFF, g: 0, b: 0, a: 0xFF };
+const HALF_GREEN: Pixel = Pixel { r: 0, g: 0xFF, b: 0, a: 0x80 };
+/// `HALF_GREEN` blended over `RED`.
+const GREEN_OVER_RED: Pixel = Pixel { r: 0x7F, g: 0x80, b: 0, a: 0xFF };
+const BLUE: Pixel = Pixel { r: 0, g: 0, b: 0xFF, a: 0xFF };
+
+/// Decodes every image of the corpus, compares it with its reference and reports which pass,
+/// grouped by series, colour type, bit depth and interlacing. Then checks APNG compositing with
+/// every dispose and blend op. Returns whether everything passed.
+pub fn run() -> bool {
+    let results = CASES
+        .iter()
+        .map(|case| {
+            let result = check(case);
+
+            match &result {
+                Ok(()) => println!("{} {}", "PASS".green(), case.name),
+                Err(e) => println!("{} {} ({})", "FAIL".red(), case.name, e),
+            }
+
+            result.is_ok()
+        })
+        .collect::<Vec<_>>();
+
+    println!();
+
+    for (feature, value) in FEATURES {
+        let mut values: Vec<&str> = vec![];
+
+        for v in CASES.iter().map(value) {
+            if !values.contains(&v) {
+                values.push(v);
+            }
+        }
+
+        let summary = values
+            .iter()
+            .map(|v| {
+                let (passed, total) = CASES
+                    .iter()
+                    .zip(&results)
+                    .filter(|(case, _)| value(case) == *v)
+                    .fold((0, 0), |(passed, total), (_, &ok)| (passed + ok as usize, total + 1));
+
+                let text = format!("{} {}/{}", v, passed, total);
+                if passed == total { text.green() } else { text.red() }.to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!("{}: {}", feature, summary);
+    }
+
+    let passed = results.iter().filter(|&&ok| ok).count();
+    println!("{}/{} images decoded correctly", passed, results.len());
+    println!();
+
+    let mut animations_passed = 0;
+
+    for (dispose_op, dispose) in DISPOSE_OPS {
+        for (blend_op, blend) in BLEND_OPS {
+            let name = format!("apng-dispose-{}-blend-{}", dispose, blend);
+
+            match check_animation(dispose_op, blend_op) {
+                Ok(()) => {
+                    println!("{} {}", "PASS".green(), name);
+                    animations_passed += 1;
+                }
+                Err(e) => println!("{} {} ({})", "FAIL".red(), name, e),
+            }
+        }
+    }
+
+    let animations = DISPOSE_OPS.len() * BLEND_OPS.len();
+    println!("{}/{} animations composited correctly", animations_passed, animations);
+
+    passed == results.len() && animations_passed == animations
+}
+
+/// Checks a three-frame 4x4 APNG: an opaque red default image; a half-transparent green 2x2
+/// region at (1, 1) with the given blend op, disposed with the given dispose op; and an opaque
+/// blue pixel at (0, 0), which shows what the dispose op left behind.
+fn check_animation(dispose_op: u8, blend_op: u8) -> Result<(), String> {
+    let bytes = encode_animation(dispose_op, blend_op);
+
+    let mut reader = PngReader::new(bytes);
+    reader.set_verbose(false);
+    reader.read_chunks()?;
+    let frames = apng::composite(&reader)?;
+
+    let region = if blend_op == 1 { GREEN_OVER_RED } else { HALF_GREEN };
+    let disposed = match dispose_op {
+        0 => region,
+        1 => Pixel::default(),
+        _ => RED,
+    };
+
+    // the pixel inside the region, one outside it, and the blue one
+    let expected = [
+        [(1, 1, RED), (3, 3, RED), (0, 0, RED)],
+        [(1, 1, region), (3, 3, RED), (0, 0, RED)],
+        [(1, 1, disposed), (3, 3, RED), (0, 0, BLUE)],
+    ];
+
+    if frames.len() != expected.len() {
+        return Err(format!("{} frames, expected {}", frames.len(), expected.len()));
+    }
+
+    for (i, (frame, expected)) in frames.iter().zip(expected).enumerate() {
+        for (x, y, expected) in expected {
+            let pixel = frame.pixel_data[y][x];
+
+            if (pixel.r, pixel.g, pixel.b, pixel.a) != (expected.r, expected.g, expected.b, expected.a) {
+                return Err(format!(
+                    "frame {} pixel ({}, {}) is {:02x}{:02x}{:02x}{:02x}, expected {:02x}{:02x}{:02x}{:02x}",
+                    i, x, y, pixel.r, pixel.g, pixel.b, pixel.a, expected.r, expected.g, expected.b, expected.a,
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_animation(dispose_op: u8, blend_op: u8) -> Vec<u8> {
+    let zlib = |width: usize, height: usize, pixel: Pixel| {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+        for _ in 0..height {
+            encoder.write_all(&[0]).unwrap();
+            encoder.write_all(&[pixel.r, pixel.g, pixel.b, pixel.a].repeat(width)).unwrap();
+        }
+
+        encoder.finish().unwrap()
+    };
+
+    let fctl = |sequence_number: u32, size: u32, offset: u32, dispose_op: u8, blend_op: u8| {
+        let mut data = vec![];
+
+        for field in [sequence_number, size, size, offset, offset] {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+
+        data.extend_from_slice(&[0, 1, 0, 10, dispose_op, blend_op]);
+        data
+    };
+
+    let mut bytes = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    PngWriter::write_chunk(&mut bytes, b"IHDR", &[0, 0, 0, 4, 0, 0, 0, 4, 8, 6, 0, 0, 0]);
+    PngWriter::write_chunk(&mut bytes, b"acTL", &[0, 0, 0, 3, 0, 0, 0, 0]);
+    PngWriter::write_chunk(&mut bytes, b"fcTL", &fctl(0, 4, 0, 0, 0));
+    PngWriter::write_chunk(&mut bytes, b"IDAT", &zlib(4, 4, RED));
+    PngWriter::write_chunk(&mut bytes, b"fcTL", &fctl(1, 2, 1, dispose_op, blend_op));
+    PngWriter::write_chunk(&mut bytes, b"fdAT", &[&2u32.to_be_bytes()[..], &zlib(2, 2, HALF_GREEN)].concat());
+    PngWriter::write_chunk(&mut bytes, b"fcTL", &fctl(3, 1, 0, 0, 0));
+    PngWriter::write_chunk(&mut bytes, b"fdAT", &[&4u32.to_be_bytes()[..], &zlib(1, 1, BLUE)].concat());
+    PngWriter::write_chunk(&mut bytes, b"IEND", &[]);
+
+    bytes
+}
+
+fn check(case: &Case) -> Result<(), String> {
+    let mut reader = PngReader::new(case.bytes.to_vec());
+    reader.set_verbose(false);
+    let decoded = reader.read_chunks().and_then(|()| reader.decode_image_data());
+
+    let (crc, gamma) = match (case.expected, decoded) {
+        (Error, Err(_)) => return Ok(()),
+        (Error, Ok(())) => return Err("decoded, expected an error".to_string()),
+        (BadCrc, _) => {
+            return match reader.chunks().iter().find(|chunk| chunk.crc != chunk.computed_crc) {
+                Some(_) => Ok(()),
+                None => Err("no chunk with a bad CRC found".to_string()),
+            };
+        }
+        (_, Err(e)) => return Err(e),
+        (Pixels(crc), Ok(())) => (crc, None),
+        (Gamma(crc, gamma), Ok(())) => (crc, Some(gamma)),
+    };
+
+    if reader.info().gamma != gamma {
+        return Err(format!("gamma is {:?}, expected {:?}", reader.info().gamma, gamma));
+    }
+
+    let decoded = &reader.pixel_data;
+
+    if decoded.len() != SIZE || decoded.iter().any(|row| row.len() != SIZE) {
+        return Err("wrong size".to_string());
+    }
+
+    let mut sum = Crc::new();
+
+    for pixel in decoded.iter().flatten() {
+        sum.update(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+    }
+
+    match sum.sum() == crc {
+        true => Ok(()),
+        false => Err(format!("pixels have CRC {:08x}, expected {:08x}", sum.sum(), crc)),
+    }
+}
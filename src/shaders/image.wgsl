@@ -0,0 +1,194 @@
+// Draws one pane of the viewer: samples the image at the pane's zoom and pan, composites it
+// over the background and applies the tone adjustments. Mirrors Viewer::draw_image.
+
+// Replaced when the shader is loaded.
+const OUTPUT_IS_SRGB: bool = true;
+const OVERLAY_IS_SRGB: bool = true;
+
+struct Params {
+    // pane x offset in the frame, zoom, origin x, origin y
+    view: vec4<f32>,
+    background: vec4<f32>,
+    divider: vec4<f32>,
+    // exposure multiplier, 1 / gamma, difference amplification, unused
+    tone: vec4<f32>,
+    // source (0 image, 1 compare, 2 difference), filter (0 nearest, 1 linear, 2 bicubic),
+    // premultiplied alpha, draw the divider
+    options: vec4<u32>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var image: texture_2d<f32>;
+@group(0) @binding(2) var compare: texture_2d<f32>;
+@group(0) @binding(3) var overlay: texture_2d<f32>;
+
+// One triangle covering the viewport.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+struct Texel {
+    colour: vec4<f32>,
+    found: bool,
+}
+
+fn load_image(p: vec2<i32>) -> Texel {
+    let size = vec2<i32>(textureDimensions(image));
+    if any(p < vec2<i32>(0)) || any(p >= size) {
+        return Texel(vec4<f32>(0.0), false);
+    }
+    return Texel(textureLoad(image, p, 0), true);
+}
+
+fn load_compare(p: vec2<i32>) -> Texel {
+    let size = vec2<i32>(textureDimensions(compare));
+    if any(p < vec2<i32>(0)) || any(p >= size) {
+        return Texel(vec4<f32>(0.0), false);
+    }
+    return Texel(textureLoad(compare, p, 0), true);
+}
+
+fn fetch(p: vec2<i32>) -> Texel {
+    switch params.options.x {
+        case 0u: {
+            return load_image(p);
+        }
+        case 1u: {
+            return load_compare(p);
+        }
+        default: {
+            let a = load_image(p);
+            let b = load_compare(p);
+            if !a.found && !b.found {
+                return Texel(vec4<f32>(0.0), false);
+            }
+
+            let ca = select(vec4<f32>(0.0), a.colour, a.found);
+            let cb = select(vec4<f32>(0.0), b.colour, b.found);
+            let alpha = abs(ca.a - cb.a);
+            let difference = min(max(abs(ca.rgb - cb.rgb), vec3<f32>(alpha)) * params.tone.z, vec3<f32>(1.0));
+            return Texel(vec4<f32>(difference, 1.0), true);
+        }
+    }
+}
+
+fn prepare(colour: vec4<f32>) -> vec4<f32> {
+    if params.options.z == 1u {
+        return vec4<f32>(colour.rgb * colour.a, colour.a);
+    }
+    return colour;
+}
+
+// A neighbouring pixel for interpolation, or `nearest` where there is none.
+fn neighbour(p: vec2<i32>, nearest: vec4<f32>) -> vec4<f32> {
+    let texel = fetch(p);
+    return select(nearest, prepare(texel.colour), texel.found);
+}
+
+fn catmull_rom(t: f32) -> vec4<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    return vec4<f32>(
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    );
+}
+
+fn sample_filtered(position: vec2<f32>) -> Texel {
+    let texel = fetch(vec2<i32>(floor(position)));
+    if !texel.found {
+        return texel;
+    }
+
+    let nearest = prepare(texel.colour);
+
+    switch params.options.y {
+        case 1u: {
+            let q = max(position - 0.5, vec2<f32>(0.0));
+            let q0 = vec2<i32>(floor(q));
+            let t = fract(q);
+
+            let top = mix(neighbour(q0, nearest), neighbour(q0 + vec2<i32>(1, 0), nearest), t.x);
+            let bottom = mix(neighbour(q0 + vec2<i32>(0, 1), nearest), neighbour(q0 + vec2<i32>(1, 1), nearest), t.x);
+            return Texel(mix(top, bottom, t.y), true);
+        }
+        case 2u: {
+            let q = position - 0.5;
+            let base = vec2<i32>(floor(q)) - vec2<i32>(1);
+            let wx = catmull_rom(q.x - floor(q.x));
+            let wy = catmull_rom(q.y - floor(q.y));
+
+            var sum = vec4<f32>(0.0);
+            for (var j = 0; j < 4; j++) {
+                for (var i = 0; i < 4; i++) {
+                    sum += neighbour(base + vec2<i32>(i, j), nearest) * wx[i] * wy[j];
+                }
+            }
+
+            sum = clamp(sum, vec4<f32>(0.0), vec4<f32>(1.0));
+            if params.options.z == 1u {
+                sum = vec4<f32>(min(sum.rgb, vec3<f32>(sum.a)), sum.a);
+            }
+            return Texel(sum, true);
+        }
+        default: {
+            return Texel(nearest, true);
+        }
+    }
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// Takes sRGB-encoded colour to what the render target expects.
+fn output(c: vec3<f32>) -> vec4<f32> {
+    if OUTPUT_IS_SRGB {
+        return vec4<f32>(srgb_to_linear(c), 1.0);
+    }
+    return vec4<f32>(c, 1.0);
+}
+
+@fragment
+fn fs_image(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let frame = floor(position.xy) - vec2<f32>(params.view.x, 0.0);
+
+    if params.options.w == 1u && frame.x == 0.0 {
+        return output(params.divider.rgb);
+    }
+
+    let texel = sample_filtered(params.view.zw + frame / params.view.y);
+    if !texel.found {
+        return output(params.background.rgb);
+    }
+
+    var c = texel.colour.rgb;
+    if params.options.z == 1u {
+        c += params.background.rgb * (1.0 - texel.colour.a);
+    }
+
+    let linear = min(srgb_to_linear(c) * params.tone.x, vec3<f32>(1.0));
+    return output(pow(linear_to_srgb(linear), vec3<f32>(params.tone.y)));
+}
+
+// Composites the CPU-drawn overlays, transparent wherever there are none.
+@fragment
+fn fs_overlay(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let c = textureLoad(overlay, vec2<i32>(floor(position.xy)), 0);
+
+    if OUTPUT_IS_SRGB == OVERLAY_IS_SRGB {
+        return c;
+    }
+    if OUTPUT_IS_SRGB {
+        return vec4<f32>(srgb_to_linear(c.rgb), c.a);
+    }
+    return vec4<f32>(linear_to_srgb(c.rgb), c.a);
+}
@@ -0,0 +1,35 @@
+use wgpu::util::DeviceExt;
+use crate::png_reader::{DecodedImage, OutputFormat};
+
+impl DecodedImage {
+    /// Uploads the image as a texture that can be sampled and copied into, for applications
+    /// drawing it with their own wgpu device. 8-bit colour is taken as sRGB-encoded, so sampling
+    /// returns linear values. `Rgba16` needs the device's `TEXTURE_FORMAT_16BIT_NORM` feature,
+    /// and `Rgb8` has no texture format to go into.
+    pub fn to_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<wgpu::Texture, String> {
+        let format = match self.format {
+            OutputFormat::Gray8 => wgpu::TextureFormat::R8Unorm,
+            OutputFormat::Rgba8 => wgpu::TextureFormat::Rgba8UnormSrgb,
+            OutputFormat::Bgra8 => wgpu::TextureFormat::Bgra8UnormSrgb,
+            OutputFormat::Rgba16 => wgpu::TextureFormat::Rgba16Unorm,
+            OutputFormat::Rgb8 => return Err("wgpu has no 8-bit RGB texture format; decode as Rgba8 instead".to_string()),
+        };
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("png_texture"),
+                size: wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &self.data,
+        );
+
+        Ok(texture)
+    }
+}
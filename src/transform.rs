@@ -0,0 +1,90 @@
+/// A mirror image, for `--flip`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Flip {
+    /// Left to right.
+    Horizontal,
+    /// Top to bottom.
+    Vertical,
+}
+
+impl Flip {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "h" => Some(Self::Horizontal),
+            "v" => Some(Self::Vertical),
+            _ => None,
+        }
+    }
+}
+
+pub fn flip_horizontal<T: Copy>(pixel_data: &[Vec<T>]) -> Vec<Vec<T>> {
+    pixel_data.iter().map(|row| row.iter().rev().copied().collect()).collect()
+}
+
+pub fn flip_vertical<T: Copy>(pixel_data: &[Vec<T>]) -> Vec<Vec<T>> {
+    pixel_data.iter().rev().cloned().collect()
+}
+
+/// Rotates a quarter turn clockwise.
+pub fn rotate_90<T: Copy>(pixel_data: &[Vec<T>]) -> Vec<Vec<T>> {
+    let height = pixel_data.len();
+    let width = pixel_data.first().map_or(0, |row| row.len());
+
+    (0..width)
+        .map(|x| (0..height).map(|y| pixel_data[height - 1 - y][x]).collect())
+        .collect()
+}
+
+pub fn rotate_180<T: Copy>(pixel_data: &[Vec<T>]) -> Vec<Vec<T>> {
+    pixel_data.iter().rev().map(|row| row.iter().rev().copied().collect()).collect()
+}
+
+/// Rotates a quarter turn anticlockwise.
+pub fn rotate_270<T: Copy>(pixel_data: &[Vec<T>]) -> Vec<Vec<T>> {
+    let height = pixel_data.len();
+    let width = pixel_data.first().map_or(0, |row| row.len());
+
+    (0..width)
+        .map(|x| (0..height).map(|y| pixel_data[y][width - 1 - x]).collect())
+        .collect()
+}
+
+/// Rotates clockwise by 90, 180 or 270 degrees; anything else leaves the image untouched.
+pub fn rotate<T: Copy>(pixel_data: &[Vec<T>], degrees: u16) -> Vec<Vec<T>> {
+    match degrees {
+        90 => rotate_90(pixel_data),
+        180 => rotate_180(pixel_data),
+        270 => rotate_270(pixel_data),
+        _ => pixel_data.to_vec(),
+    }
+}
+
+pub fn flip<T: Copy>(pixel_data: &[Vec<T>], flip: Flip) -> Vec<Vec<T>> {
+    match flip {
+        Flip::Horizontal => flip_horizontal(pixel_data),
+        Flip::Vertical => flip_vertical(pixel_data),
+    }
+}
+
+/// Size of an image after `orient`.
+pub fn oriented_size(width: u32, height: u32, orientation: u16) -> (u32, u32) {
+    match orientation {
+        5..=8 => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// Turns stored pixels upright according to an EXIF Orientation value (1-8).
+/// Unknown values leave the image untouched.
+pub fn orient<T: Copy>(pixel_data: &[Vec<T>], orientation: u16) -> Vec<Vec<T>> {
+    match orientation {
+        2 => flip_horizontal(pixel_data),
+        3 => rotate_180(pixel_data),
+        4 => flip_vertical(pixel_data),
+        5 => flip_horizontal(&rotate_90(pixel_data)),
+        6 => rotate_90(pixel_data),
+        7 => flip_vertical(&rotate_90(pixel_data)),
+        8 => rotate_270(pixel_data),
+        _ => pixel_data.to_vec(),
+    }
+}
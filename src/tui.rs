@@ -0,0 +1,187 @@
+use std::io::{self, stdout, Stdout};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use crate::png_reader::PngReader;
+use crate::Pixel;
+
+const HEX_BYTES_PER_LINE: usize = 16;
+
+struct State<'a> {
+    reader: &'a PngReader,
+    pixel_data: &'a [Vec<Pixel>],
+    chunks: ListState,
+    hex_scroll: u16,
+}
+
+/// Shows the chunk list, a hex view and the parsed fields of the selected chunk, and a
+/// half-block preview of the image in the terminal until the user quits.
+pub fn run(reader: &PngReader, pixel_data: &[Vec<Pixel>]) -> io::Result<()> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+    let mut state = State {
+        reader,
+        pixel_data,
+        chunks: ListState::default().with_selected(Some(0)),
+        hex_scroll: 0,
+    };
+
+    let result = event_loop(&mut terminal, &mut state);
+
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+
+    result
+}
+
+fn event_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &mut State) -> io::Result<()> {
+    let chunk_count = state.reader.chunks().len();
+
+    loop {
+        terminal.draw(|frame| draw(frame, state))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let selected = state.chunks.selected().unwrap_or(0);
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Up | KeyCode::Char('k') => {
+                state.chunks.select(Some(selected.saturating_sub(1)));
+                state.hex_scroll = 0;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.chunks.select(Some((selected + 1).min(chunk_count.saturating_sub(1))));
+                state.hex_scroll = 0;
+            }
+            KeyCode::PageUp => state.hex_scroll = state.hex_scroll.saturating_sub(16),
+            KeyCode::PageDown => state.hex_scroll = state.hex_scroll.saturating_add(16),
+            _ => ()
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &mut State) {
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(30), Constraint::Min(40), Constraint::Percentage(35)])
+        .split(frame.size());
+
+    let middle = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(columns[1]);
+
+    let chunks = state.reader.chunks();
+
+    let items = chunks
+        .iter()
+        .map(|chunk| ListItem::new(format!("{} @{:<8} {:>8}B", chunk.chunk_type, chunk.offset, chunk.data_range.len())))
+        .collect::<Vec<_>>();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Chunks"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(list, columns[0], &mut state.chunks);
+
+    if let Some(chunk) = state.chunks.selected().and_then(|i| chunks.get(i)) {
+        let fields = match chunk.fields.is_empty() {
+            true => vec![Line::from("(no parsed fields)")],
+            false => chunk.fields
+                .iter()
+                .flat_map(|(title, content)| {
+                    std::iter::once(Line::styled(title.clone(), Style::default().fg(Color::Green)))
+                        .chain(content.lines().map(|line| Line::from(line.to_string())))
+                })
+                .collect(),
+        };
+
+        let fields = Paragraph::new(fields)
+            .block(Block::default().borders(Borders::ALL).title(format!("{} fields (CRC {:08x})", chunk.chunk_type, chunk.crc)))
+            .wrap(Wrap { trim: false });
+
+        frame.render_widget(fields, middle[0]);
+
+        let hex = Paragraph::new(hex_lines(state.reader.chunk_data(chunk)))
+            .block(Block::default().borders(Borders::ALL).title(format!("{} data ({} bytes, PgUp/PgDn)", chunk.chunk_type, chunk.data_range.len())))
+            .scroll((state.hex_scroll, 0));
+
+        frame.render_widget(hex, middle[1]);
+    }
+
+    let preview = Block::default().borders(Borders::ALL).title("Preview");
+    let preview_area = preview.inner(columns[2]);
+
+    frame.render_widget(preview, columns[2]);
+    frame.render_widget(HalfBlockImage(state.pixel_data), preview_area);
+}
+
+fn hex_lines(data: &[u8]) -> Vec<Line<'static>> {
+    data.chunks(HEX_BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let hex = bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+            let ascii = bytes.iter().map(|&b| if b.is_ascii_graphic() { b as char } else { '.' }).collect::<String>();
+
+            Line::from(vec![
+                Span::styled(format!("{:08x}  ", i * HEX_BYTES_PER_LINE), Style::default().fg(Color::DarkGray)),
+                Span::raw(format!("{:<48} ", hex)),
+                Span::styled(ascii, Style::default().fg(Color::Cyan)),
+            ])
+        })
+        .collect()
+}
+
+/// Draws the image scaled to fit, two pixels per cell using the upper half block character.
+struct HalfBlockImage<'a>(&'a [Vec<Pixel>]);
+
+impl Widget for HalfBlockImage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let height = self.0.len();
+        let width = self.0.first().map_or(0, |row| row.len());
+
+        if width == 0 || height == 0 || area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let scale = (width as f64 / area.width as f64).max(height as f64 / (area.height as f64 * 2.0));
+        let columns = ((width as f64 / scale) as u16).min(area.width);
+        let rows = ((height as f64 / scale / 2.0).ceil() as u16).min(area.height);
+
+        let colour = |x: u16, y: u16| -> Color {
+            let ix = ((x as f64 * scale) as usize).min(width - 1);
+            let iy = (y as f64 * scale) as usize;
+
+            match self.0.get(iy) {
+                Some(row) => {
+                    // composite over black, which is what an empty terminal cell usually looks like
+                    let pixel = row[ix];
+                    let blend = |c: u8| (c as u16 * pixel.a as u16 / 0xFF) as u8;
+                    Color::Rgb(blend(pixel.r), blend(pixel.g), blend(pixel.b))
+                }
+                None => Color::Reset,
+            }
+        };
+
+        for y in 0..rows {
+            for x in 0..columns {
+                buf.get_mut(area.x + x, area.y + y)
+                    .set_symbol("▀")
+                    .set_fg(colour(x, y * 2))
+                    .set_bg(colour(x, y * 2 + 1));
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::apng;
+use crate::gif_file::Animation;
+
+/// Highest frame rate videos are written at. Frames are repeated to make up their delays, so
+/// delays are rounded to whole frames at this rate or the rate of the shortest delay if lower.
+const MAX_FPS: f64 = 60.0;
+
+/// Encodes one play of the animation as a video with ffmpeg, streaming frames composited over
+/// `background` as raw RGB on its stdin. The container and codec follow from the extension of
+/// `output`, e.g. `.mp4` or `.webm`, as ffmpeg picks them.
+pub fn write(animation: &Animation, background: [u8; 4], output: &Path) -> Result<(), String> {
+    let delays = animation.frames.iter().map(|frame| apng::shown_delay(frame.delay)).collect::<Vec<_>>();
+    let shortest = delays.iter().min().map_or(1.0, |delay| delay.as_secs_f64());
+    let fps = (1.0 / shortest).min(MAX_FPS);
+
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pixel_format", "rgb24"])
+        .args(["-video_size", &format!("{}x{}", animation.width, animation.height)])
+        .args(["-framerate", &fps.to_string()])
+        .args(["-i", "-"])
+        // yuv420p, which players expect, needs even dimensions
+        .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Can't run ffmpeg, is it installed? ({})", e))?;
+
+    let mut stdin = ffmpeg.stdin.take().expect("ffmpeg's stdin is piped");
+    let mut elapsed = 0.0;
+    let mut written = 0;
+
+    for (frame, delay) in animation.frames.iter().zip(&delays) {
+        let rgb = frame.pixel_data
+            .iter()
+            .flatten()
+            .flat_map(|p| {
+                let alpha = p.a as u32;
+                let over = |c: u8, b: u8| ((c as u32 * alpha + b as u32 * (255 - alpha)) / 255) as u8;
+
+                [over(p.r, background[0]), over(p.g, background[1]), over(p.b, background[2])]
+            })
+            .collect::<Vec<u8>>();
+
+        // repeat the frame until the video has caught up with the end of its delay
+        elapsed += delay.as_secs_f64();
+        let end = ((elapsed * fps).round() as usize).max(written + 1);
+
+        // a failed write means ffmpeg exited early, and its status says why
+        if (written..end).any(|_| stdin.write_all(&rgb).is_err()) {
+            break;
+        }
+
+        written = end;
+    }
+
+    drop(stdin);
+
+    let status = ffmpeg.wait().map_err(|e| e.to_string())?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("ffmpeg failed ({})", status)),
+    }
+}
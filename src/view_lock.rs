@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+
+/// A window's zoom and pan as last published, in `view-lock.toml` in the config directory.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq)]
+struct SharedView {
+    /// Process of the window that published it, so a window doesn't take its own view back.
+    process: u32,
+    zoom: f64,
+    /// Image coordinate of the view's top-left corner.
+    x: f64,
+    y: f64,
+}
+
+/// Mirrors pan and zoom between windows (each its own process) with their views locked: every
+/// locked window publishes its view when it changes and takes up views the others publish.
+pub struct ViewLock {
+    /// The view last published or taken up here, as (zoom, origin).
+    view: Option<(f64, (f64, f64))>,
+    /// The shared view as last read, so each is only taken up once.
+    seen: Option<SharedView>,
+}
+
+impl ViewLock {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("view-lock.toml"))
+    }
+
+    pub fn new() -> Self {
+        Self {
+            view: None,
+            seen: Self::read(),
+        }
+    }
+
+    /// Publishes `view` if it changed since the last call, and otherwise returns the view
+    /// another window published since then, if any.
+    pub fn sync(&mut self, view: (f64, (f64, f64))) -> Option<(f64, (f64, f64))> {
+        if self.publish(view) {
+            return None;
+        }
+
+        let shared = Self::read().filter(|&shared| Some(shared) != self.seen && shared.process != process::id())?;
+        let view = (shared.zoom, (shared.x, shared.y));
+
+        self.seen = Some(shared);
+        self.view = Some(view);
+
+        Some(view)
+    }
+
+    /// Publishes `view` if it changed since it was last published or taken up, returning whether
+    /// it did. Closing the window calls this so a change made since the last poll isn't lost.
+    pub fn publish(&mut self, view: (f64, (f64, f64))) -> bool {
+        if self.view == Some(view) {
+            return false;
+        }
+
+        self.view = Some(view);
+        self.write(view);
+
+        true
+    }
+
+    fn read() -> Option<SharedView> {
+        let text = fs::read_to_string(Self::path()?).ok()?;
+
+        // a view being written may be read half finished; the next poll gets all of it
+        toml::from_str(&text).ok()
+    }
+
+    fn write(&mut self, (zoom, (x, y)): (f64, (f64, f64))) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+
+        let shared = SharedView { process: process::id(), zoom, x, y };
+        let result = fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, toml::to_string(&shared).unwrap()));
+
+        match result {
+            Ok(_) => self.seen = Some(shared),
+            Err(e) => eprintln!("{} {}: {}", "Failed to share the view".red(), path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,1760 @@
+use qcms::Profile;
+use png_viewer::blit;
+use crate::colour::ColourTransform;
+use crate::font;
+use crate::Pixel;
+
+pub const DIVIDER: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+
+/// Tints for the None, Sub, Up, Average and Paeth scanline filters.
+const FILTER_COLOURS: [[u8; 3]; 5] = [
+    [0x80, 0x80, 0x80],
+    [0xE0, 0x40, 0x40],
+    [0x40, 0xC0, 0x40],
+    [0x40, 0x60, 0xE0],
+    [0xE0, 0xC0, 0x20],
+];
+
+const MIN_ZOOM: f64 = 1.0 / 16.0;
+const MAX_ZOOM: f64 = 64.0;
+
+/// Exposure range in stops and gamma range.
+const MAX_EXPOSURE: f64 = 8.0;
+const MIN_GAMMA: f64 = 0.1;
+const MAX_GAMMA: f64 = 10.0;
+
+/// Longest side of the minimap, in unscaled frame pixels.
+const MINIMAP_SIZE: usize = 160;
+
+/// Width of the stripes marking clipped pixels, in unscaled frame pixels.
+const ZEBRA_WIDTH: usize = 4;
+
+/// Linear light is looked up in this many steps when converting back to sRGB, enough that
+/// neighbouring 8-bit values stay distinct.
+const LINEAR_STEPS: usize = 4096;
+
+/// Radius of the loupe, in unscaled frame pixels, and how much more it zooms than the view.
+const LOUPE_RADIUS: usize = 80;
+const LOUPE_ZOOM: f64 = 8.0;
+
+/// How the two images are presented in compare mode.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum CompareMode {
+    /// Side by side, each in its own pane.
+    Split,
+    /// One at a time in a single pane, alternating on a timer.
+    Blink { showing_compare: bool },
+    /// The absolute per-channel difference, multiplied by `amplify`.
+    Difference { amplify: u8 },
+}
+
+/// How image pixels are sampled when the zoom isn't 1:1.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ScalingFilter {
+    Nearest,
+    /// Bilinear interpolation between the four nearest pixels.
+    Linear,
+    /// Catmull-Rom interpolation over the sixteen nearest pixels.
+    Bicubic,
+}
+
+impl ScalingFilter {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Self::Nearest),
+            "linear" => Some(Self::Linear),
+            "bicubic" => Some(Self::Bicubic),
+            _ => None,
+        }
+    }
+}
+
+/// A colour vision deficiency to simulate, by Machado, Oliveira and Fernandes' (2009) matrices
+/// for full severity, applied in linear light.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Simulation {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Simulation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "protanopia" => Some(Self::Protanopia),
+            "deuteranopia" => Some(Self::Deuteranopia),
+            "tritanopia" => Some(Self::Tritanopia),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Protanopia => "protanopia",
+            Self::Deuteranopia => "deuteranopia",
+            Self::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// The simulation after `current`, cycling through each in turn and then none.
+    pub fn next(current: Option<Self>) -> Option<Self> {
+        match current {
+            None => Some(Self::Protanopia),
+            Some(Self::Protanopia) => Some(Self::Deuteranopia),
+            Some(Self::Deuteranopia) => Some(Self::Tritanopia),
+            Some(Self::Tritanopia) => None,
+        }
+    }
+
+    fn matrix(self) -> [[f64; 3]; 3] {
+        match self {
+            Self::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Self::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Self::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+/// An entry of the right-click menu.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MenuItem {
+    /// Copies the selection, or the whole image without one.
+    Copy,
+    SaveAs,
+    OpenFolder,
+    /// Shows or hides the metadata.
+    Properties,
+    ZoomToFit,
+    ActualSize,
+    Zoom200,
+    Zoom300,
+}
+
+impl MenuItem {
+    const ALL: [Self; 8] = [Self::Copy, Self::SaveAs, Self::OpenFolder, Self::Properties, Self::ZoomToFit, Self::ActualSize, Self::Zoom200, Self::Zoom300];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Copy => "Copy",
+            Self::SaveAs => "Save as...",
+            Self::OpenFolder => "Open containing folder",
+            Self::Properties => "Properties",
+            Self::ZoomToFit => "Zoom to fit",
+            Self::ActualSize => "Zoom 100%",
+            Self::Zoom200 => "Zoom 200%",
+            Self::Zoom300 => "Zoom 300%",
+        }
+    }
+}
+
+/// A view of the alpha channel alone, in place of the image.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum AlphaView {
+    /// Alpha as grey, transparent black and opaque white.
+    Greyscale,
+    /// Transparent pixels black and opaque ones white, with partial alpha in colour from blue
+    /// (nearly transparent) to red (nearly opaque), to show up soft edges and stray pixels.
+    Partial,
+}
+
+impl AlphaView {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "greyscale" => Some(Self::Greyscale),
+            "partial" => Some(Self::Partial),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Greyscale => "greyscale",
+            Self::Partial => "partial",
+        }
+    }
+
+    /// The view after `current`, cycling through each in turn and then the image.
+    pub fn next(current: Option<Self>) -> Option<Self> {
+        match current {
+            None => Some(Self::Greyscale),
+            Some(Self::Greyscale) => Some(Self::Partial),
+            Some(Self::Partial) => None,
+        }
+    }
+
+    fn show(self, alpha: u8) -> Pixel {
+        match (self, alpha) {
+            (Self::Partial, 1..=0xFE) => Pixel { r: alpha, g: 0, b: 0xFF - alpha, a: 0xFF },
+            _ => Pixel { r: alpha, g: alpha, b: alpha, a: 0xFF },
+        }
+    }
+}
+
+/// What a pane shows.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PaneSource {
+    Image,
+    Compare,
+    /// The amplified difference between the two.
+    Difference { amplify: u8 },
+}
+
+/// Everything needed to draw the image part of the view elsewhere (on the GPU), matching `draw`.
+pub struct RenderParams {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub pane_width: u32,
+    /// What each pane shows, left to right.
+    pub panes: Vec<PaneSource>,
+    pub zoom: f64,
+    pub origin: (f64, f64),
+    pub background: [u8; 4],
+    pub filter: ScalingFilter,
+    pub premultiplied_alpha: bool,
+    pub exposure: f64,
+    pub gamma: f64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Builds the rectangle spanned by two corner pixels, both inclusive.
+    pub fn from_corners(a: (u32, u32), b: (u32, u32)) -> Self {
+        let x = a.0.min(b.0);
+        let y = a.1.min(b.1);
+
+        Self {
+            x,
+            y,
+            width: a.0.max(b.0) - x + 1,
+            height: a.1.max(b.1) - y + 1,
+        }
+    }
+}
+
+pub struct Viewer {
+    pub width: u32,
+    pub height: u32,
+    pixel_data: Vec<Vec<Pixel>>,
+    /// Bumped whenever either image's pixels change, so copies of them elsewhere can be refreshed.
+    generation: u64,
+    /// Second image shown in the right-hand pane, sharing the zoom and pan of the first.
+    compare: Option<Vec<Vec<Pixel>>>,
+    compare_mode: CompareMode,
+
+    frame_width: u32,
+    frame_height: u32,
+    /// Frame pixels per image pixel.
+    zoom: f64,
+    /// Image coordinate shown at the frame's top-left corner.
+    origin: (f64, f64),
+    /// Scale applied to overlay text, following the window's scale factor.
+    text_scale: usize,
+    background: [u8; 4],
+    filter: ScalingFilter,
+    /// PNG alpha is straight (not premultiplied). When true, pixels are premultiplied before
+    /// interpolation and composited over the background; when false, alpha is ignored and the
+    /// straight colours are shown, which shows what a transparent pixel's colour channels hold.
+    premultiplied_alpha: bool,
+    /// Exposure adjustment in stops.
+    exposure: f64,
+    /// Gamma adjustment, 1.0 leaving the image alone.
+    gamma: f64,
+    /// `exposure` and `gamma` baked into a lookup table.
+    tone_curve: [u8; 256],
+    simulation: Option<Simulation>,
+    /// Show the image's colours inverted, its alpha unchanged.
+    invert: bool,
+    /// Show red as blue and blue as red, as a BGR image read as RGB would be.
+    swap_red_blue: bool,
+    /// Treat samples as linear light rather than sRGB-encoded, encoding them for display.
+    linear_samples: bool,
+    alpha_view: Option<AlphaView>,
+    /// Stripe pure white pixels red and pure black ones blue, the stripes swapping places each
+    /// time the phase flips so they flash.
+    show_clipping: bool,
+    clipping_phase: bool,
+    /// sRGB-encoded values to linear light, and linear light in `LINEAR_STEPS` steps back.
+    to_linear: [f64; 256],
+    from_linear: Vec<u8>,
+    /// Colour spaces of the display and of each image, sRGB when None.
+    output_profile: Option<Box<Profile>>,
+    image_profile: Option<Box<Profile>>,
+    compare_profile: Option<Box<Profile>>,
+    /// Conversions into the display's colour space from sRGB (for the background and
+    /// difference view) and from each image's colour space, rebuilt when a profile changes.
+    srgb_transform: Option<ColourTransform>,
+    image_transform: Option<ColourTransform>,
+    compare_transform: Option<ColourTransform>,
+
+    frame_cursor: (f64, f64),
+    cursor: (u32, u32),
+    pan_anchor: Option<(f64, f64)>,
+    selection_anchor: Option<(u32, u32)>,
+    selection: Option<Rect>,
+    /// Statistics of an alt-dragged selection, shown beside it until the selection changes.
+    sample: Option<Vec<String>>,
+    sampling: bool,
+    /// The two image pixels measured between, and whether the second still follows the cursor.
+    measurement: Option<((u32, u32), (u32, u32))>,
+    measuring: bool,
+
+    metadata: Vec<String>,
+    show_metadata: bool,
+
+    /// Filter type of the scanline each pixel came from, once decoding has finished.
+    filter_map: Option<Vec<Vec<u8>>>,
+    filter_statistics: Vec<String>,
+    show_filters: bool,
+
+    /// A blown-up view of the pixels around the cursor, shown while its key is held.
+    show_loupe: bool,
+
+    /// Rulers along the top and left edges, a crosshair and a readout of the pixel under it.
+    show_rulers: bool,
+
+    /// Which of how many images this is, shown in the bottom-left corner when there are several.
+    position: (usize, usize),
+
+    /// Where the right-click menu was opened, in frame pixels, while it's open.
+    menu: Option<(f64, f64)>,
+}
+
+impl Viewer {
+    /// Creates a viewer with no image; call `set_image` before drawing.
+    pub fn new(frame_width: u32, frame_height: u32, scale_factor: f64) -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            pixel_data: vec![],
+            generation: 0,
+            compare: None,
+            compare_mode: CompareMode::Split,
+            frame_width,
+            frame_height,
+            zoom: scale_factor,
+            origin: (0.0, 0.0),
+            text_scale: scale_factor.round().max(1.0) as usize,
+            background: [0x20, 0x20, 0x20, 0xFF],
+            filter: ScalingFilter::Nearest,
+            premultiplied_alpha: true,
+            exposure: 0.0,
+            gamma: 1.0,
+            tone_curve: std::array::from_fn(|i| i as u8),
+            simulation: None,
+            invert: false,
+            swap_red_blue: false,
+            linear_samples: false,
+            alpha_view: None,
+            show_clipping: false,
+            clipping_phase: false,
+            to_linear: std::array::from_fn(|i| srgb_to_linear(i as f64 / 255.0)),
+            from_linear: (0..=LINEAR_STEPS).map(|i| (linear_to_srgb(i as f64 / LINEAR_STEPS as f64) * 255.0).round() as u8).collect(),
+            output_profile: None,
+            image_profile: None,
+            compare_profile: None,
+            srgb_transform: None,
+            image_transform: None,
+            compare_transform: None,
+            frame_cursor: (0.0, 0.0),
+            cursor: (0, 0),
+            pan_anchor: None,
+            selection_anchor: None,
+            selection: None,
+            sample: None,
+            sampling: false,
+            measurement: None,
+            measuring: false,
+            metadata: vec![],
+            show_metadata: false,
+            filter_map: None,
+            filter_statistics: vec![],
+            show_filters: false,
+            show_loupe: false,
+            show_rulers: false,
+            position: (0, 1),
+            menu: None,
+        }
+    }
+
+    /// Shows a different image, keeping the zoom but dropping the selection and everything
+    /// known about the previous image.
+    pub fn set_image(&mut self, width: u32, height: u32, pixel_data: Vec<Vec<Pixel>>) {
+        self.width = width;
+        self.height = height;
+        self.pixel_data = pixel_data;
+        self.generation += 1;
+        self.origin = (0.0, 0.0);
+        self.clear_selection();
+        self.metadata = vec![];
+        self.filter_map = None;
+        self.filter_statistics = vec![];
+        self.clamp_origin();
+    }
+
+    /// Replaces the image with a refined decode of the same size.
+    pub fn set_pixel_data(&mut self, pixel_data: Vec<Vec<Pixel>>) {
+        self.pixel_data = pixel_data;
+        self.generation += 1;
+    }
+
+    /// Splits the window in two, showing `pixel_data` to the right of the main image.
+    pub fn set_compare(&mut self, pixel_data: Vec<Vec<Pixel>>) {
+        self.compare = Some(pixel_data);
+        self.generation += 1;
+        self.clamp_origin();
+    }
+
+    /// Switches between split view and the given mode, returning false outside compare mode.
+    pub fn toggle_compare_mode(&mut self, mode: CompareMode) -> bool {
+        if self.compare.is_none() {
+            return false;
+        }
+
+        let same_kind = std::mem::discriminant(&self.compare_mode) == std::mem::discriminant(&mode);
+        self.compare_mode = if same_kind { CompareMode::Split } else { mode };
+        self.clamp_origin();
+
+        true
+    }
+
+    pub fn is_blinking(&self) -> bool {
+        matches!(self.compare_mode, CompareMode::Blink { .. })
+    }
+
+    /// Flips to the other image while blinking.
+    pub fn blink(&mut self) {
+        if let CompareMode::Blink { showing_compare } = &mut self.compare_mode {
+            *showing_compare = !*showing_compare;
+        }
+    }
+
+    /// Doubles or halves the difference amplification, returning the new factor.
+    pub fn amplify_difference(&mut self, up: bool) -> Option<u8> {
+        match &mut self.compare_mode {
+            CompareMode::Difference { amplify } => {
+                *amplify = if up { amplify.saturating_mul(2).min(128) } else { (*amplify / 2).max(1) };
+                Some(*amplify)
+            }
+            _ => None
+        }
+    }
+
+    /// Width of the area one image is shown in: the whole frame, or half of it for a split comparison.
+    fn pane_width(&self) -> u32 {
+        match (&self.compare, self.compare_mode) {
+            (Some(_), CompareMode::Split) => self.frame_width / 2,
+            _ => self.frame_width,
+        }
+    }
+
+    pub fn set_background(&mut self, background: [u8; 4]) {
+        self.background = background;
+    }
+
+    pub fn set_filter(&mut self, filter: ScalingFilter) {
+        self.filter = filter;
+    }
+
+    /// Sets the display's colour space, which everything drawn is converted into.
+    pub fn set_output_profile(&mut self, profile: Box<Profile>) {
+        self.output_profile = Some(profile);
+        self.update_colour_transforms();
+    }
+
+    /// Sets the colour space the image is in, None for sRGB.
+    pub fn set_image_profile(&mut self, profile: Option<Box<Profile>>) {
+        self.image_profile = profile;
+        self.update_colour_transforms();
+    }
+
+    /// Sets the colour space the compared image is in, None for sRGB.
+    pub fn set_compare_profile(&mut self, profile: Option<Box<Profile>>) {
+        self.compare_profile = profile;
+        self.update_colour_transforms();
+    }
+
+    fn update_colour_transforms(&mut self) {
+        let output = self.output_profile.as_deref();
+
+        self.srgb_transform = ColourTransform::new(None, output);
+        self.image_transform = ColourTransform::new(self.image_profile.as_deref(), output);
+        self.compare_transform = ColourTransform::new(self.compare_profile.as_deref(), output);
+    }
+
+    /// The colour conversion for what's shown in the given pane.
+    fn pane_transform(&self, pane: usize) -> Option<&ColourTransform> {
+        let transform = match (&self.compare, self.compare_mode, pane) {
+            (None, _, 0) => &self.image_transform,
+            (Some(_), CompareMode::Split, 0) => &self.image_transform,
+            (Some(_), CompareMode::Split, 1) => &self.compare_transform,
+            (Some(_), CompareMode::Blink { showing_compare: false }, 0) => &self.image_transform,
+            (Some(_), CompareMode::Blink { showing_compare: true }, 0) => &self.compare_transform,
+            _ => &self.srgb_transform,
+        };
+
+        transform.as_ref()
+    }
+
+    /// Switches between compositing with premultiplied alpha and showing the straight colours,
+    /// returning whether alpha is now premultiplied.
+    pub fn toggle_premultiplied_alpha(&mut self) -> bool {
+        self.premultiplied_alpha = !self.premultiplied_alpha;
+        self.premultiplied_alpha
+    }
+
+    /// Brightens or darkens by `stops`, returning the new exposure.
+    pub fn adjust_exposure(&mut self, stops: f64) -> f64 {
+        self.exposure = (self.exposure + stops).clamp(-MAX_EXPOSURE, MAX_EXPOSURE);
+        self.update_tone_curve();
+        self.exposure
+    }
+
+    /// Multiplies the gamma adjustment, returning the new gamma.
+    pub fn adjust_gamma(&mut self, factor: f64) -> f64 {
+        self.gamma = (self.gamma * factor).clamp(MIN_GAMMA, MAX_GAMMA);
+        self.update_tone_curve();
+        self.gamma
+    }
+
+    pub fn reset_tone(&mut self) {
+        self.exposure = 0.0;
+        self.gamma = 1.0;
+        self.update_tone_curve();
+    }
+
+    /// Simulates a colour vision deficiency, or stops with None.
+    pub fn set_simulation(&mut self, simulation: Option<Simulation>) {
+        self.simulation = simulation;
+    }
+
+    pub fn simulation(&self) -> Option<Simulation> {
+        self.simulation
+    }
+
+    /// Toggles inverted colours, returning whether they're now shown.
+    pub fn toggle_invert(&mut self) -> bool {
+        self.invert = !self.invert;
+        self.invert
+    }
+
+    pub fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    /// Toggles swapping red and blue, returning whether they're now swapped.
+    pub fn toggle_swap_red_blue(&mut self) -> bool {
+        self.swap_red_blue = !self.swap_red_blue;
+        self.swap_red_blue
+    }
+
+    pub fn set_swap_red_blue(&mut self, swap_red_blue: bool) {
+        self.swap_red_blue = swap_red_blue;
+    }
+
+    /// Toggles treating samples as linear light, returning whether they now are.
+    pub fn toggle_linear_samples(&mut self) -> bool {
+        self.linear_samples = !self.linear_samples;
+        self.linear_samples
+    }
+
+    pub fn set_linear_samples(&mut self, linear_samples: bool) {
+        self.linear_samples = linear_samples;
+    }
+
+    /// Toggles marking clipped pixels, returning whether they're now marked.
+    pub fn toggle_clipping(&mut self) -> bool {
+        self.show_clipping = !self.show_clipping;
+        self.show_clipping
+    }
+
+    pub fn set_clipping(&mut self, show_clipping: bool) {
+        self.show_clipping = show_clipping;
+    }
+
+    /// Whether clipped pixels are marked, and so need `flash_clipping` calling on a timer.
+    pub fn is_showing_clipping(&self) -> bool {
+        self.show_clipping
+    }
+
+    pub fn flash_clipping(&mut self) {
+        self.clipping_phase = !self.clipping_phase;
+    }
+
+    /// Shows the alpha channel instead of the image, or the image again with None.
+    pub fn set_alpha_view(&mut self, alpha_view: Option<AlphaView>) {
+        self.alpha_view = alpha_view;
+    }
+
+    pub fn alpha_view(&self) -> Option<AlphaView> {
+        self.alpha_view
+    }
+
+    fn update_tone_curve(&mut self) {
+        self.tone_curve = std::array::from_fn(|i| (tone(i as f64 / 255.0, self.exposure, self.gamma) * 255.0).round() as u8);
+    }
+
+    /// The image and the compared image, if any.
+    pub fn images(&self) -> (&[Vec<Pixel>], Option<&[Vec<Pixel>]>) {
+        (&self.pixel_data, self.compare.as_deref())
+    }
+
+    /// A counter that changes whenever either image's pixels do.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether drawing needs features only the CPU path has: the filter tint, colour vision
+    /// simulation, inverted or swapped colours, linear samples, alpha views, clipping stripes and
+    /// colour management.
+    pub fn needs_cpu(&self) -> bool {
+        self.show_filters || self.simulation.is_some() || self.invert || self.swap_red_blue || self.linear_samples
+            || self.alpha_view.is_some() || self.show_clipping || self.srgb_transform.is_some() || self.image_transform.is_some() || self.compare_transform.is_some()
+    }
+
+    pub fn render_params(&self) -> RenderParams {
+        let pane_count = match (&self.compare, self.compare_mode) {
+            (Some(_), CompareMode::Split) => 2,
+            _ => 1,
+        };
+
+        RenderParams {
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            pane_width: self.pane_width(),
+            panes: (0..pane_count).filter_map(|pane| self.pane_source(pane)).collect(),
+            zoom: self.zoom,
+            origin: self.origin,
+            background: self.background,
+            filter: self.filter,
+            premultiplied_alpha: self.premultiplied_alpha,
+            exposure: self.exposure,
+            gamma: self.gamma,
+        }
+    }
+
+    pub fn resize(&mut self, frame_width: u32, frame_height: u32) {
+        self.frame_width = frame_width;
+        self.frame_height = frame_height;
+        self.clamp_origin();
+    }
+
+    /// Multiplies the zoom, keeping the frame's top-left corner fixed.
+    pub fn zoom_by(&mut self, factor: f64) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.clamp_origin();
+    }
+
+    /// Multiplies the zoom, keeping the image point under the cursor where it is.
+    pub fn zoom_at_cursor(&mut self, factor: f64) {
+        let x = self.frame_cursor.0 % self.pane_width().max(1) as f64;
+        let y = self.frame_cursor.1;
+        let anchor = (self.origin.0 + x / self.zoom, self.origin.1 + y / self.zoom);
+
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.origin = (anchor.0 - x / self.zoom, anchor.1 - y / self.zoom);
+        self.clamp_origin();
+    }
+
+    /// Sets the zoom outright, 1.0 showing one image pixel per frame pixel.
+    pub fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.clamp_origin();
+    }
+
+    /// The zoom and the image coordinate of the view's top-left corner.
+    pub fn view(&self) -> (f64, (f64, f64)) {
+        (self.zoom, self.origin)
+    }
+
+    /// Sets the zoom and pan together, as `view` returned them, keeping the image in view.
+    pub fn set_view(&mut self, zoom: f64, origin: (f64, f64)) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.origin = origin;
+        self.clamp_origin();
+    }
+
+    /// Zooms so the whole image fits in its pane.
+    pub fn zoom_to_fit(&mut self) {
+        let zoom = (self.pane_width() as f64 / self.width as f64).min(self.frame_height as f64 / self.height as f64);
+        self.set_zoom(zoom);
+    }
+
+    /// Zooms so the image covers its pane, cropping the overflow evenly from both sides.
+    pub fn zoom_to_fill(&mut self) {
+        let zoom = (self.pane_width() as f64 / self.width as f64).max(self.frame_height as f64 / self.height as f64);
+        self.set_zoom(zoom);
+        self.centre_on(self.width as f64 / 2.0, self.height as f64 / 2.0);
+    }
+
+    /// Frame size that shows the whole image (both, side by side, when comparing) at the current zoom.
+    pub fn zoomed_size(&self) -> (u32, u32) {
+        let (mut width, mut height) = (self.width, self.height);
+
+        if let Some(compare) = &self.compare {
+            width = width.max(compare.first().map_or(0, |row| row.len()) as u32);
+            height = height.max(compare.len() as u32);
+
+            if self.compare_mode == CompareMode::Split {
+                width *= 2;
+            }
+        }
+
+        ((width as f64 * self.zoom).ceil() as u32, (height as f64 * self.zoom).ceil() as u32)
+    }
+
+    pub fn begin_pan(&mut self) {
+        self.pan_anchor = Some(self.frame_cursor);
+    }
+
+    pub fn end_pan(&mut self) {
+        self.pan_anchor = None;
+    }
+
+    /// Keeps the image on screen: an axis that fits is pinned to 0, otherwise the view may not run past the image edge.
+    fn clamp_origin(&mut self) {
+        let visible_width = self.pane_width() as f64 / self.zoom;
+        let visible_height = self.frame_height as f64 / self.zoom;
+
+        self.origin.0 = self.origin.0.clamp(0.0, (self.width as f64 - visible_width).max(0.0));
+        self.origin.1 = self.origin.1.clamp(0.0, (self.height as f64 - visible_height).max(0.0));
+    }
+
+    /// Centres the view on an image coordinate.
+    fn centre_on(&mut self, x: f64, y: f64) {
+        self.origin = (
+            x - self.pane_width() as f64 / self.zoom / 2.0,
+            y - self.frame_height as f64 / self.zoom / 2.0,
+        );
+        self.clamp_origin();
+    }
+
+    pub fn set_metadata(&mut self, metadata: Vec<String>) {
+        self.metadata = metadata;
+    }
+
+    pub fn toggle_metadata(&mut self) {
+        self.show_metadata = !self.show_metadata;
+    }
+
+    pub fn set_filter_types(&mut self, filter_map: Vec<Vec<u8>>, filter_statistics: Vec<String>) {
+        self.filter_map = Some(filter_map);
+        self.filter_statistics = filter_statistics;
+    }
+
+    pub fn toggle_filters(&mut self) {
+        self.show_filters = !self.show_filters;
+    }
+
+    /// Shows or hides the loupe, returning whether that changed anything.
+    pub fn set_loupe(&mut self, shown: bool) -> bool {
+        let changed = self.show_loupe != shown;
+        self.show_loupe = shown;
+        changed
+    }
+
+    pub fn toggle_rulers(&mut self) {
+        self.show_rulers = !self.show_rulers;
+    }
+
+    /// Sets which of `count` images is being shown, `index` counting from 0.
+    pub fn set_position(&mut self, index: usize, count: usize) {
+        self.position = (index, count);
+    }
+
+    pub fn selection(&self) -> Option<Rect> {
+        self.selection
+    }
+
+    /// Converts a frame position into image coordinates, clamped to the image.
+    /// When comparing, positions in either pane map onto the same image coordinates.
+    fn frame_to_image(&self, x: f64, y: f64) -> (u32, u32) {
+        let x = x % self.pane_width().max(1) as f64;
+        let ix = (self.origin.0 + x / self.zoom).clamp(0.0, (self.width - 1) as f64);
+        let iy = (self.origin.1 + y / self.zoom).clamp(0.0, (self.height - 1) as f64);
+
+        (ix as u32, iy as u32)
+    }
+
+    /// Updates the cursor position (in frame pixels). Returns true if the view or selection
+    /// changed, or anything drawn at the cursor moved with it.
+    pub fn cursor_moved(&mut self, x: f64, y: f64) -> bool {
+        // the crosshair and loupe follow the cursor, and the menu highlights the item under it
+        let follows = (self.show_rulers || self.show_loupe || self.menu.is_some()) && self.frame_cursor != (x, y);
+
+        self.frame_cursor = (x, y);
+        self.cursor = self.frame_to_image(x, y);
+
+        if let Some((anchor_x, anchor_y)) = self.pan_anchor {
+            self.origin.0 -= (x - anchor_x) / self.zoom;
+            self.origin.1 -= (y - anchor_y) / self.zoom;
+            self.clamp_origin();
+            self.pan_anchor = Some((x, y));
+            return true;
+        }
+
+        if self.measuring {
+            if let Some((_, end)) = &mut self.measurement {
+                let changed = *end != self.cursor;
+                *end = self.cursor;
+                return changed || follows;
+            }
+        }
+
+        match self.selection_anchor {
+            Some(anchor) => {
+                let selection = Some(Rect::from_corners(anchor, self.cursor));
+                let changed = selection != self.selection;
+                self.selection = selection;
+                changed || follows
+            }
+            None => follows
+        }
+    }
+
+    pub fn begin_selection(&mut self) {
+        self.selection_anchor = Some(self.cursor);
+        self.selection = Some(Rect::from_corners(self.cursor, self.cursor));
+        self.sample = None;
+    }
+
+    /// Starts a selection whose colour statistics are taken once it's released.
+    pub fn begin_sample(&mut self) {
+        self.begin_selection();
+        self.sampling = true;
+    }
+
+    /// Ends a selection started with `begin_sample`, returning its size and position and the
+    /// mean, minimum and maximum of each channel as lines of text, which stay shown beside it.
+    pub fn end_sample(&mut self) -> Option<Vec<String>> {
+        if !std::mem::take(&mut self.sampling) {
+            return None;
+        }
+
+        let rect = self.selection?;
+        let pixels = self.crop(rect);
+        let count = (rect.width * rect.height) as f64;
+
+        let mut sum = [0u64; 4];
+        let mut min = [0xFFu8; 4];
+        let mut max = [0u8; 4];
+
+        for pixel in pixels.iter().flatten() {
+            for (c, value) in [pixel.r, pixel.g, pixel.b, pixel.a].into_iter().enumerate() {
+                sum[c] += value as u64;
+                min[c] = min[c].min(value);
+                max[c] = max[c].max(value);
+            }
+        }
+
+        let mean = sum.map(|sum| sum as f64 / count);
+        let row = |name: &str, values: [String; 4]| format!("{:<5}{:>7}{:>7}{:>7}{:>7}", name, values[0], values[1], values[2], values[3]);
+
+        let lines = vec![
+            format!("{}x{} at ({}, {})", rect.width, rect.height, rect.x, rect.y),
+            row("", ["r", "g", "b", "a"].map(String::from)),
+            row("mean", mean.map(|mean| format!("{:.1}", mean))),
+            row("min", min.map(|min| min.to_string())),
+            row("max", max.map(|max| max.to_string())),
+        ];
+
+        self.sample = Some(lines.clone());
+
+        Some(lines)
+    }
+
+    pub fn end_selection(&mut self) {
+        self.selection_anchor = None;
+    }
+
+    /// Drops the selection, its statistics and any measurement.
+    pub fn clear_selection(&mut self) {
+        self.selection_anchor = None;
+        self.selection = None;
+        self.sample = None;
+        self.measurement = None;
+        self.measuring = false;
+    }
+
+    /// Starts measuring from the pixel under the cursor, replacing any earlier measurement.
+    pub fn begin_measure(&mut self) {
+        self.measurement = Some((self.cursor, self.cursor));
+        self.measuring = true;
+    }
+
+    /// Stops following the cursor, leaving the measurement shown until the selection is cleared.
+    pub fn end_measure(&mut self) {
+        self.measuring = false;
+    }
+
+    /// Frame rectangle of the minimap as (x, y, width, height, minimap pixels per image pixel),
+    /// or None while the whole image fits in the window.
+    fn minimap_rect(&self) -> Option<(usize, usize, usize, usize, f64)> {
+        let visible_width = self.pane_width() as f64 / self.zoom;
+        let visible_height = self.frame_height as f64 / self.zoom;
+
+        if visible_width >= self.width as f64 && visible_height >= self.height as f64 {
+            return None;
+        }
+
+        let scale = (MINIMAP_SIZE * self.text_scale) as f64 / self.width.max(self.height) as f64;
+        let width = ((self.width as f64 * scale) as usize).max(1);
+        let height = ((self.height as f64 * scale) as usize).max(1);
+        let margin = 8 * self.text_scale;
+
+        if width + margin > self.frame_width as usize || height + margin > self.frame_height as usize {
+            return None;
+        }
+
+        Some((self.frame_width as usize - width - margin, self.frame_height as usize - height - margin, width, height, scale))
+    }
+
+    /// Jumps the view to the clicked point if the cursor is over the minimap. Returns true if it was.
+    pub fn click_minimap(&mut self) -> bool {
+        let Some((x, y, width, height, scale)) = self.minimap_rect() else {
+            return false;
+        };
+
+        let (cx, cy) = self.frame_cursor;
+        if cx < x as f64 || cy < y as f64 || cx >= (x + width) as f64 || cy >= (y + height) as f64 {
+            return false;
+        }
+
+        self.centre_on((cx - x as f64) / scale, (cy - y as f64) / scale);
+        true
+    }
+
+    /// Opens the right-click menu at the cursor.
+    pub fn open_menu(&mut self) {
+        self.menu = Some(self.frame_cursor);
+    }
+
+    /// Closes the right-click menu, returning the item under the cursor if there was one, or
+    /// None if the click was outside it or it wasn't open.
+    pub fn close_menu(&mut self) -> Option<MenuItem> {
+        let item = self.menu_item();
+        self.menu = None;
+        item
+    }
+
+    pub fn is_menu_open(&self) -> bool {
+        self.menu.is_some()
+    }
+
+    /// The menu's left, top, width and item height in frame pixels, moved left or up from where
+    /// it was opened if it would run off the frame.
+    fn menu_rect(&self) -> Option<(usize, usize, usize, usize)> {
+        let (x, y) = self.menu?;
+        let scale = self.text_scale;
+        let padding = 4 * scale;
+        let item_height = (font::GLYPH_HEIGHT + 4) * scale;
+
+        let width = MenuItem::ALL.iter().map(|item| font::text_width(item.label(), scale)).max().unwrap_or(0) + padding * 2;
+        let height = MenuItem::ALL.len() * item_height;
+
+        let left = (x as usize).min((self.frame_width as usize).saturating_sub(width));
+        let top = (y as usize).min((self.frame_height as usize).saturating_sub(height));
+
+        Some((left, top, width, item_height))
+    }
+
+    fn menu_item(&self) -> Option<MenuItem> {
+        let (left, top, width, item_height) = self.menu_rect()?;
+        let (cx, cy) = self.frame_cursor;
+
+        if cx < left as f64 || cx >= (left + width) as f64 || cy < top as f64 {
+            return None;
+        }
+
+        MenuItem::ALL.get((cy as usize - top) / item_height).copied()
+    }
+
+    pub fn crop(&self, rect: Rect) -> Vec<Vec<Pixel>> {
+        self.pixel_data[rect.y as usize..(rect.y + rect.height) as usize]
+            .iter()
+            .map(|row| row[rect.x as usize..(rect.x + rect.width) as usize].to_vec())
+            .collect()
+    }
+
+    /// Draws the `viewport` part of the image, scaled by `zoom` with nearest-neighbour sampling,
+    /// composited onto the background and colour managed, into the top-left of any RGBA buffer
+    /// whose rows are `dst_stride` bytes apart. Whatever doesn't fit in `dst` is clipped.
+    pub fn blit(&self, dst: &mut [u8], dst_stride: usize, viewport: Rect, zoom: f64) {
+        let width = ((viewport.width as f64 * zoom) as usize).max(1).min(dst_stride / 4);
+        let height = ((viewport.height as f64 * zoom) as usize).max(1);
+        let origin = (viewport.x as f64, viewport.y as f64);
+
+        blit::blit_with(dst, dst_stride, (width, height), origin, zoom, |_, _, x, y| {
+            let ix = (x as usize).min(self.width as usize - 1);
+            let iy = (y as usize).min(self.height as usize - 1);
+            let pixel = self.composite(self.prepare(self.pixel_data[iy][ix]));
+
+            [pixel.r, pixel.g, pixel.b, 0xFF]
+        });
+
+        if let Some(transform) = &self.image_transform {
+            for row in dst.chunks_mut(dst_stride).take(height) {
+                let len = (width * 4).min(row.len());
+                transform.apply(&mut row[..len]);
+            }
+        }
+    }
+
+    /// Fills a frame of the window's size with the image, selection outline and overlays.
+    pub fn draw(&self, frame: &mut [u8]) {
+        self.draw_image(frame);
+        self.draw_overlays(frame);
+    }
+
+    /// Draws only the overlays onto a transparent frame, for compositing over an image drawn
+    /// elsewhere. What would invert or darken the image turns white or translucent black instead.
+    pub fn draw_overlays_only(&self, frame: &mut [u8]) {
+        frame.fill(0);
+        self.draw_overlays(frame);
+    }
+
+    /// Fills a frame with just the image, for when there's nobody to read the overlays.
+    pub fn draw_image_only(&self, frame: &mut [u8]) {
+        self.draw_image(frame);
+    }
+
+    fn draw_overlays(&self, frame: &mut [u8]) {
+        if let Some(selection) = self.selection {
+            self.draw_selection(frame, selection);
+
+            if let Some(sample) = &self.sample {
+                let x = ((selection.x + selection.width) as f64 - self.origin.0) * self.zoom;
+                let y = ((selection.y + selection.height) as f64 - self.origin.1) * self.zoom;
+                self.draw_label(frame, &sample.iter().map(String::as_str).collect::<Vec<_>>(), x.max(0.0) as usize, y.max(0.0) as usize);
+            }
+        }
+
+        if let Some((start, end)) = self.measurement {
+            self.draw_measurement(frame, start, end);
+        }
+
+        self.draw_minimap(frame);
+
+        if self.position.1 > 1 {
+            self.draw_position(frame);
+        }
+
+        if self.show_metadata {
+            self.draw_metadata(frame);
+        }
+
+        if self.show_filters {
+            self.draw_filter_legend(frame);
+        }
+
+        if self.show_rulers {
+            self.draw_rulers(frame);
+            self.draw_crosshair(frame);
+        }
+
+        if self.show_loupe {
+            self.draw_loupe(frame);
+        }
+
+        self.draw_menu(frame);
+    }
+
+    fn draw_image(&self, frame: &mut [u8]) {
+        let (frame_width, frame_height) = (self.frame_width as usize, self.frame_height as usize);
+        let pane_width = self.pane_width().max(1) as usize;
+
+        for pane in 0..frame_width.div_ceil(pane_width) {
+            // the second pane starts with the divider, and its image one pixel in
+            let divider = usize::from(pane == 1);
+            let left = pane * pane_width + divider;
+            let width = ((pane + 1) * pane_width).min(frame_width).saturating_sub(left);
+            let origin = (self.origin.0 + divider as f64 / self.zoom, self.origin.1);
+
+            blit::blit_with(&mut frame[left * 4..], frame_width * 4, (width, frame_height), origin, self.zoom, |dx, fy, x, y| {
+                let Some(pixel) = self.sample_filtered(pane, x, y) else {
+                    return self.background;
+                };
+
+                let pixel = self.present(pixel);
+                let pixel = if self.show_clipping { self.mark_clipping(pixel, left + dx, fy) } else { pixel };
+                let pixel = if self.show_filters && pane == 0 { self.tint_filter(pixel, x as usize, y as usize) } else { pixel };
+
+                [pixel.r, pixel.g, pixel.b, pixel.a]
+            });
+
+            if divider == 1 {
+                for row in frame.chunks_mut(frame_width * 4) {
+                    row[(left - 1) * 4..left * 4].copy_from_slice(&DIVIDER);
+                }
+            }
+        }
+
+        for row in frame.chunks_mut(frame_width * 4) {
+            for (pane, segment) in row.chunks_mut(pane_width * 4).enumerate() {
+                if let Some(transform) = self.pane_transform(pane) {
+                    transform.apply(segment);
+                }
+            }
+        }
+    }
+
+    /// The pixel shown at a fractional image coordinate in the given pane, interpolated
+    /// according to the scaling filter.
+    /// The result is premultiplied when `premultiplied_alpha` is set.
+    fn sample_filtered(&self, pane: usize, x: f64, y: f64) -> Option<Pixel> {
+        let nearest = self.prepare(self.sample(pane, x as usize, y as usize)?);
+
+        // interpolating would blur hard alpha edges into partial alpha that isn't in the image
+        match if self.alpha_view.is_some() { ScalingFilter::Nearest } else { self.filter } {
+            ScalingFilter::Nearest => Some(nearest),
+            ScalingFilter::Linear => Some(self.sample_linear(pane, x, y, nearest)),
+            ScalingFilter::Bicubic => Some(self.sample_bicubic(pane, x, y, nearest)),
+        }
+    }
+
+    /// Interpolates between the four pixels around a position. Pixels off the image are
+    /// replaced by `nearest`, the one the position falls in.
+    fn sample_linear(&self, pane: usize, x: f64, y: f64, nearest: Pixel) -> Pixel {
+        // pixel centres sit at .5, so interpolate between the pixels either side of the shifted position
+        let (x, y) = ((x - 0.5).max(0.0), (y - 0.5).max(0.0));
+        let (x0, y0) = (x as usize, y as usize);
+        let (tx, ty) = (x.fract(), y.fract());
+        let get = |dx: usize, dy: usize| self.sample(pane, x0 + dx, y0 + dy).map(|pixel| self.prepare(pixel)).unwrap_or(nearest);
+
+        let (p00, p10, p01, p11) = (get(0, 0), get(1, 0), get(0, 1), get(1, 1));
+        let lerp = |a: u8, b: u8, c: u8, d: u8| {
+            let top = a as f64 + (b as f64 - a as f64) * tx;
+            let bottom = c as f64 + (d as f64 - c as f64) * tx;
+            (top + (bottom - top) * ty).round() as u8
+        };
+
+        Pixel {
+            r: lerp(p00.r, p10.r, p01.r, p11.r),
+            g: lerp(p00.g, p10.g, p01.g, p11.g),
+            b: lerp(p00.b, p10.b, p01.b, p11.b),
+            a: lerp(p00.a, p10.a, p01.a, p11.a),
+        }
+    }
+
+    /// Catmull-Rom interpolation over the 4x4 pixels around a position. Pixels off the image
+    /// are replaced by `nearest`, the one the position falls in.
+    fn sample_bicubic(&self, pane: usize, x: f64, y: f64, nearest: Pixel) -> Pixel {
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor() as i64 - 1, y.floor() as i64 - 1);
+        let (wx, wy) = (catmull_rom(x - x.floor()), catmull_rom(y - y.floor()));
+
+        let mut sum = [0.0; 4];
+
+        for (j, wy) in wy.iter().enumerate() {
+            for (i, wx) in wx.iter().enumerate() {
+                let (sx, sy) = (x0 + i as i64, y0 + j as i64);
+                let pixel = match sx >= 0 && sy >= 0 {
+                    true => self.sample(pane, sx as usize, sy as usize).map(|pixel| self.prepare(pixel)).unwrap_or(nearest),
+                    false => nearest,
+                };
+
+                for (total, c) in sum.iter_mut().zip([pixel.r, pixel.g, pixel.b, pixel.a]) {
+                    *total += c as f64 * wx * wy;
+                }
+            }
+        }
+
+        let channel = |c: f64| c.round().clamp(0.0, 255.0) as u8;
+        let a = channel(sum[3]);
+
+        // overshoot can leave premultiplied colour brighter than its alpha allows
+        let limit = |c: u8| if self.premultiplied_alpha { c.min(a) } else { c };
+
+        Pixel {
+            r: limit(channel(sum[0])),
+            g: limit(channel(sum[1])),
+            b: limit(channel(sum[2])),
+            a,
+        }
+    }
+
+    /// What a pane shows, or None for the strip left over beside an odd-width split.
+    fn pane_source(&self, pane: usize) -> Option<PaneSource> {
+        if self.compare.is_none() {
+            return if pane == 0 { Some(PaneSource::Image) } else { None };
+        }
+
+        match (self.compare_mode, pane) {
+            (CompareMode::Split, 0) => Some(PaneSource::Image),
+            (CompareMode::Split, 1) => Some(PaneSource::Compare),
+            (CompareMode::Blink { showing_compare: false }, 0) => Some(PaneSource::Image),
+            (CompareMode::Blink { showing_compare: true }, 0) => Some(PaneSource::Compare),
+            (CompareMode::Difference { amplify }, 0) => Some(PaneSource::Difference { amplify }),
+            _ => None,
+        }
+    }
+
+    /// The pixel shown at an image coordinate in the given pane, or None where there is no image.
+    fn sample(&self, pane: usize, x: usize, y: usize) -> Option<Pixel> {
+        let get = |data: &Vec<Vec<Pixel>>| data.get(y).and_then(|row| row.get(x)).copied();
+        let compare = || self.compare.as_ref().and_then(get);
+
+        match self.pane_source(pane)? {
+            PaneSource::Image => get(&self.pixel_data),
+            PaneSource::Compare => compare(),
+            PaneSource::Difference { amplify } => {
+                let (a, b) = (get(&self.pixel_data), compare());
+                if a.is_none() && b.is_none() {
+                    return None;
+                }
+
+                let (a, b) = (a.unwrap_or_default(), b.unwrap_or_default());
+                let alpha = a.a.abs_diff(b.a);
+                let channel = |x: u8, y: u8| x.abs_diff(y).max(alpha).saturating_mul(amplify);
+
+                Some(Pixel {
+                    r: channel(a.r, b.r),
+                    g: channel(a.g, b.g),
+                    b: channel(a.b, b.b),
+                    a: 0xFF,
+                })
+            }
+        }
+    }
+
+    /// Turns a sampled pixel into an opaque one: a premultiplied pixel is composited over the
+    /// background, a straight one just has its alpha dropped. Over a translucent background, as
+    /// in a transparent window, the result is as translucent as the two together, premultiplied.
+    fn composite(&self, pixel: Pixel) -> Pixel {
+        if !self.premultiplied_alpha {
+            return Pixel { a: 0xFF, ..pixel };
+        }
+
+        let below = |c: u8| (c as u16 * (0xFF - pixel.a) as u16 + 127) / 0xFF;
+        let over = |c: u8, background: u8| c.saturating_add(below((background as u16 * self.background[3] as u16 / 0xFF) as u8) as u8);
+
+        Pixel {
+            r: over(pixel.r, self.background[0]),
+            g: over(pixel.g, self.background[1]),
+            b: over(pixel.b, self.background[2]),
+            a: over(pixel.a, 0xFF),
+        }
+    }
+
+    /// Readies an image pixel for filtering: encodes linear samples as sRGB and premultiplies.
+    fn prepare(&self, pixel: Pixel) -> Pixel {
+        let encode = |c: u8| self.from_linear[(c as usize * LINEAR_STEPS + 127) / 255];
+        let pixel = match self.linear_samples {
+            true => Pixel { r: encode(pixel.r), g: encode(pixel.g), b: encode(pixel.b), a: pixel.a },
+            false => pixel,
+        };
+
+        if self.premultiplied_alpha { premultiply(pixel) } else { pixel }
+    }
+
+    /// Turns a sampled pixel into the opaque one shown, with every display adjustment applied.
+    fn present(&self, pixel: Pixel) -> Pixel {
+        if let Some(alpha_view) = self.alpha_view {
+            return alpha_view.show(pixel.a);
+        }
+
+        let pixel = if self.swap_red_blue { Pixel { r: pixel.b, b: pixel.r, ..pixel } } else { pixel };
+        let pixel = if self.invert { self.invert_colour(pixel) } else { pixel };
+        let pixel = self.adjust_tone(self.composite(pixel));
+
+        match self.simulation {
+            Some(simulation) => self.simulate(pixel, simulation),
+            None => pixel,
+        }
+    }
+
+    /// Colours a shown pixel red if it's pure white or blue if it's pure black, where it falls on
+    /// a diagonal stripe.
+    fn mark_clipping(&self, pixel: Pixel, fx: usize, fy: usize) -> Pixel {
+        let width = ZEBRA_WIDTH * self.text_scale;
+        let stripe = (fx + fy) / width + self.clipping_phase as usize;
+
+        match (pixel.r, pixel.g, pixel.b) {
+            _ if stripe % 2 == 1 => pixel,
+            (0xFF, 0xFF, 0xFF) => Pixel { r: 0xFF, g: 0, b: 0, a: 0xFF },
+            (0, 0, 0) => Pixel { r: 0, g: 0x40, b: 0xFF, a: 0xFF },
+            _ => pixel,
+        }
+    }
+
+    /// Inverts a sampled pixel's colour, which for a premultiplied pixel means subtracting it
+    /// from its alpha rather than from white.
+    fn invert_colour(&self, pixel: Pixel) -> Pixel {
+        let full = if self.premultiplied_alpha { pixel.a } else { 0xFF };
+
+        Pixel {
+            r: full.saturating_sub(pixel.r),
+            g: full.saturating_sub(pixel.g),
+            b: full.saturating_sub(pixel.b),
+            a: pixel.a,
+        }
+    }
+
+    fn simulate(&self, pixel: Pixel, simulation: Simulation) -> Pixel {
+        let rgb = [pixel.r, pixel.g, pixel.b].map(|c| self.to_linear[c as usize]);
+        let channel = |row: [f64; 3]| {
+            let linear = (row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]).clamp(0.0, 1.0);
+            self.from_linear[(linear * LINEAR_STEPS as f64).round() as usize]
+        };
+        let [r, g, b] = simulation.matrix().map(channel);
+
+        Pixel { r, g, b, a: pixel.a }
+    }
+
+    fn adjust_tone(&self, pixel: Pixel) -> Pixel {
+        Pixel {
+            r: self.tone_curve[pixel.r as usize],
+            g: self.tone_curve[pixel.g as usize],
+            b: self.tone_curve[pixel.b as usize],
+            a: pixel.a,
+        }
+    }
+
+    /// Mixes a pixel half-and-half with the colour of the filter its scanline used.
+    fn tint_filter(&self, pixel: Pixel, x: usize, y: usize) -> Pixel {
+        let Some(filter_type) = self.filter_map.as_ref().and_then(|map| map.get(y)).and_then(|row| row.get(x)) else {
+            return pixel;
+        };
+
+        let colour = FILTER_COLOURS.get(*filter_type as usize).unwrap_or(&[0xFF, 0x00, 0xFF]);
+        let mix = |c: u8, t: u8| ((c as u16 + t as u16) / 2) as u8;
+
+        Pixel {
+            r: mix(pixel.r, colour[0]),
+            g: mix(pixel.g, colour[1]),
+            b: mix(pixel.b, colour[2]),
+            a: 0xFF,
+        }
+    }
+
+    /// Outlines the selection by inverting the frame pixels along its border.
+    fn draw_selection(&self, frame: &mut [u8], selection: Rect) {
+        let x0 = (selection.x as f64 - self.origin.0) * self.zoom;
+        let y0 = (selection.y as f64 - self.origin.1) * self.zoom;
+        let x1 = ((selection.x + selection.width) as f64 - self.origin.0) * self.zoom;
+        let y1 = ((selection.y + selection.height) as f64 - self.origin.1) * self.zoom;
+
+        self.invert_outline(frame, x0, y0, x1, y1);
+    }
+
+    /// Outlines the bounding box of two image pixels, inverts a line between their centres, and
+    /// labels it with the offset, distance and box size in image pixels.
+    fn draw_measurement(&self, frame: &mut [u8], start: (u32, u32), end: (u32, u32)) {
+        let to_frame = |(x, y): (u32, u32)| ((x as f64 + 0.5 - self.origin.0) * self.zoom, (y as f64 + 0.5 - self.origin.1) * self.zoom);
+        let (x0, y0) = to_frame(start);
+        let (x1, y1) = to_frame(end);
+
+        let bounds = Rect::from_corners(start, end);
+        self.invert_outline(
+            frame,
+            (bounds.x as f64 - self.origin.0) * self.zoom,
+            (bounds.y as f64 - self.origin.1) * self.zoom,
+            ((bounds.x + bounds.width) as f64 - self.origin.0) * self.zoom,
+            ((bounds.y + bounds.height) as f64 - self.origin.1) * self.zoom,
+        );
+
+        // one frame pixel per step along the longer axis, skipping the outline's corners so they
+        // aren't inverted back
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as usize;
+        for i in 1..steps {
+            let t = i as f64 / steps as f64;
+            let (fx, fy) = (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+
+            if fx >= 0.0 && fy >= 0.0 && fx < self.frame_width as f64 && fy < self.frame_height as f64 {
+                self.invert_pixel(frame, fx as usize, fy as usize);
+            }
+        }
+
+        let (dx, dy) = (end.0 as i64 - start.0 as i64, end.1 as i64 - start.1 as i64);
+        let text = format!(
+            "dx {}  dy {}  {:.2} px  box {}x{}",
+            dx, dy, ((dx * dx + dy * dy) as f64).sqrt(), bounds.width, bounds.height,
+        );
+        self.draw_label(frame, &[&text], x1.max(0.0) as usize, y1.max(0.0) as usize);
+    }
+
+    /// Inverts the frame pixels along the border of a frame-space rectangle, clipping what falls off screen.
+    fn invert_outline(&self, frame: &mut [u8], x0: f64, y0: f64, x1: f64, y1: f64) {
+        let (x0, y0) = (x0.floor() as i64, y0.floor() as i64);
+        let (x1, y1) = ((x1.ceil() as i64 - 1).max(x0), (y1.ceil() as i64 - 1).max(y0));
+
+        for fy in y0.max(0)..=y1.min(self.frame_height as i64 - 1) {
+            for fx in x0.max(0)..=x1.min(self.frame_width as i64 - 1) {
+                if fx != x0 && fx != x1 && fy != y0 && fy != y1 {
+                    continue;
+                }
+
+                self.invert_pixel(frame, fx as usize, fy as usize);
+            }
+        }
+    }
+
+    /// Inverts one frame pixel, turning a transparent overlay pixel white.
+    fn invert_pixel(&self, frame: &mut [u8], fx: usize, fy: usize) {
+        let idx = (fy * self.frame_width as usize + fx) * 4;
+
+        for c in &mut frame[idx..idx + 3] {
+            *c = 0xFF - *c;
+        }
+        frame[idx + 3] = 0xFF;
+    }
+
+    /// Darkens a frame rectangle as a backdrop for text, clipping what falls off screen. A
+    /// transparent overlay pixel turns translucent black.
+    fn darken_rect(&self, frame: &mut [u8], x: usize, y: usize, width: usize, height: usize) {
+        for fy in y..(y + height).min(self.frame_height as usize) {
+            for fx in x..(x + width).min(self.frame_width as usize) {
+                let idx = (fy * self.frame_width as usize + fx) * 4;
+                for c in &mut frame[idx..idx + 3] {
+                    *c /= 4;
+                }
+                frame[idx + 3] = if frame[idx + 3] == 0 { 0xC0 } else { 0xFF };
+            }
+        }
+    }
+
+    /// Draws a thumbnail of the whole image in the bottom-right corner with the viewport outlined.
+    fn draw_minimap(&self, frame: &mut [u8]) {
+        let Some((x, y, width, height, scale)) = self.minimap_rect() else {
+            return;
+        };
+
+        let whole = Rect { x: 0, y: 0, width: self.width, height: self.height };
+        self.blit(&mut frame[(y * self.frame_width as usize + x) * 4..], self.frame_width as usize * 4, whole, scale);
+
+        let viewport_x = x as f64 + self.origin.0 * scale;
+        let viewport_y = y as f64 + self.origin.1 * scale;
+        let viewport_width = self.pane_width() as f64 / self.zoom * scale;
+        let viewport_height = self.frame_height as f64 / self.zoom * scale;
+
+        self.invert_outline(
+            frame,
+            viewport_x,
+            viewport_y,
+            (viewport_x + viewport_width).min((x + width) as f64),
+            (viewport_y + viewport_height).min((y + height) as f64),
+        );
+    }
+
+    /// Draws rulers along the top and left edges, ticked in image pixels of the pane beneath,
+    /// with labels at every step that leaves room for them.
+    fn draw_rulers(&self, frame: &mut [u8]) {
+        let scale = self.text_scale;
+        let (frame_width, frame_height) = (self.frame_width as usize, self.frame_height as usize);
+        let pane_width = self.pane_width().max(1) as usize;
+
+        let digits = self.width.max(self.height).to_string().len();
+        let top = (font::GLYPH_HEIGHT + 4) * scale;
+        let left = (digits * font::GLYPH_WIDTH + 4) * scale;
+        let (step, minor_step) = ruler_steps(self.zoom, (digits * font::GLYPH_WIDTH + 8) * scale);
+        let minor_step = if minor_step as f64 * self.zoom >= (4 * scale) as f64 { minor_step } else { step };
+
+        self.darken_rect(frame, 0, 0, frame_width, top);
+        self.darken_rect(frame, 0, top, left, frame_height.saturating_sub(top));
+
+        // the first tick in the image coordinates a frame pixel spans, if any
+        let tick = |start: f64, end: f64, size: u32| {
+            let tick = (start / minor_step as f64).ceil().max(0.0) as u64 * minor_step;
+            (tick as f64 <= end.min(size as f64)).then_some(tick)
+        };
+        let white = [0xFF, 0xFF, 0xFF, 0xFF];
+
+        for fx in left..frame_width {
+            let pane_x = (fx % pane_width) as f64;
+            let Some(x) = tick(self.origin.0 + pane_x / self.zoom, self.origin.0 + (pane_x + 1.0) / self.zoom, self.width) else {
+                continue;
+            };
+
+            let length = if x % step == 0 { top } else { 3 * scale };
+
+            for fy in top - length.min(top)..top.min(frame_height) {
+                frame[(fy * frame_width + fx) * 4..][..4].copy_from_slice(&white);
+            }
+
+            if x % step == 0 {
+                font::draw_text(frame, frame_width, fx + 2 * scale, 2 * scale, &x.to_string(), white, scale);
+            }
+        }
+
+        for fy in top..frame_height {
+            let frame_y = fy as f64;
+            let Some(y) = tick(self.origin.1 + frame_y / self.zoom, self.origin.1 + (frame_y + 1.0) / self.zoom, self.height) else {
+                continue;
+            };
+
+            let length = if y % step == 0 { left } else { 3 * scale };
+
+            for fx in left - length.min(left)..left.min(frame_width) {
+                frame[(fy * frame_width + fx) * 4..][..4].copy_from_slice(&white);
+            }
+
+            if y % step == 0 {
+                font::draw_text(frame, frame_width, 2 * scale, fy + 2 * scale, &y.to_string(), white, scale);
+            }
+        }
+    }
+
+    /// Inverts a horizontal and a vertical line through the cursor, and shows the image
+    /// coordinate and value of the pixel under it beside the cursor.
+    fn draw_crosshair(&self, frame: &mut [u8]) {
+        let (frame_width, frame_height) = (self.frame_width as usize, self.frame_height as usize);
+        let (cx, cy) = (self.frame_cursor.0 as usize, self.frame_cursor.1 as usize);
+
+        if cx >= frame_width || cy >= frame_height {
+            return;
+        }
+
+        for fx in 0..frame_width {
+            self.invert_pixel(frame, fx, cy);
+        }
+
+        for fy in (0..frame_height).filter(|&fy| fy != cy) {
+            self.invert_pixel(frame, cx, fy);
+        }
+
+        let Some(pixel) = self.pixel_data.get(self.cursor.1 as usize).and_then(|row| row.get(self.cursor.0 as usize)) else {
+            return;
+        };
+
+        let text = format!("{}, {}  #{:02x}{:02x}{:02x}{:02x}", self.cursor.0, self.cursor.1, pixel.r, pixel.g, pixel.b, pixel.a);
+        self.draw_label(frame, &[&text], cx, cy);
+    }
+
+    /// Draws a circle centred on the cursor showing the pixels of the pane beneath at
+    /// `LOUPE_ZOOM` times the zoom, without filtering, with the pixel under the cursor outlined
+    /// and its value labelled below.
+    fn draw_loupe(&self, frame: &mut [u8]) {
+        let (frame_width, frame_height) = (self.frame_width as i64, self.frame_height as i64);
+        let (cx, cy) = (self.frame_cursor.0 as i64, self.frame_cursor.1 as i64);
+        let pane_width = self.pane_width().max(1) as f64;
+        let pane = (self.frame_cursor.0 / pane_width) as usize;
+
+        let radius = (LOUPE_RADIUS * self.text_scale) as i64;
+        let outer = radius + 2 * self.text_scale as i64;
+        let zoom = self.zoom * LOUPE_ZOOM;
+        // the image position under the cursor stays under it when blown up
+        let centre = (self.origin.0 + self.frame_cursor.0 % pane_width / self.zoom, self.origin.1 + self.frame_cursor.1 / self.zoom);
+
+        let mut row = vec![];
+
+        for fy in (cy - outer).max(0)..(cy + outer + 1).min(frame_height) {
+            let dy = fy - cy;
+            let half = ((outer * outer - dy * dy) as f64).sqrt() as i64;
+            let (x0, x1) = ((cx - half).max(0), (cx + half + 1).min(frame_width));
+            let y = centre.1 + dy as f64 / zoom;
+
+            row.clear();
+
+            for fx in x0..x1 {
+                let dx = fx - cx;
+                let x = centre.0 + dx as f64 / zoom;
+
+                let colour = match self.sample(pane, x.max(0.0) as usize, y.max(0.0) as usize).filter(|_| x >= 0.0 && y >= 0.0) {
+                    _ if dx * dx + dy * dy > radius * radius => DIVIDER,
+                    Some(pixel) => {
+                        let pixel = self.present(self.prepare(pixel));
+                        [pixel.r, pixel.g, pixel.b, pixel.a]
+                    }
+                    None => self.background,
+                };
+
+                row.extend_from_slice(&colour);
+            }
+
+            if let Some(transform) = self.pane_transform(pane) {
+                transform.apply(&mut row);
+            }
+
+            let idx = ((fy * frame_width + x0) * 4) as usize;
+            frame[idx..idx + row.len()].copy_from_slice(&row);
+        }
+
+        let (ix, iy) = (centre.0.floor(), centre.1.floor());
+        let (x0, y0) = (cx as f64 + (ix - centre.0) * zoom, cy as f64 + (iy - centre.1) * zoom);
+        self.invert_outline(frame, x0, y0, x0 + zoom, y0 + zoom);
+
+        if let Some(pixel) = self.sample(pane, ix.max(0.0) as usize, iy.max(0.0) as usize).filter(|_| ix >= 0.0 && iy >= 0.0) {
+            let text = format!("{}, {}  #{:02x}{:02x}{:02x}{:02x}", ix, iy, pixel.r, pixel.g, pixel.b, pixel.a);
+            self.draw_label(frame, &[&text], cx.max(0) as usize, (cy + outer).max(0) as usize);
+        }
+    }
+
+    /// Draws lines of text on a darkened backdrop just below and right of a frame point, or on
+    /// whichever side keeps it on screen.
+    fn draw_label(&self, frame: &mut [u8], lines: &[&str], x: usize, y: usize) {
+        let scale = self.text_scale;
+        let padding = 3 * scale;
+        let offset = 12 * scale;
+        let line_height = (font::GLYPH_HEIGHT + 2) * scale;
+        let width = lines.iter().map(|line| font::text_width(line, scale)).max().unwrap_or(0) + padding * 2;
+        let height = lines.len() * line_height - 2 * scale + padding * 2;
+
+        let x = if x + offset + width <= self.frame_width as usize { x + offset } else { x.saturating_sub(offset + width) };
+        let y = if y + offset + height <= self.frame_height as usize { y + offset } else { y.saturating_sub(offset + height) };
+
+        self.darken_rect(frame, x, y, width, height);
+
+        for (i, line) in lines.iter().enumerate() {
+            font::draw_text(frame, self.frame_width as usize, x + padding, y + padding + i * line_height, line, [0xFF, 0xFF, 0xFF, 0xFF], scale);
+        }
+    }
+
+    /// Draws the right-click menu, if open, over everything else, with the item under the cursor
+    /// highlighted.
+    fn draw_menu(&self, frame: &mut [u8]) {
+        let Some((left, top, width, item_height)) = self.menu_rect() else {
+            return;
+        };
+
+        let scale = self.text_scale;
+        let hovered = self.menu_item();
+
+        self.darken_rect(frame, left, top, width, item_height * MenuItem::ALL.len());
+
+        for (i, item) in MenuItem::ALL.iter().enumerate() {
+            let y = top + i * item_height;
+            let mut colour = [0xFF, 0xFF, 0xFF, 0xFF];
+
+            // the darkened backdrop inverts to a light bar, for dark text
+            if hovered == Some(*item) {
+                for fy in y..(y + item_height).min(self.frame_height as usize) {
+                    for fx in left..(left + width).min(self.frame_width as usize) {
+                        self.invert_pixel(frame, fx, fy);
+                    }
+                }
+
+                colour = [0x00, 0x00, 0x00, 0xFF];
+            }
+
+            font::draw_text(frame, self.frame_width as usize, left + 4 * scale, y + 2 * scale, item.label(), colour, scale);
+        }
+    }
+
+    /// Draws the image's position in the list, e.g. "37/812", in the bottom-left corner.
+    fn draw_position(&self, frame: &mut [u8]) {
+        let text = format!("{}/{}", self.position.0 + 1, self.position.1);
+        let scale = self.text_scale;
+        let padding = 3 * scale;
+        let width = font::text_width(&text, scale) + padding * 2;
+        let height = font::GLYPH_HEIGHT * scale + padding * 2;
+        let y = (self.frame_height as usize).saturating_sub(height);
+
+        self.darken_rect(frame, 0, y, width, height);
+        font::draw_text(frame, self.frame_width as usize, padding, y + padding, &text, [0xFF, 0xFF, 0xFF, 0xFF], scale);
+    }
+
+    /// Draws the metadata lines in the top-left corner.
+    fn draw_metadata(&self, frame: &mut [u8]) {
+        let lines = self.metadata.iter().map(|line| (line.as_str(), [0xFF, 0xFF, 0xFF])).collect::<Vec<_>>();
+
+        self.draw_text_box(frame, &lines, false);
+    }
+
+    /// Draws the filter colour key and usage statistics in the top-right corner.
+    fn draw_filter_legend(&self, frame: &mut [u8]) {
+        let lines = match self.filter_map {
+            Some(_) => self.filter_statistics
+                .iter()
+                .enumerate()
+                .map(|(i, line)| (line.as_str(), FILTER_COLOURS.get(i).copied().unwrap_or([0xFF, 0x00, 0xFF])))
+                .collect::<Vec<_>>(),
+            None => vec![("Decoding...", [0xFF, 0xFF, 0xFF])],
+        };
+
+        self.draw_text_box(frame, &lines, true);
+    }
+
+    /// Draws coloured lines of text over a darkened backdrop in the top-left or top-right corner.
+    fn draw_text_box(&self, frame: &mut [u8], lines: &[(&str, [u8; 3])], right: bool) {
+        let scale = self.text_scale;
+        let padding = 4 * scale;
+        let line_height = (font::GLYPH_HEIGHT + 2) * scale;
+
+        let box_width = lines.iter().map(|(line, _)| font::text_width(line, scale)).max().unwrap_or(0) + padding * 2;
+        let box_height = lines.len() * line_height + padding * 2;
+        let box_x = if right { (self.frame_width as usize).saturating_sub(box_width) } else { 0 };
+
+        self.darken_rect(frame, box_x, 0, box_width, box_height);
+
+        for (i, (line, colour)) in lines.iter().enumerate() {
+            let colour = [colour[0], colour[1], colour[2], 0xFF];
+            font::draw_text(frame, self.frame_width as usize, box_x + padding, padding + i * line_height, line, colour, scale);
+        }
+    }
+}
+
+/// The distance in image pixels between labelled ruler ticks, 1, 2 or 5 times a power of ten,
+/// and between the unlabelled ticks in between, keeping labels `spacing` frame pixels apart.
+fn ruler_steps(zoom: f64, spacing: usize) -> (u64, u64) {
+    let mut power = 1;
+
+    loop {
+        for (multiple, divisions) in [(1, 5), (2, 2), (5, 5)] {
+            let step = multiple * power;
+
+            if step as f64 * zoom >= spacing as f64 {
+                return (step, (step / divisions).max(1));
+            }
+        }
+
+        power *= 10;
+    }
+}
+
+/// Multiplies the colour channels by alpha.
+fn premultiply(pixel: Pixel) -> Pixel {
+    let multiply = |c: u8| ((c as u16 * pixel.a as u16 + 127) / 0xFF) as u8;
+
+    Pixel {
+        r: multiply(pixel.r),
+        g: multiply(pixel.g),
+        b: multiply(pixel.b),
+        a: pixel.a,
+    }
+}
+
+/// Weights of the four pixels around a position `t` (0..1) past the second of them.
+fn catmull_rom(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+
+    [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ]
+}
+
+/// Applies exposure (in stops, to linear light) and then a gamma adjustment to an sRGB-encoded
+/// value in 0..1. Mirrored by the GPU renderer's shader.
+fn tone(value: f64, exposure: f64, gamma: f64) -> f64 {
+    let linear = (srgb_to_linear(value) * 2f64.powf(exposure)).min(1.0);
+
+    linear_to_srgb(linear).powf(1.0 / gamma)
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 }
+}
@@ -0,0 +1,150 @@
+use smithay_client_toolkit::compositor::{CompositorHandler, CompositorState};
+use smithay_client_toolkit::output::{OutputHandler, OutputState};
+use smithay_client_toolkit::reexports::client::globals::registry_queue_init;
+use smithay_client_toolkit::reexports::client::protocol::{wl_output, wl_shm, wl_surface};
+use smithay_client_toolkit::reexports::client::{Connection, QueueHandle};
+use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
+use smithay_client_toolkit::shell::wlr_layer::{
+    Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure,
+};
+use smithay_client_toolkit::shell::WaylandSurface;
+use smithay_client_toolkit::shm::slot::{Buffer, SlotPool};
+use smithay_client_toolkit::shm::{Shm, ShmHandler};
+use smithay_client_toolkit::{
+    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm, registry_handlers,
+};
+use crate::viewer::Viewer;
+
+/// Shows the viewer's image on a background layer-shell surface, filling the output the
+/// compositor picks, until the compositor closes the surface. Needs a compositor with
+/// `wlr-layer-shell`, such as Sway, Hyprland or KDE.
+pub fn run(viewer: Viewer) -> Result<(), String> {
+    let connection = Connection::connect_to_env().map_err(|e| format!("Can't connect to Wayland: {}", e))?;
+    let (globals, mut event_queue) = registry_queue_init(&connection).map_err(|e| e.to_string())?;
+    let qh = event_queue.handle();
+
+    let compositor = CompositorState::bind(&globals, &qh).map_err(|_| "wl_compositor isn't available".to_string())?;
+    let layer_shell = LayerShell::bind(&globals, &qh).map_err(|_| "The compositor doesn't support wlr-layer-shell".to_string())?;
+    let shm = Shm::bind(&globals, &qh).map_err(|_| "wl_shm isn't available".to_string())?;
+
+    let layer = layer_shell.create_layer_surface(&qh, compositor.create_surface(&qh), Layer::Background, Some("wallpaper"), None);
+    layer.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
+    layer.set_exclusive_zone(-1);
+    layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+    layer.set_size(0, 0);
+    layer.commit();
+
+    let mut wallpaper = Wallpaper {
+        registry_state: RegistryState::new(&globals),
+        output_state: OutputState::new(&globals, &qh),
+        pool: SlotPool::new(4, &shm).map_err(|e| e.to_string())?,
+        shm,
+        layer,
+        buffer: None,
+        viewer,
+        closed: false,
+    };
+
+    while !wallpaper.closed {
+        event_queue.blocking_dispatch(&mut wallpaper).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+struct Wallpaper {
+    registry_state: RegistryState,
+    output_state: OutputState,
+    shm: Shm,
+    pool: SlotPool,
+    layer: LayerSurface,
+    /// The buffer on screen, kept until the next one replaces it.
+    buffer: Option<Buffer>,
+    viewer: Viewer,
+    closed: bool,
+}
+
+impl Wallpaper {
+    fn draw(&mut self, width: u32, height: u32) {
+        self.viewer.resize(width, height);
+        self.viewer.zoom_to_fill();
+
+        let mut frame = vec![0; width as usize * height as usize * 4];
+        self.viewer.draw_image_only(&mut frame);
+
+        let Ok((buffer, canvas)) = self.pool.create_buffer(width as i32, height as i32, width as i32 * 4, wl_shm::Format::Xrgb8888) else {
+            return;
+        };
+
+        // XRGB8888 is stored little-endian, so BGRX
+        for (dst, src) in canvas.chunks_exact_mut(4).zip(frame.chunks_exact(4)) {
+            dst.copy_from_slice(&[src[2], src[1], src[0], 0xFF]);
+        }
+
+        let surface = self.layer.wl_surface();
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+
+        if buffer.attach_to(surface).is_ok() {
+            self.layer.commit();
+            self.buffer = Some(buffer);
+        }
+    }
+}
+
+impl LayerShellHandler for Wallpaper {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
+        self.closed = true;
+    }
+
+    fn configure(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface, configure: LayerSurfaceConfigure, _: u32) {
+        let (width, height) = configure.new_size;
+
+        if width > 0 && height > 0 {
+            self.draw(width, height);
+        }
+    }
+}
+
+impl CompositorHandler for Wallpaper {
+    fn scale_factor_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: i32) {}
+
+    fn transform_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: wl_output::Transform) {}
+
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+
+    fn surface_enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: &wl_output::WlOutput) {}
+
+    fn surface_leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: &wl_output::WlOutput) {}
+}
+
+impl OutputHandler for Wallpaper {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl ShmHandler for Wallpaper {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for Wallpaper {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+
+    registry_handlers![OutputState];
+}
+
+delegate_compositor!(Wallpaper);
+delegate_output!(Wallpaper);
+delegate_shm!(Wallpaper);
+delegate_layer!(Wallpaper);
+delegate_registry!(Wallpaper);